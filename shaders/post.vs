@@ -0,0 +1,12 @@
+#version 330 core
+
+layout (location = 0) in vec2 position;
+layout (location = 1) in vec2 coordinates;
+
+out vec2 uv;
+
+// passes read textures they drew themselves, whose first row is at the bottom
+void main(){
+    uv = vec2(coordinates.x, 1.0 - coordinates.y);
+    gl_Position = vec4(position, 0.0, 1.0);
+}
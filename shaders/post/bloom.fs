@@ -0,0 +1,23 @@
+#version 330 core
+
+uniform sampler2D source;
+uniform vec2 sourceSize;
+
+in vec2 uv;
+out vec4 fragment;
+
+const int RADIUS = 3;
+const float STRENGTH = 0.6;
+
+// lit pixels glow into their neighbours, a box blur of the source added on top of it
+void main(){
+    vec2 texel = 2.0 / sourceSize;
+    vec3 glow = vec3(0.0);
+    for(int y = -RADIUS; y <= RADIUS; y++){
+        for(int x = -RADIUS; x <= RADIUS; x++){
+            glow += texture(source, uv + vec2(x, y) * texel).rgb;
+        }
+    }
+    glow /= float((2 * RADIUS + 1) * (2 * RADIUS + 1));
+    fragment = vec4(texture(source, uv).rgb + glow * STRENGTH, 1.0);
+}
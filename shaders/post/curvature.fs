@@ -0,0 +1,20 @@
+#version 330 core
+
+uniform sampler2D source;
+
+in vec2 uv;
+out vec4 fragment;
+
+const float BEND = 0.08;
+
+// bends the picture like the glass of a tube, darkening towards the corners
+void main(){
+    vec2 centred = uv * 2.0 - 1.0;
+    centred *= 1.0 + BEND * (centred.yx * centred.yx);
+    vec2 bent = centred * 0.5 + 0.5;
+    // what bends past the edges is black
+    vec2 inside = step(vec2(0.0), bent) * step(bent, vec2(1.0));
+    bent = clamp(bent, 0.0, 1.0);
+    float vignette = clamp(pow(16.0 * bent.x * bent.y * (1.0 - bent.x) * (1.0 - bent.y), 0.2), 0.0, 1.0);
+    fragment = vec4(texture(source, bent).rgb * vignette * inside.x * inside.y, 1.0);
+}
@@ -0,0 +1,16 @@
+#version 330 core
+
+uniform sampler2D source;
+uniform vec2 screenSize;
+uniform vec2 outputSize;
+
+in vec2 uv;
+out vec4 fragment;
+
+// a line of one output pixel between the chip-8 pixels, like the gaps of an lcd
+void main(){
+    vec2 cell = fract(uv * screenSize);
+    vec2 line = step(cell, screenSize / outputSize);
+    float grid = max(line.x, line.y);
+    fragment = vec4(texture(source, uv).rgb * (1.0 - 0.4 * grid), 1.0);
+}
@@ -0,0 +1,14 @@
+#version 330 core
+
+uniform sampler2D source;
+uniform vec2 screenSize;
+
+in vec2 uv;
+out vec4 fragment;
+
+// darkens the edges of every row of chip-8 pixels, brightest through their middle
+void main(){
+    float row = fract(uv.y * screenSize.y);
+    float shade = 0.7 + 0.3 * cos((row - 0.5) * 6.2831853);
+    fragment = vec4(texture(source, uv).rgb * shade, 1.0);
+}
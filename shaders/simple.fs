@@ -1,5 +1,13 @@
 #version 330 core
 
+uniform sampler2D screen;
+// a colour for each combination of the two xo-chip planes, plain chip-8 only uses the first two
+uniform vec3 palette[4];
+
+in vec2 uv;
+out vec4 fragment;
+
 void main(){
-    gl_FragColor = vec4(0.2, 0.4, 1.0, 1.0);
-}
\ No newline at end of file
+    int index = int(texture(screen, uv).r * 255.0 + 0.5);
+    fragment = vec4(palette[index & 3], 1.0);
+}
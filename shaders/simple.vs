@@ -1,7 +1,14 @@
 #version 330 core
 
 layout (location = 0) in vec2 position;
+layout (location = 1) in vec2 coordinates;
+
+// the part of the 128x64 texture the screen covers, all of it in hires and a quarter in lores
+uniform vec2 area;
+
+out vec2 uv;
 
 void main(){
-    gl_Position = vec4(position, 0.0,1.0);
-}
\ No newline at end of file
+    uv = coordinates * area;
+    gl_Position = vec4(position, 0.0, 1.0);
+}
@@ -1,29 +1,33 @@
 mod parser;
 use std::collections::HashMap;
-use std::iter::Map;
-use crate::chip::utils::hex;
-use crate::chip::Opcode;
 use crate::chip::assembler::parser::{Parser, Expression};
+use crate::chip::utils::from_hex;
 
 struct Sub{ name: String, subtype:String }
 
-pub struct Assemblier{ map: HashMap<String, (String, Vec<Expression>)> }
+// sections are laid out after start in the order they appear in the source
+pub struct Assemblier{ map: HashMap<String, (String, Vec<Expression>)>, order: Vec<String>, labels: Vec<(String, u16)> }
 impl Assemblier{
     pub fn new()->Self{
-        Assemblier{ map: HashMap::new() }
+        Assemblier{ map: HashMap::new(), order: Vec::new(), labels: Vec::new() }
     }
 
-    pub fn init(&mut self, data: &str){
+    // the address of every subroutine and sprite, known once run has laid them out
+    pub fn labels(&self)->&[(String, u16)]{ &self.labels }
+
+    // reads the sections of the source, failing on the first line that does not assemble
+    pub fn init(&mut self, data: &str)->Result<(), String>{
         let mut parser = Parser::new(data);
 
         let mut sub: Option<Sub> = None;
         let mut codes: Vec<Expression> = Vec::new();
 
         while parser.next_token(){
-            let init = parser.get_next();
-            if (matches!(init, Expression::Jump{ nemode:_ , address:_ }) || matches!(init, Expression::Opcode(_))) && sub.is_some() && sub.as_ref().unwrap().subtype.eq_ignore_ascii_case("commands"){
-                codes.push(init);
-            }else if matches!(init, Expression::Sprite(_)) && sub.is_some() && sub.as_ref().unwrap().subtype.eq_ignore_ascii_case("sprite"){
+            let init = parser.get_next()?;
+            // commands may hold data too, for the words that are not instructions
+            let commands = matches!(init, Expression::Jump{ .. } | Expression::Opcode(_) | Expression::Long(_) | Expression::Sprite(_)) && sub.is_some() && sub.as_ref().unwrap().subtype.eq_ignore_ascii_case("commands");
+            let sprite = matches!(init, Expression::Sprite(_)) && sub.is_some() && sub.as_ref().unwrap().subtype.eq_ignore_ascii_case("sprite");
+            if commands || sprite{
                 codes.push(init);
             }else if let Expression::Subroutine{name, subtype} = init{
                 self.insert(&mut sub, &mut codes);
@@ -31,74 +35,136 @@ impl Assemblier{
             }
         }
         self.insert(&mut sub, &mut codes);
+        return Ok(());
     }
 
     fn insert(&mut self, sub: &mut Option<Sub>, codes: &mut Vec<Expression>){
         if sub.is_some() && !codes.is_empty(){
-            self.map.insert(sub.as_ref().unwrap().name.clone(), (sub.as_ref().unwrap().subtype.clone(), codes.to_vec()));
+            let name = sub.as_ref().unwrap().name.clone();
+            if self.map.insert(name.clone(), (sub.as_ref().unwrap().subtype.clone(), codes.to_vec())).is_none(){
+                self.order.push(name);
+            }
             codes.clear();
         }
     }
 
-    pub fn run(&mut self)->Vec<u8>{
+    // lays the sections out from 0x200 and resolves the labels, failing on a label no section defines
+    pub fn run(&mut self)->Result<Vec<u8>, String>{
         let mut codes:Vec<u8> = Vec::new();
         let mut addresses: Vec<(String, u16)> = Vec::new();
+        // without a start section there is no program to lay out
+        if !self.map.contains_key("start"){
+            return Err(String::from("there is no start: section"));
+        }
 
         addresses.push(("start".to_owned(), 0x200));
-        let mut current: u16 = 0x200 + (self.map["start"].1.len() as u16 * 2);
-        for address in self.map.keys(){
+        let mut current: u16 = 0x200 + self.size("start");
+        for address in self.order.iter(){
             if !address.eq_ignore_ascii_case("start"){
                 addresses.push((address.clone(), current));
-                if self.map[address].0.eq_ignore_ascii_case("sprite"){
-                    if let Expression::Sprite(sprite) = &self.map[address].1[0]{
-                        current += sprite.len() as u16;
-                    }
-                }else if self.map[address].0.eq_ignore_ascii_case("commands"){
-                    current += self.map[address].1.len() as u16 * 2;
-                }
+                current += self.size(address);
             }
         }
 
-        let init_addr = addresses.clone(); //println!("{:?}", addresses);
-        self.process("start", &mut codes, addresses.as_ref());
-        for addr in init_addr{
-            if !addr.0.eq_ignore_ascii_case("start"){
-                self.process(addr.0.as_str(), &mut codes, addresses.as_ref());
-            }
+        self.labels = addresses.clone();
+        for (name, _) in addresses.iter(){
+            self.process(name, &mut codes, addresses.as_ref())?;
         }
-        
-        /*let mut index = 0;
-        while index < codes.len(){
-            println!("{}: {}", hex(0x200 + index as u16), hex(codes[index] as u16));
-            index += 1;
-        }*/
-        return codes;
+        return Ok(codes);
     }
 
-    fn process(&mut self, addr: &str, codes: &mut Vec<u8>, addresses: &[(String, u16)] ){
+    fn size(&self, addr: &str)->u16{
+        return self.map[addr].1.iter().map(| exp | exp.size()).sum();
+    }
+
+    // every expression emits the bytes size gave it, or the addresses of the sections after it would be off
+    fn process(&self, addr: &str, codes: &mut Vec<u8>, addresses: &[(String, u16)])->Result<(), String>{
+        let find = | label: &str | addresses.iter().find(| ad | ad.0.eq_ignore_ascii_case(label)).map(| ad | ad.1);
         let (_subtype, exps) = &self.map[addr];
-            for exp in exps{
-                if let Expression::Opcode(code) = exp{
-                    //let opcode = Opcode::new(code.clone());
-                    //println!("{}: {}", hex(code.clone()), opcode.dessemble());
+        for exp in exps{
+            match exp{
+                Expression::Opcode(code) =>{
                     codes.push(((code & 0xff00) >> 8) as u8);
                     codes.push((code & 0x00ff) as u8);
-                }else if let Expression::Jump{nemode, address} = exp{
-                    for ad in addresses{
-                        if ad.0.eq_ignore_ascii_case(address){
-                            let init = nemode | ad.1;
-                            //let opcode = Opcode::new(init);
-                            //println!("{}: {}", hex(init), opcode.dessemble());
-                            codes.push(((init & 0xff00) >> 8) as u8);
-                            codes.push((init & 0x00ff) as u8);
-                        }
+                },
+                Expression::Jump{ nemode, address } =>{
+                    let target = find(address).ok_or_else(|| format!("unknown label {} in {}", address, addr))?;
+                    if target > 0x0fff{
+                        return Err(format!("{} at {:#06x} is out of reach of a 12 bit address, in {}", address, target, addr));
                     }
-                }else if let Expression::Sprite(data) = exp{
-                    //println!("Sprite: name {:?}", data);
+                    let init = nemode | target;
+                    codes.push(((init & 0xff00) >> 8) as u8);
+                    codes.push((init & 0x00ff) as u8);
+                },
+                Expression::Long(address) =>{
+                    // a label, or failing that a number
+                    let init = match find(address){
+                        Some(init) => init,
+                        None if address.starts_with(| ch: char | ch.is_ascii_digit()) => from_hex(address)?,
+                        None => return Err(format!("unknown label {} in {}", address, addr))
+                    };
+                    codes.extend_from_slice(&[0xf0, 0x00, (init >> 8) as u8, (init & 0x00ff) as u8]);
+                },
+                Expression::Sprite(data) =>{
                     for datum in data{
                         codes.push(datum.to_owned() as u8);
                     }
-                }
+                },
+                _ => {}
             }
+        }
+        return Ok(());
+    }
+}
+
+impl Default for Assemblier{
+    fn default()->Self{ Assemblier::new() }
+}
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::chip::Disassembler;
+
+    fn assemble(source: &str)->Result<Vec<u8>, String>{
+        let mut assembler = Assemblier::new();
+        assembler.init(source)?;
+        return assembler.run();
+    }
+
+    #[test]
+    fn labels_resolve_to_the_sections_after_start(){
+        let rom = assemble("start:\n    CALL draw\n    JP start\n\ndraw.commands:\n    LD I, dot\n    RET\n\ndot.sprite: 0x80 ;\n").unwrap();
+        assert_eq!(rom, [0x22, 0x04, 0x12, 0x00, 0xa2, 0x08, 0x00, 0xee, 0x80]);
+    }
+
+    #[test]
+    fn unknown_labels_are_errors(){
+        assert_eq!(assemble("start:\n    CALL missing\n    CLR\n"), Err(String::from("unknown label missing in start")));
+        assert_eq!(assemble("start:\n    LD I, LONG missing\n"), Err(String::from("unknown label missing in start")));
+    }
+
+    #[test]
+    fn a_source_needs_a_start_section(){
+        assert!(assemble("draw.commands:\n    CLR\n").is_err());
+    }
+
+    #[test]
+    fn bad_sources_are_errors_not_panics(){
+        assert_eq!(assemble(""), Err(String::from("there is no start: section")));
+        assert_eq!(assemble("start:\n    LD V0, 999999\n"), Err(String::from("line 2: 999999 does not fit in 16 bits")));
+        assert_eq!(assemble("start:\n    LD V0, 0x100\n"), Err(String::from("line 2: 0x100 does not fit in 8 bits")));
+        assert_eq!(assemble("start:\n    LD V0, 0xZZ\n"), Err(String::from("line 2: 0x is not a number")));
+        assert_eq!(assemble("start:\n    LD VZ, 1\n"), Err(String::from("line 2: VZ is not a register, they go from V0 to VF")));
+        assert_eq!(assemble("start:\n    CLR\n    FOO V0\n"), Err(String::from("line 3: unknown instruction FOO")));
+        assert_eq!(assemble("start:\n    DRW V0, V1\n"), Err(String::from("line 2: DRW is missing its operands")));
+    }
+
+    #[test]
+    fn disassembled_roms_assemble_back_to_themselves(){
+        for rom in [&include_bytes!("../../../games/BRIX")[..], include_bytes!("../../../games/TETRIS"), include_bytes!("../../../games/INVADERS")]{
+            let mut disassembler = Disassembler::new();
+            disassembler.init(rom);
+            assert_eq!(assemble(&disassembler.run()).unwrap(), rom);
+        }
     }
-}
\ No newline at end of file
+}
@@ -1,28 +1,47 @@
 use crate::chip::utils::from_hex;
 use crate::chip::utils::{Lexer, Token};
 
-const NEMONICS:[&str; 20] = [ 
+const NEMONICS:[&str; 31] = [ 
     "CLR", "RET", "SYS", "CALL", "JP", "SE", "SNE", "LD", "ADD", "OR",
-    "AND", "XOR", "SUB", "SHR", "SUBN", "SHL", "RND", "DRW", "SKP", "SKNP"
+    "AND", "XOR", "SUB", "SHR", "SUBN", "SHL", "RND", "DRW", "SKP", "SKNP",
+    "SCD", "SCR", "SCL", "EXIT", "LOW", "HIGH", "SCU", "SAVE", "LOAD", "PLANE", "AUDIO"
 ];
 
 fn is_nemonic(name: &str)->bool{
     return NEMONICS.contains(&name);
 }
 
-fn v_value(code: &str)->u16{
-    let mut init = code.to_uppercase();
-    init.remove(0);
-    return match init.as_ref(){
-        "A" => 10, "B" => 11, "C" => 12,
-        "D" => 13, "E" => 14, "F" => 15,
-        _ => { init.parse().unwrap() }
-    };
+// a register name, V0 to VF
+fn v_value(code: &str)->Result<u16, String>{
+    let digit = code.to_uppercase().strip_prefix('V').filter(| digit | digit.len() == 1).and_then(| digit | u16::from_str_radix(digit, 16).ok());
+    return digit.ok_or_else(|| format!("{} is not a register, they go from V0 to VF", code));
 }
 
+// a literal operand, which has to fit in the bits the instruction has for it
+fn number(value: &str, max: u16)->Result<u16, String>{
+    let number = from_hex(value)?;
+    if number > max{
+        return Err(format!("{} does not fit in {} bits", value, 16 - max.leading_zeros()));
+    }
+    return Ok(number);
+}
+
+// Long is the XO-CHIP F000 load of I, followed by its 16 bit address as a label or number
 #[derive(Debug,Clone)]
 pub enum Expression{
-    Opcode(u16), Jump{ nemode: u16, address: String}, Subroutine{ subtype: String, name :String}, Sprite(Vec<u16>), None
+    Opcode(u16), Jump{ nemode: u16, address: String}, Long(String), Subroutine{ subtype: String, name :String}, Sprite(Vec<u16>), None
+}
+
+impl Expression{
+    // bytes taken in the assembled rom
+    pub fn size(&self)->u16{
+        return match self{
+            Expression::Opcode(_) | Expression::Jump{ .. } => 2,
+            Expression::Long(_) => 4,
+            Expression::Sprite(data) => data.len() as u16,
+            _ => 0
+        };
+    }
 }
 
 pub struct Parser{ lexer:Box<Lexer>, errors: Vec<String> , current: Token }
@@ -34,7 +53,7 @@ impl Parser{
     pub fn next_token(&mut self)->bool{
         while self.lexer.has_next(){
             match self.lexer.get_next_token(){
-                Err(error) =>{ self.errors.push(String::from(error)); }
+                Err(error) =>{ self.errors.push(error); }
                 Ok(token) =>{
                     self.current = token;
                     return true;
@@ -45,99 +64,145 @@ impl Parser{
         return false;
     }
 
-    fn pop_token(&mut self)->Token{
-        let init = self.current.clone();
-        self.next_token();
-        return init;
+    // the token after the current one, without moving past it
+    fn peek_token(&self)->Token{
+        let mut lexer = (*self.lexer).clone();
+        while lexer.has_next(){
+            if let Ok(token) = lexer.get_next_token(){
+                return token;
+            }
+        }
+        return Token::None;
+    }
+
+    // the next instruction, label or sprite data, an error names the line it starts on
+    pub fn get_next(&mut self)->Result<Expression, String>{
+        let line = self.lexer.line();
+        let init = self.get_expression();
+        let init = match self.errors.first(){
+            Some(error) => Err(error.clone()),
+            None => init
+        };
+        return init.map_err(| error | format!("line {}: {}", line, error));
     }
 
-    pub fn get_next(&mut self)->Expression{
+    fn get_expression(&mut self)->Result<Expression, String>{
         while !matches!(self.current, Token::None){
             if let Token::Name(name) = &self.current{
-                match name.to_uppercase().as_str(){
-                    "CLR"   => return Expression::Opcode(0x00e0),
-                    "RET"   => return Expression::Opcode(0x00ee),
-                    "SYS"   => return self.init_sys_call(0x0000),
-                    "JP"    => return self.init_jp(),
-                    "CALL"  => return self.init_sys_call(0x2000),
-                    "SE"    => return self.init_se_sne([ 0x3000, 0x5000 ]),
-                    "SNE"   => return self.init_se_sne([0x4000, 0x9000 ]),
-                    "ADD"   => return self.init_add([ 0x7000, 0x8004, 0xf01e]),
-                    "SKP"   => return self.init_skp_sknp_shr_shl(0xe00e),
-                    "SKNP"  => return self.init_skp_sknp_shr_shl(0xe001),
-                    "SHR"   => return self.init_skp_sknp_shr_shl(0x8006),
-                    "SHL"   => return self.init_skp_sknp_shr_shl(0x800e),
-                    "OR"    => return self.init_or_xor_sub_subn(0x8001),
-                    "XOR"   => return self.init_or_xor_sub_subn(0x8003),
-                    "SUB"   => return self.init_or_xor_sub_subn(0x8005),
-                    "SUBN"  => return self.init_or_xor_sub_subn(0x8007),
-                    "RND"   => return self.init_rnd(),
-                    "DRW"   => return self.init_drw(),
-                    "LD"    => return self.init_load(),
+                let name = name.to_uppercase();
+                let init = match name.as_str(){
+                    "CLR"   => Ok(Expression::Opcode(0x00e0)),
+                    "RET"   => Ok(Expression::Opcode(0x00ee)),
+                    "SCR"   => Ok(Expression::Opcode(0x00fb)),
+                    "SCL"   => Ok(Expression::Opcode(0x00fc)),
+                    "EXIT"  => Ok(Expression::Opcode(0x00fd)),
+                    "LOW"   => Ok(Expression::Opcode(0x00fe)),
+                    "HIGH"  => Ok(Expression::Opcode(0x00ff)),
+                    "AUDIO" => Ok(Expression::Opcode(0xf002)),
+                    "SCD"   => self.init_scroll_plane(0x00c0, 0),
+                    "SCU"   => self.init_scroll_plane(0x00d0, 0),
+                    "PLANE" => self.init_scroll_plane(0xf001, 8),
+                    "SAVE"  => self.init_or_xor_sub_subn(0x5002),
+                    "LOAD"  => self.init_or_xor_sub_subn(0x5003),
+                    "SYS"   => self.init_sys_call(0x0000),
+                    "JP"    => self.init_jp(),
+                    "CALL"  => self.init_sys_call(0x2000),
+                    "SE"    => self.init_se_sne([ 0x3000, 0x5000 ]),
+                    "SNE"   => self.init_se_sne([0x4000, 0x9000 ]),
+                    "ADD"   => self.init_add([ 0x7000, 0x8004, 0xf01e]),
+                    "SKP"   => self.init_skp_sknp_shr_shl(0xe09e),
+                    "SKNP"  => self.init_skp_sknp_shr_shl(0xe0a1),
+                    "SHR"   => self.init_skp_sknp_shr_shl(0x8006),
+                    "SHL"   => self.init_skp_sknp_shr_shl(0x800e),
+                    "OR"    => self.init_or_xor_sub_subn(0x8001),
+                    "AND"   => self.init_or_xor_sub_subn(0x8002),
+                    "XOR"   => self.init_or_xor_sub_subn(0x8003),
+                    "SUB"   => self.init_or_xor_sub_subn(0x8005),
+                    "SUBN"  => self.init_or_xor_sub_subn(0x8007),
+                    "RND"   => self.init_rnd(),
+                    "DRW"   => self.init_drw(),
+                    "LD"    => self.init_load(),
                     _ => return self.init_subroutine(),
+                }?;
+                // a mnemonic the operands ran out on before it was complete
+                if let Expression::None = init{
+                    return Err(format!("{} is missing its operands", name));
                 }
+                return Ok(init);
             }else if let Token::Number(_) = &self.current{
                 return self.init_sprite();
+            }else if let Token::SemiColon = &self.current{
+                // the end of a list of sprite data
+                self.next_token();
+                continue;
             }
-            let token = self.pop_token();
-            self.errors.push(format!("Unexpected token: {:?}", token));
+            return Err(format!("unexpected token {:?}", self.current));
         }
-        return Expression::None;
+        return Ok(Expression::None);
     }
 
-    fn init_sprite(&mut self)->Expression{
+    fn init_sprite(&mut self)->Result<Expression, String>{
         let mut init: Vec<u16> = Vec::new();
         while !matches!(self.current, Token::None){
             if let Token::Number(value) = &self.current{
-                init.push(from_hex(value));
+                init.push(number(value, 0xff)?);
                 self.next_token();
-            }else if let Token::SemiColon = self.current{
+            }else{
                 break;
             }
         }
-        return Expression::Sprite(init);
+        return Ok(Expression::Sprite(init));
     }
 
-    fn init_subroutine(&mut self)->Expression{
-        let (mut step, mut name, mut subtype): (u16, Option<String>, Option<String>) = ( 0, None, None);
-        loop{
-            if let Token::Name(value) = &self.current{
-                if step == 0 && !is_nemonic(&value){
-                    name = Some(value.clone());
-                    step = 1;
-                }if step == 2 && ["sprite", "commands", "text"].contains(&value.as_str()) {
-                    subtype = Some(value.clone());
-                    step = 3;
-                }
-            }else if let Token::Dot = &self.current{
-                if step == 1{
-                    step = 2;
+    // a section, name.commands:, name.sprite:, name.text: or start:
+    fn init_subroutine(&mut self)->Result<Expression, String>{
+        let name = match &self.current{
+            Token::Name(name) => name.clone(),
+            _ => return Ok(Expression::None)
+        };
+        self.next_token();
+        if let Token::Colon = self.current{
+            if name.eq_ignore_ascii_case("start"){
+                return Ok(Expression::Subroutine{ name, subtype: "commands".to_owned() });
+            }
+            return Err(format!("{} needs a type, {}.commands:, {}.sprite: or {}.text:", name, name, name, name));
+        }else if let Token::Dot = self.current{
+            self.next_token();
+            if let Token::Name(subtype) = &self.current{
+                let subtype = subtype.clone();
+                if !["sprite", "commands", "text"].contains(&subtype.as_str()){
+                    return Err(format!("{} is not a section type, they are commands, sprite or text", subtype));
                 }
-            }else if let Token::Colon = &self.current{
-                if step == 3{
-                    return Expression::Subroutine{ name: name.unwrap(), subtype: subtype.unwrap() };
-                }else if step == 1 && name.as_ref().unwrap().eq_ignore_ascii_case("start"){
-                    return Expression::Subroutine{ name: name.unwrap(), subtype: "commands".to_owned() };
+                self.next_token();
+                if let Token::Colon = self.current{
+                    return Ok(Expression::Subroutine{ name, subtype });
                 }
-            }else if let Token::None = self.current{
-                break;
+                return Err(format!("{}.{} is missing its :", name, subtype));
             }
-            self.next_token();
+            return Err(format!("{}. is missing its section type", name));
+        }
+        return Err(format!("unknown instruction {}", name));
+    }
+
+    fn init_scroll_plane(&mut self, opcode: u16, shift: u16)->Result<Expression, String>{
+        self.next_token();
+        if let Token::Number(value) = &self.current {
+            return Ok(Expression::Opcode(opcode | (number(value, 0xf)? << shift)));
         }
-        return Expression::None;
+        return Ok(Expression::None);
     }
 
-    fn init_sys_call(&mut self, nemode: u16)->Expression{
+    fn init_sys_call(&mut self, nemode: u16)->Result<Expression, String>{
         self.next_token();
         if let Token::Name(value) = &self.current {
             if !is_nemonic(value.as_ref()){
-                return Expression::Jump{ nemode, address: value.clone() };
+                return Ok(Expression::Jump{ nemode, address: value.clone() });
             }
         }
-        return Expression::None;
+        return Ok(Expression::None);
     }
 
-    fn init_jp(&mut self)->Expression{
+    fn init_jp(&mut self)->Result<Expression, String>{
         let mut step : u16 = 0;
         while self.next_token(){
             if let Token::Name(value) = &self.current {
@@ -145,52 +210,53 @@ impl Parser{
                     step = 1;
                 }else if (step == 0 || step == 1) && !is_nemonic(value.as_ref()){
                     let nemode = if step == 0 { 0x1000 } else { 0xb000 };
-                    return Expression::Jump{ nemode , address: value.clone() };
+                    return Ok(Expression::Jump{ nemode , address: value.clone() });
                 }
             }
         }
-        return Expression::None;
+        return Ok(Expression::None);
     }
 
-    fn init_se_sne(&mut self, opcodes: [u16; 2])->Expression{
+    fn init_se_sne(&mut self, opcodes: [u16; 2])->Result<Expression, String>{
         let (mut vx, mut step) : (u16, usize) = (0, 0);
         while self.next_token(){
             if let Token::Name(value) = &self.current {
                 if step == 0  && value.to_uppercase().starts_with("V"){
-                    vx = v_value(value.to_uppercase().as_ref());
+                    vx = v_value(value)?;
                     step = 1;
                 }else if step == 2 && value.to_uppercase().starts_with("V"){
-                    let vy = v_value(value.to_uppercase().as_ref());
-                    return Expression::Opcode(opcodes[1] | (vx << 8) | (vy << 4));
+                    let vy = v_value(value)?;
+                    return Ok(Expression::Opcode(opcodes[1] | (vx << 8) | (vy << 4)));
                 }
             }else if let Token::Coma = self.current{
                 if step == 1{ step = 2; }
             }else if let Token::Number(value) = &self.current{
                 if step == 2{
-                    let init:u16 = from_hex(value);
-                    return Expression::Opcode(opcodes[0] | (vx << 8) | init);
+                    let init:u16 = number(value, 0xff)?;
+                    return Ok(Expression::Opcode(opcodes[0] | (vx << 8) | init));
                 }
             }
         }
-        return Expression::None;
+        return Ok(Expression::None);
     }
 
-    fn init_add(&mut self, opcodes: [u16; 3])->Expression{
+    fn init_add(&mut self, opcodes: [u16; 3])->Result<Expression, String>{
         let (mut vx, mut step, mut is_i) : (u16, usize, bool) = (0, 0, false);
         while self.next_token(){
             if let Token::Name(value) = &self.current {
                 if value.to_uppercase().starts_with("V"){
                     if step == 0{
-                        vx = v_value(value.to_uppercase().as_ref());
+                        vx = v_value(value)?;
                         step = 1;
                     }else if step == 2{
                         if is_i{
-                            return Expression::Opcode(opcodes[2] | (vx << 8));
+                            let vx = v_value(value)?;
+                            return Ok(Expression::Opcode(opcodes[2] | (vx << 8)));
                         }
-                        let vy = v_value(value.to_uppercase().as_ref());
-                        return Expression::Opcode(opcodes[1] | (vx << 8) | (vy << 4));
+                        let vy = v_value(value)?;
+                        return Ok(Expression::Opcode(opcodes[1] | (vx << 8) | (vy << 4)));
                     }
-                }if step == 0  && value.eq_ignore_ascii_case("I"){
+                }else if step == 0  && value.eq_ignore_ascii_case("I"){
                     step = 1;
                     is_i = true;
                 }
@@ -198,53 +264,65 @@ impl Parser{
                 if step == 1{ step = 2; }
             }else if let Token::Number(value) = &self.current{
                 if step == 2 && !is_i{
-                    let init:u16 = from_hex(value);
-                    return Expression::Opcode(opcodes[0] | (vx << 8) | init);
+                    let init:u16 = number(value, 0xff)?;
+                    return Ok(Expression::Opcode(opcodes[0] | (vx << 8) | init));
                 }
             }
         }
-        return Expression::None;
+        return Ok(Expression::None);
     }
 
-    fn init_skp_sknp_shr_shl(&mut self, opcode: u16)->Expression{
+    // the shifts take an optional vy, SHR vx is SHR vx, v0
+    fn init_skp_sknp_shr_shl(&mut self, opcode: u16)->Result<Expression, String>{
         while self.next_token(){
             if let Token::Name(value) = &self.current {
                 if value.to_uppercase().starts_with("V"){
-                    let vx = v_value(value.to_uppercase().as_ref());
-                    return Expression::Opcode(opcode | (vx << 8));
+                    let vx = v_value(value)?;
+                    if opcode & 0xf000 == 0x8000 && matches!(self.peek_token(), Token::Coma){
+                        self.next_token();
+                        self.next_token();
+                        if let Token::Name(value) = &self.current{
+                            if value.to_uppercase().starts_with("V"){
+                                let vy = v_value(value)?;
+                                return Ok(Expression::Opcode(opcode | (vx << 8) | (vy << 4)));
+                            }
+                        }
+                        return Ok(Expression::None);
+                    }
+                    return Ok(Expression::Opcode(opcode | (vx << 8)));
                 }
             }
         }
-        return Expression::None;
+        return Ok(Expression::None);
     }
 
-    fn init_or_xor_sub_subn(&mut self, opcode: u16)->Expression{
+    fn init_or_xor_sub_subn(&mut self, opcode: u16)->Result<Expression, String>{
         let (mut vx, mut step) : (u16, usize) = (0, 0);
         while self.next_token(){
             if let Token::Name(value) = &self.current {
                 if step == 0  && value.to_uppercase().starts_with("V"){
-                    vx = v_value(value.to_uppercase().as_ref());
+                    vx = v_value(value)?;
                     step = 1;
                 }else if step == 2 && value.to_uppercase().starts_with("V"){
-                    let vy = v_value(value.to_uppercase().as_ref());
-                    return Expression::Opcode(opcode | (vx << 8) | (vy << 4));
+                    let vy = v_value(value)?;
+                    return Ok(Expression::Opcode(opcode | (vx << 8) | (vy << 4)));
                 }
             }else if let Token::Coma = self.current{
                 if step == 1{ step = 2; }
             }
         }
-        return Expression::None;
+        return Ok(Expression::None);
     }
 
-    fn init_drw(&mut self)->Expression{
+    fn init_drw(&mut self)->Result<Expression, String>{
         let (mut vx, mut vy, mut step) : (u16, u16, usize) = (0, 0, 0);
         while self.next_token(){
             if let Token::Name(value) = &self.current {
                 if step == 0  && value.to_uppercase().starts_with("V"){
-                    vx = v_value(value.to_uppercase().as_ref());
+                    vx = v_value(value)?;
                     step = 1;
                 }else if step == 2 && value.to_uppercase().starts_with("V"){
-                    vy = v_value(value.to_uppercase().as_ref());
+                    vy = v_value(value)?;
                     step = 3;
                     
                 }
@@ -252,50 +330,52 @@ impl Parser{
                 if step == 1 || step == 3{ step += 1; }
             }else if let Token::Number(value) = &self.current{
                 if step == 4{
-                    let init:u16 = from_hex(value);
-                    return Expression::Opcode(0xd000 | (vx << 8) | (vy << 4) | init);
+                    let init:u16 = number(value, 0xf)?;
+                    return Ok(Expression::Opcode(0xd000 | (vx << 8) | (vy << 4) | init));
                 }
             }
         }
-        return Expression::None;
+        return Ok(Expression::None);
     }
 
-    fn init_rnd(&mut self)->Expression{
+    fn init_rnd(&mut self)->Result<Expression, String>{
         let (mut vx, mut step) : (u16, usize) = (0, 0);
         while self.next_token(){
             if let Token::Name(value) = &self.current {
                 if step == 0  && value.to_uppercase().starts_with("V"){
-                    vx = v_value(value.to_uppercase().as_ref());
+                    vx = v_value(value)?;
                     step = 1;
                 }
             }else if let Token::Coma = self.current{
                 if step == 1{ step = 2; }
             }else if let Token::Number(value) = &self.current{
                 if step == 2{
-                    let init:u16 = value.parse().unwrap();
-                    return Expression::Opcode(0xc000 | (vx << 8) | init);
+                    let init:u16 = number(value, 0xff)?;
+                    return Ok(Expression::Opcode(0xc000 | (vx << 8) | init));
                 }
             }
         }
-        return Expression::None;
+        return Ok(Expression::None);
     }
 
-    fn init_load(&mut self)->Expression{
+    fn init_load(&mut self)->Result<Expression, String>{
         let  mut step :  usize = 0;
         while self.next_token(){
             if let Token::Name(value) = &self.current {
                 let name = value.to_uppercase();
                 if name.starts_with("V"){
-                    let vx = v_value(value.to_uppercase().as_ref());
+                    let vx = v_value(value)?;
                     while self.next_token(){
                         if let Token::Name(init) = &self.current {
                             if init.to_uppercase().starts_with("V") && step == 1{
-                                let vy = v_value(init.to_uppercase().as_ref());
-                                return Expression::Opcode(0x8000 | (vx << 8) | (vy << 4));
+                                let vy = v_value(init)?;
+                                return Ok(Expression::Opcode(0x8000 | (vx << 8) | (vy << 4)));
                             }else if init.eq_ignore_ascii_case("K") && step == 1{
-                                return Expression::Opcode(0xf00a | (vx << 8));
+                                return Ok(Expression::Opcode(0xf00a | (vx << 8)));
                             }else if init.eq_ignore_ascii_case("DT") && step == 1{
-                                return Expression::Opcode(0xf007 | (vx << 8));
+                                return Ok(Expression::Opcode(0xf007 | (vx << 8)));
+                            }else if init.eq_ignore_ascii_case("R") && step == 1{
+                                return Ok(Expression::Opcode(0xf085 | (vx << 8)));
                             }else if init.eq_ignore_ascii_case("I") && step == 2{
                                 step = 3;
                             }
@@ -303,24 +383,24 @@ impl Parser{
                             if step == 0{ step = 1; }
                         }else if let Token::Number(value) = &self.current{
                             if step == 1{
-                                let init:u16 = from_hex(value);
-                                return Expression::Opcode(0x6000 | (vx << 8) | init);
+                                let init:u16 = number(value, 0xff)?;
+                                return Ok(Expression::Opcode(0x6000 | (vx << 8) | init));
                             }
                         }else if let Token::OpenSquareBracket = self.current{
                             if step == 1{ step = 2; }
                         }else if let Token::ClosingSquareBracket = self.current{
-                            if step == 3{ return Expression::Opcode(0xf065 | (vx << 8)); }
+                            if step == 3{ return Ok(Expression::Opcode(0xf065 | (vx << 8))); }
                         }
                     }
-                }else if ["DT", "ST", "F", "B"].contains(&(name.as_ref())){
+                }else if ["DT", "ST", "F", "HF", "B", "R", "PITCH"].contains(&(name.as_ref())){
                     while self.next_token(){
                         if let Token::Name(init) = &self.current {
                             if step == 1 && init.to_uppercase().starts_with("V"){
                                 let code: u16 = match name.to_uppercase().as_ref(){
-                                    "DT" => 0xf015, "ST" => 0xf018, "F" => 0xf029, _ => 0xf033
+                                    "DT" => 0xf015, "ST" => 0xf018, "F" => 0xf029, "HF" => 0xf030, "R" => 0xf075, "PITCH" => 0xf03a, _ => 0xf033
                                 };
-                                let vx = v_value(init.to_uppercase().as_ref());
-                                return Expression::Opcode(code | (vx << 8));
+                                let vx = v_value(init)?;
+                                return Ok(Expression::Opcode(code | (vx << 8)));
                             }
                         }else if let Token::Coma = self.current{
                             if step == 0{ step = 1; }
@@ -332,12 +412,19 @@ impl Parser{
                             if step == 0{ step = 1; }
                         }else if let Token::Number(value) = &self.current{
                             if step == 1{
-                                let init:u16 = from_hex(value);
-                                return Expression::Opcode(0xa000 | init);
+                                let init:u16 = number(value, 0xfff)?;
+                                return Ok(Expression::Opcode(0xa000 | init));
+                            }else if step == 2{
+                                from_hex(value)?;
+                                return Ok(Expression::Long(value.clone()));
                             }
                         }else if let Token::Name(name) = &self.current{
-                            if step == 1{
-                                return Expression::Jump{ nemode:0xa000, address: name.clone() };
+                            if step == 1 && name.eq_ignore_ascii_case("LONG"){
+                                step = 2;
+                            }else if step == 1{
+                                return Ok(Expression::Jump{ nemode:0xa000, address: name.clone() });
+                            }else if step == 2{
+                                return Ok(Expression::Long(name.clone()));
                             }
                         }
                     }   
@@ -348,8 +435,8 @@ impl Parser{
                         if step == 0  && value.eq_ignore_ascii_case("I"){
                             step = 1;
                         }else if step == 3 && value.to_uppercase().starts_with("V"){
-                            let vx = v_value(value.to_uppercase().as_ref());
-                            return Expression::Opcode(0xf055 | (vx << 8));
+                            let vx = v_value(value)?;
+                            return Ok(Expression::Opcode(0xf055 | (vx << 8)));
                         }
                     }else if let Token::ClosingSquareBracket = self.current{
                         if step == 1{ step = 2; }
@@ -359,6 +446,6 @@ impl Parser{
                 }
             }
         }
-        return Expression::None;
+        return Ok(Expression::None);
     }
 }
\ No newline at end of file
@@ -0,0 +1,411 @@
+use std::fs::File;
+use std::io::{ self, BufWriter, Seek, SeekFrom, Write };
+
+use crate::chip::error::ChipError;
+use crate::chip::state::{ StateWriter, StateReader };
+
+// receives the mono PCM samples produced by the emulator
+pub trait Sink{
+    fn write(&mut self, samples: &[f32]);
+}
+
+// keeps every sample written to it, useful to compare headless runs against reference buffers
+pub struct BufferSink{ samples: Vec<f32> }
+impl BufferSink{
+    pub fn new()->Self{ BufferSink{ samples: Vec::new() } }
+
+    pub fn samples(&self)->&[f32]{ &self.samples }
+    pub fn clear(&mut self){ self.samples.clear(); }
+}
+
+impl Default for BufferSink{
+    fn default()->Self{ BufferSink::new() }
+}
+
+impl Sink for BufferSink{
+    fn write(&mut self, samples: &[f32]){ self.samples.extend_from_slice(samples); }
+}
+
+// drops everything, for running without sound
+pub struct NullSink;
+
+impl Sink for NullSink{
+    fn write(&mut self, _samples: &[f32]){}
+}
+
+// records the samples as a 16 bit mono WAV file. The sizes in the header are only known at the end,
+// they are written by finish, which dropping the sink also does.
+pub struct WavSink{ file: BufWriter<File>, samples: u32, error: Option<io::Error> }
+
+impl WavSink{
+    pub fn create(path: &str, sample_rate: u32)->io::Result<Self>{
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(b"RIFF")?;
+        file.write_all(&36u32.to_le_bytes())?;
+        file.write_all(b"WAVEfmt ")?;
+        file.write_all(&16u32.to_le_bytes())?;          // fmt chunk size
+        file.write_all(&1u16.to_le_bytes())?;           // PCM
+        file.write_all(&1u16.to_le_bytes())?;           // mono
+        file.write_all(&sample_rate.to_le_bytes())?;
+        file.write_all(&(sample_rate * 2).to_le_bytes())?;  // bytes per second
+        file.write_all(&2u16.to_le_bytes())?;           // bytes per sample
+        file.write_all(&16u16.to_le_bytes())?;          // bits per sample
+        file.write_all(b"data")?;
+        file.write_all(&0u32.to_le_bytes())?;
+        return Ok(WavSink{ file, samples: 0, error: None });
+    }
+
+    // number of samples written so far
+    pub fn len(&self)->u32{ self.samples }
+    pub fn is_empty(&self)->bool{ self.samples == 0 }
+
+    // patches the header, also reporting the first error met while writing samples
+    pub fn finish(&mut self)->io::Result<()>{
+        if let Some(error) = self.error.take(){
+            return Err(error);
+        }
+        let data = self.samples * 2;
+        self.file.seek(SeekFrom::Start(4))?;
+        self.file.write_all(&(36 + data).to_le_bytes())?;
+        self.file.seek(SeekFrom::Start(40))?;
+        self.file.write_all(&data.to_le_bytes())?;
+        self.file.seek(SeekFrom::End(0))?;
+        return self.file.flush();
+    }
+}
+
+impl Sink for WavSink{
+    fn write(&mut self, samples: &[f32]){
+        if self.error.is_some(){
+            return;
+        }
+        let mut data = Vec::with_capacity(samples.len() * 2);
+        for sample in samples{
+            data.extend_from_slice(&((sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16).to_le_bytes());
+        }
+        match self.file.write_all(&data){
+            Ok(_) => self.samples += samples.len() as u32,
+            Err(error) => self.error = Some(error)
+        }
+    }
+}
+
+impl Drop for WavSink{
+    fn drop(&mut self){ let _ = self.finish(); }
+}
+
+// plays the samples on the default output device of the host. The samples are queued for the device
+// callback, which repeats them on every channel, at most a tenth of a second is kept to bound the latency.
+#[cfg(feature = "host-audio")]
+pub struct DeviceSink{ queue: std::sync::Arc<std::sync::Mutex<std::collections::VecDeque<f32>>>, sample_rate: u32, _stream: cpal::Stream }
+
+#[cfg(feature = "host-audio")]
+impl DeviceSink{
+    pub fn open()->Result<Self, String>{
+        use std::sync::{ Arc, Mutex };
+        use std::collections::VecDeque;
+        use cpal::traits::{ HostTrait, DeviceTrait, StreamTrait };
+
+        let device = cpal::default_host().default_output_device().ok_or_else(|| String::from("no audio output device"))?;
+        let config = device.default_output_config().map_err(| error | error.to_string())?;
+        let channels = config.channels() as usize;
+        let sample_rate = config.sample_rate().0;
+
+        let queue = Arc::new(Mutex::new(VecDeque::new()));
+        let shared = queue.clone();
+        let stream = device.build_output_stream(&config.config(), move | data: &mut [f32], _: &cpal::OutputCallbackInfo |{
+            let mut queue = shared.lock().unwrap();
+            for frame in data.chunks_mut(channels){
+                frame.fill(queue.pop_front().unwrap_or(0.0));
+            }
+        }, | error | println!("{}", error), None).map_err(| error | error.to_string())?;
+        stream.play().map_err(| error | error.to_string())?;
+        return Ok(DeviceSink{ queue, sample_rate, _stream: stream });
+    }
+
+    // the chip has to render at this rate
+    pub fn get_sample_rate(&self)->u32{ self.sample_rate }
+}
+
+#[cfg(feature = "host-audio")]
+impl Sink for DeviceSink{
+    fn write(&mut self, samples: &[f32]){
+        let mut queue = self.queue.lock().unwrap();
+        queue.extend(samples.iter());
+        let limit = self.sample_rate as usize / 10;
+        if queue.len() > limit{
+            let excess = queue.len() - limit;
+            queue.drain(..excess);
+        }
+    }
+}
+
+// shape of the beeper tone, a custom waveform is one period of samples between -1 and 1
+#[derive(Debug, Clone, PartialEq)]
+pub enum Waveform{ Square, Sine, Custom(Vec<f32>) }
+
+// length of the fade in and out around a beep, which keeps the speaker from clicking
+const ENVELOPE: f64 = 0.005;
+
+// The buzzer, sounding while the sound timer is non-zero. Plain CHIP-8 programs get the beeper waveform
+// at a fixed frequency. Once a program loads an XO-CHIP pattern with F002 the 128 bit 1-bit pattern is
+// played instead, at 4000*2^((pitch-64)/48) bits per second.
+pub struct Audio{
+    pattern: [u8; 16], pitch: u8, xo: bool, phase: f64, gain: f64,
+    waveform: Waveform, tone: f64, sample_rate: u32, volume: f32
+}
+
+impl Audio{
+    pub fn new()->Self{
+        Audio{
+            pattern: [0xf0; 16], pitch: 64, xo: false, phase: 0.0, gain: 0.0,
+            waveform: Waveform::Square, tone: 500.0, sample_rate: 44100, volume: 0.25
+        }
+    }
+
+    // the default pattern is a 500 Hz square wave, the same as the default beeper
+    pub fn reset(&mut self){
+        self.pattern = [0xf0; 16];
+        self.pitch = 64;
+        self.xo = false;
+        self.phase = 0.0;
+        self.gain = 0.0;
+    }
+
+    pub fn get_pattern(&self)->&[u8; 16]{ &self.pattern }
+    pub fn set_pattern(&mut self, pattern: [u8; 16]){ self.pattern = pattern; }
+
+    // F002 switches the buzzer over to the pattern
+    pub fn load_pattern(&mut self, pattern: [u8; 16]){
+        if !self.xo{
+            self.xo = true;
+            self.phase = 0.0;
+        }
+        self.pattern = pattern;
+    }
+
+    pub fn is_pattern(&self)->bool{ self.xo }
+
+    pub fn get_pitch(&self)->u8{ self.pitch }
+    pub fn set_pitch(&mut self, pitch: u8){ self.pitch = pitch; }
+
+    pub fn get_phase(&self)->f64{ self.phase }
+    pub fn set_phase(&mut self, phase: f64){ self.phase = phase; }
+
+    pub fn get_waveform(&self)->&Waveform{ &self.waveform }
+    pub fn set_waveform(&mut self, waveform: Waveform){ self.waveform = waveform; }
+
+    // frequency of the beeper in Hz
+    pub fn get_tone(&self)->f64{ self.tone }
+    pub fn set_tone(&mut self, tone: f64){ self.tone = tone; }
+
+    pub fn get_sample_rate(&self)->u32{ self.sample_rate }
+    pub fn set_sample_rate(&mut self, sample_rate: u32){ self.sample_rate = sample_rate; }
+
+    pub fn get_volume(&self)->f32{ self.volume }
+    pub fn set_volume(&mut self, volume: f32){ self.volume = volume; }
+
+    // only the machine state is saved, the waveform, tone, sample rate and volume belong to the host
+    pub fn save_state(&self, state: &mut StateWriter){
+        state.bytes(&self.pattern);
+        state.u8(self.pitch);
+        state.f64(self.phase);
+        state.bool(self.xo);
+        state.f64(self.gain);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader)->Result<(), ChipError>{
+        self.pattern.copy_from_slice(state.bytes(16)?);
+        self.pitch = state.u8()?;
+        self.phase = state.f64()?;
+        if state.version() >= 5{
+            self.xo = state.bool()?;
+            self.gain = state.f64()?;
+        }else{
+            self.xo = self.pattern != [0xf0; 16] || self.pitch != 64;
+        }
+        Ok(())
+    }
+
+    // pattern bits played per second
+    pub fn frequency(&self)->f64{
+        return 4000.0 * 2f64.powf((self.pitch as f64 - 64.0) / 48.0);
+    }
+
+    // the current point of the wave, between -1 and 1
+    fn wave(&self)->f32{
+        if self.xo{
+            let bit = self.phase as usize;
+            return if (self.pattern[bit / 8] >> (7 - bit % 8)) & 0x01 == 1 { 1.0 } else { -1.0 };
+        }
+        return match &self.waveform{
+            Waveform::Square => if self.phase < 0.5 { 1.0 } else { -1.0 },
+            Waveform::Sine => (self.phase * std::f64::consts::TAU).sin() as f32,
+            Waveform::Custom(samples) if !samples.is_empty() => samples[(self.phase * samples.len() as f64) as usize % samples.len()],
+            Waveform::Custom(_) => 0.0
+        };
+    }
+
+    // fills out with samples while the sound timer is running, fading in and out at the edges.
+    // The wave starts over once a beep faded out completely.
+    pub fn render(&mut self, playing: bool, out: &mut [f32]){
+        // the phase counts pattern bits for xo-chip and periods of the tone for the beeper
+        let (step, period) = if self.xo { (self.frequency(), 128.0) } else { (self.tone, 1.0) };
+        let step = step / self.sample_rate as f64;
+        let ramp = 1.0 / (ENVELOPE * self.sample_rate as f64).max(1.0);
+        let target = if playing { 1.0 } else { 0.0 };
+
+        for sample in out.iter_mut(){
+            self.gain = if self.gain < target { (self.gain + ramp).min(target) } else { (self.gain - ramp).max(target) };
+            if self.gain == 0.0{
+                self.phase = 0.0;
+                *sample = 0.0;
+                continue;
+            }
+            *sample = self.wave() * self.volume * self.gain as f32;
+            self.phase = (self.phase + step) % period;
+        }
+    }
+}
+
+impl Default for Audio{
+    fn default()->Self{ Audio::new() }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::chip::{ Chip, Quirks };
+    use crate::chip::scheduler::{ ManualClock, Scheduler };
+
+    // sets the sound timer to 255 and, with a pattern, loads the 16 bytes after the program with F002
+    fn chip(pattern: Option<u8>)->Chip{
+        let mut chip = Chip::with_quirks(Quirks::preset("xo-chip").unwrap());
+        let mut rom = vec![0x6a, 0xff, 0xfa, 0x18, 0xa2, 0x0a, 0x00, 0xe0, 0x12, 0x08];
+        if let Some(byte) = pattern{
+            rom[6..8].copy_from_slice(&[0xf0, 0x02]);
+            rom.extend_from_slice(&[byte; 16]);
+        }
+        chip.load_rom(&rom).unwrap();
+        chip.run(4).unwrap();
+        return chip;
+    }
+
+    fn sign_changes(samples: &[f32])->usize{
+        return samples.windows(2).filter(| pair | (pair[0] > 0.0) != (pair[1] > 0.0) && pair[0] != 0.0 && pair[1] != 0.0).count();
+    }
+
+    #[test]
+    fn beeper_plays_at_its_tone(){
+        let mut chip = chip(None);
+        let mut sink = BufferSink::new();
+        chip.play(44100, &mut sink);
+        assert!(!chip.audio().is_pattern());
+        // a 500 Hz square wave changes sign twice a period
+        assert!(sign_changes(sink.samples()).abs_diff(1000) <= 2);
+    }
+
+    #[test]
+    fn pattern_plays_4000_bits_a_second_at_pitch_64(){
+        let mut chip = chip(Some(0xaa));
+        let mut sink = BufferSink::new();
+        chip.play(44100, &mut sink);
+        assert!(chip.audio().is_pattern());
+        // 0xaa alternates every bit
+        assert!(sign_changes(sink.samples()).abs_diff(4000) <= 2);
+    }
+
+    #[test]
+    fn pattern_matches_the_reference_buffer(){
+        let mut chip = chip(Some(0xf0));
+        let mut sink = BufferSink::new();
+        chip.play(2000, &mut sink);
+
+        // every bit lasts 44100 / 4000 samples, four set then four clear, the gain rises over the first 5 ms
+        let volume = chip.audio().get_volume();
+        let ramp = 1.0 / (ENVELOPE * 44100.0);
+        let reference: Vec<f32> = (0..2000).map(| index |{
+            let bit = (index as f64 * 4000.0 / 44100.0) as usize % 128;
+            let sign = if bit % 8 < 4 { 1.0 } else { -1.0 };
+            let gain = ((index + 1) as f64 * ramp).min(1.0);
+            sign * volume * gain as f32
+        }).collect();
+        for (index, (sample, expected)) in sink.samples().iter().zip(reference.iter()).enumerate(){
+            // the sum of steps may land a sample either side of an edge
+            let edge = (index as f64 * 4000.0 / 44100.0).fract() < 0.001;
+            assert!(edge || (sample - expected).abs() < 1e-4, "sample {}: {} instead of {}", index, sample, expected);
+        }
+    }
+
+    #[test]
+    fn silence_once_the_timer_runs_out(){
+        let mut audio = Audio::new();
+        let mut out = [1.0; 441];
+        audio.render(false, &mut out);
+        assert!(out.iter().all(| sample | *sample == 0.0));
+    }
+
+    #[test]
+    fn ld_pitch_sets_the_rate_the_pattern_plays_at(){
+        // 6AFF FA18 6Bxx FB3A A20E F002 120C, then the pattern
+        for (pitch, frequency) in [(0x70u8, 8000.0), (0x10, 2000.0), (0x40, 4000.0)]{
+            let mut chip = Chip::with_quirks(Quirks::preset("xo-chip").unwrap());
+            let mut rom = vec![0x6a, 0xff, 0xfa, 0x18, 0x6b, pitch, 0xfb, 0x3a, 0xa2, 0x0e, 0xf0, 0x02, 0x12, 0x0c];
+            rom.extend_from_slice(&[0xaa; 16]);
+            chip.load_rom(&rom).unwrap();
+            chip.run(6).unwrap();
+            assert_eq!((chip.audio().get_pitch(), chip.audio().frequency()), (pitch, frequency));
+
+            let mut sink = BufferSink::new();
+            chip.play(44100, &mut sink);
+            assert!(sign_changes(sink.samples()).abs_diff(frequency as usize) <= 2, "pitch {}", pitch);
+        }
+    }
+
+    // a path in the temporary directory only this test process uses
+    fn temporary(name: &str)->String{
+        return std::env::temp_dir().join(format!("chip-8-{}-{}", std::process::id(), name)).to_string_lossy().into_owned();
+    }
+
+    fn u32_at(data: &[u8], at: usize)->u32{ u32::from_le_bytes(data[at..at + 4].try_into().unwrap()) }
+    fn u16_at(data: &[u8], at: usize)->u16{ u16::from_le_bytes(data[at..at + 2].try_into().unwrap()) }
+
+    #[test]
+    fn wav_header_describes_16_bit_mono_samples(){
+        let path = temporary("header.wav");
+        let mut sink = WavSink::create(&path, 22050).unwrap();
+        sink.write(&[0.0, 1.0, -2.0]);
+        assert_eq!(sink.len(), 3);
+        sink.finish().unwrap();
+
+        let data = std::fs::read(&path).unwrap();
+        assert_eq!(data.len(), 44 + 6);
+        assert_eq!((&data[0..4], u32_at(&data, 4), &data[8..16]), (&b"RIFF"[..], 36 + 6, &b"WAVEfmt "[..]));
+        // chunk size, pcm, mono, rate, bytes a second, bytes a sample, bits a sample
+        assert_eq!((u32_at(&data, 16), u16_at(&data, 20), u16_at(&data, 22)), (16, 1, 1));
+        assert_eq!((u32_at(&data, 24), u32_at(&data, 28), u16_at(&data, 32), u16_at(&data, 34)), (22050, 44100, 2, 16));
+        assert_eq!((&data[36..40], u32_at(&data, 40)), (&b"data"[..], 6));
+        // samples past full scale are clipped
+        assert_eq!([u16_at(&data, 44) as i16, u16_at(&data, 46) as i16, u16_at(&data, 48) as i16], [0, i16::MAX, -i16::MAX]);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn every_frame_records_its_share_of_samples(){
+        // 22050 / 60 is 367.5, so frames alternate between 367 and 368 samples
+        let path = temporary("frames.wav");
+        let mut chip = chip(None);
+        chip.audio_mut().set_sample_rate(22050);
+        let mut scheduler = Scheduler::new(Box::new(ManualClock::new()));
+        scheduler.set_sink(Some(Box::new(WavSink::create(&path, 22050).unwrap())));
+        for _ in 0..3{
+            scheduler.frame(&mut chip).unwrap();
+        }
+        // the header is patched when the sink is dropped
+        scheduler.set_sink(None);
+        let data = std::fs::read(&path).unwrap();
+        assert_eq!(u32_at(&data, 40), (367 + 368 + 367) * 2);
+        assert_eq!(data.len(), 44 + (367 + 368 + 367) * 2);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
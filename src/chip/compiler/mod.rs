@@ -5,6 +5,303 @@ mod parser;
 
 mod syntax;
 
+use crate::chip::compiler::parser::{ Parser, Expression };
+use crate::chip::Assemblier;
+
+// the types that fit in a register
+const TYPES: [&str; 2] = ["num", "bool"];
+
+// an argument of a function, its register and default value
+type Argument = (String, usize, Option<u8>);
+
+// The script compiler writes assembler source for the parsed statements, which the assembler makes a rom of.
+// Values are bytes: every variable and argument is given a register of its own from v0 up, the values being
+// worked out take registers from ve down and vf is left to the carry and borrow flags. Functions become
+// subroutines with their arguments loaded into their registers before the call, a function calling itself
+// would overwrite them. Strings, characters and arrays have no code generation yet and are reported as errors.
 pub struct Compiler{
+    errors: Vec<String>,
+    variables: Vec<(String, Option<usize>)>,    // the register of a variable is its index, with the function it is local to
+    functions: Vec<(String, Vec<Argument>)>,
+    function: Option<usize>,    // the function being compiled, none at the top of the script
+    temporary: usize            // the next register for a value being worked out
+}
+
+impl Compiler{
+    pub fn new()->Self{
+        Compiler{ errors: Vec::new(), variables: Vec::new(), functions: Vec::new(), function: None, temporary: 0xe }
+    }
+
+    // the rom of a script, or every error found in it
+    pub fn compile(&mut self, data: &str)->Result<Vec<u8>, Vec<String>>{
+        *self = Compiler::new();
+        let mut parser = Parser::new(data);
+        let mut statements = Vec::new();
+        while !parser.is_done(){
+            let statement = parser.get_next();
+            if !matches!(statement, Expression::None){
+                statements.push(statement);
+            }
+        }
+        if !parser.errors().is_empty(){
+            return Err(parser.errors().to_vec());
+        }
+
+        // the functions are declared first so that they can be called before they are defined
+        for statement in statements.iter(){
+            if let Expression::FunctionDefinition{ name, args, rtype, .. } = statement{
+                self.declare_function(name, args, rtype);
+            }
+        }
+
+        let mut source = vec![String::from("start:")];
+        for statement in statements.iter(){
+            if !matches!(statement, Expression::FunctionDefinition{ .. }){
+                self.statement(statement, &mut source);
+            }
+        }
+        source.push(String::from("    JP done"));
+        for statement in statements.iter(){
+            if let Expression::FunctionDefinition{ name, body, .. } = statement{
+                self.function = self.functions.iter().position(| (function, _) | function == name);
+                source.push(format!("fun{}.commands:", self.function.unwrap_or(0)));
+                for statement in body{
+                    self.statement(statement, &mut source);
+                }
+                source.push(String::from("    RET"));
+            }
+        }
+        // the script ends in a loop, like roms do
+        source.push(String::from("done.commands:"));
+        source.push(String::from("    JP done"));
+
+        if !self.errors.is_empty(){
+            return Err(self.errors.clone());
+        }
+        let mut assembler = Assemblier::new();
+        return assembler.init(&source.join("\n")).and_then(|_| assembler.run()).map_err(| error | vec![error]);
+    }
+
+    fn declare_function(&mut self, name: &str, args: &[Expression], rtype: &Option<String>){
+        if self.functions.iter().any(| (function, _) | function == name){
+            self.errors.push(format!("function {} is defined twice", name));
+            return;
+        }
+        if let Some(rtype) = rtype{
+            self.check_type(name, rtype);
+        }
+        let index = self.functions.len();
+        self.functions.push((name.to_owned(), Vec::new()));
+        for arg in args{
+            if let Expression::ArgumentDefinition{ name: arg, dt, value } = arg{
+                self.check_type(arg, dt);
+                let default = value.as_ref().and_then(| value | self.byte(value));
+                if value.is_some() && default.is_none(){
+                    self.errors.push(format!("the default value of {} has to be a number", arg));
+                }
+                let register = self.declare(arg, Some(index));
+                self.functions[index].1.push((arg.clone(), register, default));
+            }
+        }
+    }
+
+    fn check_type(&mut self, name: &str, dt: &str){
+        if !TYPES.contains(&dt){
+            self.errors.push(format!("{} is a {}, only num and bool have code generation", name, dt));
+        }
+    }
+
+    // a register for a new variable of the function being compiled
+    fn declare(&mut self, name: &str, function: Option<usize>)->usize{
+        if self.variables.iter().any(| variable | variable.0 == name && variable.1 == function){
+            self.errors.push(format!("{} is declared twice", name));
+        }
+        if self.variables.len() >= 0xf{
+            self.errors.push(format!("no register is left for {}, a script has room for 15 variables", name));
+        }
+        self.variables.push((name.to_owned(), function));
+        return self.variables.len() - 1;
+    }
+
+    // the variables of the function being compiled hide the ones of the script
+    fn find(&mut self, name: &str)->usize{
+        let local = self.variables.iter().rposition(| variable | variable.0 == name && variable.1 == self.function);
+        let global = self.variables.iter().rposition(| variable | variable.0 == name && variable.1.is_none());
+        return match local.or(global){
+            Some(register) => register,
+            None =>{
+                self.errors.push(format!("unknown variable {}", name));
+                0
+            }
+        };
+    }
+
+    fn temporary(&mut self)->usize{
+        if self.temporary < self.variables.len(){
+            self.errors.push(String::from("no register is left to work the value out in"));
+            return 0xe;
+        }
+        let register = self.temporary;
+        self.temporary -= 1;
+        return register;
+    }
+
+    // a value known when compiling
+    fn constant(&self, exp: &Expression)->Option<i32>{
+        return match exp{
+            Expression::Number(number) => Some(*number as i32),
+            Expression::Boolean(value) => Some((value == "true") as i32),
+            Expression::Binary{ left, op, right } =>{
+                let (left, right) = (self.constant(left)?, self.constant(right)?);
+                match op.as_str(){
+                    "+" => Some(left.saturating_add(right)),
+                    "-" => Some(left.saturating_sub(right)),
+                    "*" => Some(left.saturating_mul(right)),
+                    "/" if right != 0 => Some(left / right),
+                    _ => None
+                }
+            },
+            _ => None
+        };
+    }
+
+    fn byte(&self, exp: &Expression)->Option<u8>{
+        return self.constant(exp).filter(| value | (0..=0xff).contains(value)).map(| value | value as u8);
+    }
+
+    // the register the value ends up in
+    fn eval(&mut self, exp: &Expression, source: &mut Vec<String>)->usize{
+        if let Some(value) = self.constant(exp){
+            if !(0..=0xff).contains(&value){
+                self.errors.push(format!("{} does not fit in a byte", value));
+            }
+            let register = self.temporary();
+            source.push(format!("    LD V{:X}, {}", register, value & 0xff));
+            return register;
+        }
+        match exp{
+            Expression::Reference(name) => return self.find(name),
+            Expression::Binary{ left, op, right } if op == "+" || op == "-" =>{
+                let left = self.eval(left, source);
+                // the value is worked out in a temporary register, variables keep theirs
+                let init = if left > self.temporary { left } else {
+                    let init = self.temporary();
+                    source.push(format!("    LD V{:X}, V{:X}", init, left));
+                    init
+                };
+                let right = self.eval(right, source);
+                source.push(format!("    {} V{:X}, V{:X}", if op == "+" { "ADD" } else { "SUB" }, init, right));
+                return init;
+            },
+            Expression::Binary{ op, right, .. } if op == "/" && self.constant(right) == Some(0) => self.errors.push(String::from("division by zero")),
+            Expression::Binary{ op, .. } => self.errors.push(format!("{} only works on numbers known when compiling", op)),
+            Expression::String(text) => self.errors.push(format!("strings like \"{}\" have no code generation yet", text)),
+            Expression::Array(values) => self.errors.push(format!("arrays, like this one of {} values, have no code generation yet", values.len())),
+            _ => self.errors.push(format!("{:?} is not a value", exp))
+        }
+        return 0;
+    }
+
+    fn statement(&mut self, statement: &Expression, source: &mut Vec<String>){
+        self.temporary = 0xe;
+        match statement{
+            Expression::Variable{ name, dt, value } =>{
+                self.check_type(name, dt);
+                // the value is worked out first, it may use a variable of the same name the new one hides
+                let value = value.as_ref().map(| value | self.eval(value, source));
+                let register = self.declare(name, self.function);
+                match value{
+                    Some(value) => source.push(format!("    LD V{:X}, V{:X}", register, value)),
+                    None => source.push(format!("    LD V{:X}, 0", register))
+                }
+            },
+            Expression::Assignment{ variable, exp } =>{
+                let value = self.eval(exp, source);
+                let register = self.find(variable);
+                if value != register{
+                    source.push(format!("    LD V{:X}, V{:X}", register, value));
+                }
+            },
+            Expression::FunctionCall{ name, args } =>{
+                let function = match self.functions.iter().position(| (function, _) | function == name){
+                    Some(function) => function,
+                    None => return self.errors.push(format!("unknown function {}", name))
+                };
+                for arg in args{
+                    if let Expression::ArgumentPassing{ name: arg, .. } = arg{
+                        if !self.functions[function].1.iter().any(| (known, _, _) | known == arg){
+                            self.errors.push(format!("{} has no argument {}", name, arg));
+                        }
+                    }
+                }
+                // every argument is worked out before any is loaded, one of them may use another
+                let mut values = Vec::new();
+                for (arg, register, default) in self.functions[function].1.clone(){
+                    let passed = args.iter().find_map(| passed | match passed{
+                        Expression::ArgumentPassing{ name, value } if *name == arg => Some(value),
+                        _ => None
+                    });
+                    match (passed, default){
+                        (Some(value), _) => values.push((register, self.eval(value, source))),
+                        (None, Some(default)) =>{
+                            let value = self.temporary();
+                            source.push(format!("    LD V{:X}, {}", value, default));
+                            values.push((register, value));
+                        },
+                        (None, None) => self.errors.push(format!("{} is missing the argument {}", name, arg))
+                    }
+                }
+                for (register, value) in values{
+                    source.push(format!("    LD V{:X}, V{:X}", register, value));
+                }
+                source.push(format!("    CALL fun{}", function));
+            },
+            Expression::FunctionDefinition{ name, .. } => self.errors.push(format!("function {} has to be defined at the top of the script", name)),
+            _ => self.errors.push(format!("{:?} is not a statement", statement))
+        }
+    }
+}
+
+impl Default for Compiler{
+    fn default()->Self{ Compiler::new() }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::chip::Chip;
+
+    // runs the rom of a script until it settles in its final loop
+    fn run(script: &str)->Chip{
+        let rom = Compiler::new().compile(script).unwrap();
+        let mut chip = Chip::new();
+        chip.load_rom(&rom).unwrap();
+        chip.run(200).unwrap();
+        return chip;
+    }
+
+    #[test]
+    fn variables_are_registers(){
+        let chip = run("let a: num = 2 + 3 * 4;\nlet b: num = a - 4;\nb = b + a;\nlet c: bool = true;\n");
+        assert_eq!([chip.cpu().get_register(0), chip.cpu().get_register(1), chip.cpu().get_register(2)], [14, 24, 1]);
+    }
+
+    #[test]
+    fn functions_are_subroutines_with_their_arguments_loaded(){
+        let chip = run("let total: num = 1;\nadd(by: 5);\nadd(by: 2, extra: 0);\nfun add(by: num, extra: num = 1){\n    let step: num = by + extra;\n    total = total + step;\n}\n");
+        // the arguments were given v0 and v1 before total
+        assert_eq!(chip.cpu().get_register(2), 1 + 5 + 1 + 2);
+        assert!(chip.cpu().get_stack().is_empty());
+    }
 
-}
\ No newline at end of file
+    #[test]
+    fn scripts_the_generator_cannot_handle_are_errors(){
+        let mut compiler = Compiler::new();
+        assert_eq!(compiler.compile("let name: str = \"chip\";"), Err(vec![String::from("name is a str, only num and bool have code generation"), String::from("strings like \"chip\" have no code generation yet")]));
+        assert_eq!(compiler.compile("let a: num = 300;"), Err(vec![String::from("300 does not fit in a byte")]));
+        assert_eq!(compiler.compile("let a: num = 1 / 0;"), Err(vec![String::from("division by zero")]));
+        assert_eq!(compiler.compile("a = 1;"), Err(vec![String::from("unknown variable a")]));
+        assert_eq!(compiler.compile("missing();"), Err(vec![String::from("unknown function missing")]));
+        assert_eq!(compiler.compile("let a: num = 1\nlet b: num = 2;"), Err(vec![String::from("line 2: expected a semi colon ';'")]));
+    }
+}
@@ -1,4 +1,3 @@
-use crate::chip::utils::hex;
 use std::fmt::{ Error, Formatter, Display};
 use std::ops::{ BitXor, BitOr, BitAnd };
 
@@ -12,67 +11,92 @@ impl Opcode{
     pub fn kk(&self)->u16{ self.code & 0x00ff }
     pub fn n(&self)->u16{ self.code & 0x000f }
 
+    pub fn code(&self)->u16{ self.code }
+
     pub fn clear(&mut self){ self.code = 0; }
 
+    // the two bytes of the word, written as assembler data
+    pub fn data(&self)->String{ format!("0x{:02X} 0x{:02X} ;", self.code >> 8, self.code & 0x00ff) }
+
+    // the instruction in the syntax of the assembler, words that are no instruction as data
     pub fn dessemble(&self)->String{
         match self.code & 0xf000{
             0x0000 =>{
                 match self.code & 0x00ff{
                     0x00e0 => return String::from("CLR"), // clear screen
                     0x00ee => return String::from("RET"), // pop stack pointer
-                    _ => return format!("SYS {}", hex(self.nnn())),
+                    0x00fb => return String::from("SCR"), // scroll right
+                    0x00fc => return String::from("SCL"), // scroll left
+                    0x00fd => return String::from("EXIT"),
+                    0x00fe => return String::from("LOW"),
+                    0x00ff => return String::from("HIGH"),
+                    code if code & 0x00f0 == 0x00c0 => return format!("SCD 0x{:X}", self.n()), // scroll down n lines
+                    code if code & 0x00f0 == 0x00d0 => return format!("SCU 0x{:X}", self.n()), // scroll up n lines
+                    _ => return format!("SYS 0x{:03X}", self.nnn()),
                 }
             },
-            0x1000 => return format!("JP {}", hex(self.nnn())),      // jump to address in opcode
-            0x2000 => return format!("CALL {}", hex(self.nnn())),      // jump to address in opcode,
-            0x3000 => return format!("SE V{}, {}", hex(self.x() as u16), hex(self.kk())),     //skip next instruction if vx is equal to kk
-            0x4000 => return format!("SNE V{}, {}", hex(self.x() as u16), hex(self.kk())),    //skip next instruction if vx is not equal to kk
-            0x5000 => return format!("SE V{}, V{}", hex(self.x() as u16), hex(self.y() as u16)),   //skip next instruction if vx is equal to vy
-            0x6000 => return format!("LD V{}, {}", hex(self.x() as u16), hex(self.kk())),      // set vx = kk
-            0x7000 => return format!("ADD V{}, {}", hex(self.x() as u16), hex(self.kk())),        // 7XNN - add kk to vx
-            0x8000 =>{
+            0x1000 => return format!("JP 0x{:03X}", self.nnn()),      // jump to address in opcode
+            0x2000 => return format!("CALL 0x{:03X}", self.nnn()),      // jump to address in opcode,
+            0x3000 => return format!("SE V{:X}, 0x{:02X}", self.x(), self.kk()),     //skip next instruction if vx is equal to kk
+            0x4000 => return format!("SNE V{:X}, 0x{:02X}", self.x(), self.kk()),    //skip next instruction if vx is not equal to kk
+            0x5000 =>{
                 match self.code & 0x000f{
-                    0x0000 => return format!("LD V{}, V{}", hex(self.x() as u16), hex(self.y() as u16)),    // set vx to vy
-                    0x0001 => return format!("OR V{}, V{}", hex(self.x() as u16), hex(self.y() as u16)),     // vx = vx or vy
-                    0x0002 => return format!("AND V{}, V{}", hex(self.x() as u16), hex(self.y() as u16)),    // vx = vx and vy
-                    0x0003 => return format!("XOR V{}, V{}", hex(self.x() as u16), hex(self.y() as u16)),    // vx = vx xor vy
-                    0x0004 => return format!("ADD V{}, V{}", hex(self.x() as u16), hex(self.y() as u16)),    // vx = vx + vy, vf = carry
-                    0x0005 => return format!("SUB V{}, V{}", hex(self.x() as u16), hex(self.y() as u16)),    // vx = vx-vy vf-not borrow if vx > vy vf = 1 else vf = 0
-                    0x0006 => return format!("SHR V{}", hex(self.x() as u16)),    // SHR vx{, vy}  vx = vx shr 1
-                    0x0007 => return format!("SUBN V{}, V{}", hex(self.x() as u16), hex(self.y() as u16)),   // vx = vy-vx vf-not borrow if vy > vx vf = 1 else vf = 0
-                    0x000e => return format!("SHL V{}", hex(self.x() as u16)),
-                    _ => return format!("{}", self.code),
+                    0x0000 => return format!("SE V{:X}, V{:X}", self.x(), self.y()),   //skip next instruction if vx is equal to vy
+                    0x0002 => return format!("SAVE V{:X}, V{:X}", self.x(), self.y()), // store vx through vy at I
+                    0x0003 => return format!("LOAD V{:X}, V{:X}", self.x(), self.y()), // read vx through vy from I
+                    _ => return self.data(),
                 }
             },
-            0x9000 => return format!("SNE V{}, V{}", hex(self.x() as u16), hex(self.y() as u16)),    // skip next instruction if vx not equal to vy
-            0xa000 => return format!("LD I, {}", hex(self.nnn())),          // set i = nnn
-            0xb000 => return format!("JP V0, {}", hex(self.nnn())),
-            0xc000 => return format!("RND V{}, {}", hex(self.x() as u16), hex(self.kk())),       // vx = random byte and kk
-            0xd000 => return format!("DRW V{}, V{}, {}", hex(self.x() as u16), hex(self.y() as u16), hex(self.n())), //display n-byte sprite starting at memory location I at (vx/vy), set vf=collision
-            0xe000 =>{ 
+            0x6000 => return format!("LD V{:X}, 0x{:02X}", self.x(), self.kk()),      // set vx = kk
+            0x7000 => return format!("ADD V{:X}, 0x{:02X}", self.x(), self.kk()),        // 7XNN - add kk to vx
+            0x8000 =>{
                 match self.code & 0x000f{
-                    0x000e => return format!("SKP V{}", hex(self.x() as u16)), // skip new instruction if keys with the value of vx is pressed
-                    0x0001 => return format!("SKNP V{}", hex(self.x() as u16)), // skip new instruction if keys with the value of vx is not pressed
-                    _ => return format!("{}", self.code),
+                    0x0000 => return format!("LD V{:X}, V{:X}", self.x(), self.y()),    // set vx to vy
+                    0x0001 => return format!("OR V{:X}, V{:X}", self.x(), self.y()),     // vx = vx or vy
+                    0x0002 => return format!("AND V{:X}, V{:X}", self.x(), self.y()),    // vx = vx and vy
+                    0x0003 => return format!("XOR V{:X}, V{:X}", self.x(), self.y()),    // vx = vx xor vy
+                    0x0004 => return format!("ADD V{:X}, V{:X}", self.x(), self.y()),    // vx = vx + vy, vf = carry
+                    0x0005 => return format!("SUB V{:X}, V{:X}", self.x(), self.y()),    // vx = vx-vy vf-not borrow if vx > vy vf = 1 else vf = 0
+                    0x0006 => return format!("SHR V{:X}, V{:X}", self.x(), self.y()),    // SHR vx{, vy}  vx = vx shr 1
+                    0x0007 => return format!("SUBN V{:X}, V{:X}", self.x(), self.y()),   // vx = vy-vx vf-not borrow if vy > vx vf = 1 else vf = 0
+                    0x000e => return format!("SHL V{:X}, V{:X}", self.x(), self.y()),
+                    _ => return self.data(),
+                }
+            },
+            0x9000 => return format!("SNE V{:X}, V{:X}", self.x(), self.y()),    // skip next instruction if vx not equal to vy
+            0xa000 => return format!("LD I, 0x{:03X}", self.nnn()),          // set i = nnn
+            0xb000 => return format!("JP V0, 0x{:03X}", self.nnn()),
+            0xc000 => return format!("RND V{:X}, 0x{:02X}", self.x(), self.kk()),       // vx = random byte and kk
+            0xd000 => return format!("DRW V{:X}, V{:X}, 0x{:X}", self.x(), self.y(), self.n()), //display n-byte sprite starting at memory location I at (vx/vy), set vf=collision
+            0xe000 =>{
+                match self.code & 0x00ff{
+                    0x009e => return format!("SKP V{:X}", self.x()), // skip new instruction if keys with the value of vx is pressed
+                    0x00a1 => return format!("SKNP V{:X}", self.x()), // skip new instruction if keys with the value of vx is not pressed
+                    _ => return self.data(),
                 }
             },
             0xf000 =>{
                 match self.code & 0x00ff{
-                    0x0007 => return format!("LD V{}, DT", hex(self.x() as u16)), // get vx = delay timer table
-                    0x000a => return format!("LD V{}, K", hex(self.x() as u16)),
-                    0x0015 => return format!("LD DT, V{}", hex(self.x() as u16)),      // set delay timer = vx,
-                    0x0018 => return format!("LD ST, V{}", hex(self.x() as u16)),      // set sound timer = vx
-                    0x001e => return format!("ADD I, V{}", hex(self.x() as u16)),         // I = I + vx
-                    0x0029 => return format!("LD F, V{}", hex(self.x() as u16)),       // set i = location of 5 bit sprite for digit vx
-                    0x0033 => return format!("LD B, V{}", hex(self.x() as u16)),           // store BCD representation of vx in memory location I, I + 1, I + 2
-                    0x0055 => return format!("LD [I], V{}", hex(self.x() as u16)),       // read register v0 through vx in memory starting at location I
-                    0x0065 => return format!("LD V{}, [I]", hex(self.x() as u16)),      // read register v0 through vx in memory starting at location I
-                    //0x0075 => self.cpu.user_reg_dump(&opcode),   // store register v0 through vx in memory starting at location I
-                    //0x0085 => self.cpu.user_reg_load(&opcode),   // read register v0 through vx in memory starting at location I
-                    _ => return format!("{}", self.code),
+                    0x0000 if self.x() == 0 => return String::from("LD I, LONG"), // the address is in the following word
+                    0x0001 => return format!("PLANE 0x{:X}", self.x()),
+                    0x0002 if self.x() == 0 => return String::from("AUDIO"),  // load the audio pattern from I
+                    0x003a => return format!("LD PITCH, V{:X}", self.x()),
+                    0x0007 => return format!("LD V{:X}, DT", self.x()), // get vx = delay timer table
+                    0x000a => return format!("LD V{:X}, K", self.x()),
+                    0x0015 => return format!("LD DT, V{:X}", self.x()),      // set delay timer = vx,
+                    0x0018 => return format!("LD ST, V{:X}", self.x()),      // set sound timer = vx
+                    0x001e => return format!("ADD I, V{:X}", self.x()),         // I = I + vx
+                    0x0029 => return format!("LD F, V{:X}", self.x()),       // set i = location of 5 bit sprite for digit vx
+                    0x0030 => return format!("LD HF, V{:X}", self.x()),      // set i = location of 10 bit sprite for digit vx
+                    0x0033 => return format!("LD B, V{:X}", self.x()),           // store BCD representation of vx in memory location I, I + 1, I + 2
+                    0x0055 => return format!("LD [I], V{:X}", self.x()),       // read register v0 through vx in memory starting at location I
+                    0x0065 => return format!("LD V{:X}, [I]", self.x()),      // read register v0 through vx in memory starting at location I
+                    0x0075 => return format!("LD R, V{:X}", self.x()),       // store register v0 through vx in the user flags
+                    0x0085 => return format!("LD V{:X}, R", self.x()),       // read register v0 through vx from the user flags
+                    _ => return self.data(),
                 }
             },
-            _ => return format!("{}", self.code),
+            _ => return self.data(),
         }
     }
 }
@@ -114,7 +138,7 @@ impl Display for Opcode{
                 "13" => "D",
                 "14" => "E",
                 "15" => "F",
-                __ => ch.as_ref()
+                _ => ch.as_ref()
             });
             init /= 16;
         }
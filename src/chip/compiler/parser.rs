@@ -1,12 +1,10 @@
 use crate::chip::compiler::syntax::is_datatype;
 use crate::chip::compiler::syntax::is_keyword;
-use crate::chip::utils::Token;
-use crate::chip::utils::Lexer;
-//use crate::compiler::syntax::{ is_keyword, is_datatype };
+use crate::chip::utils::{ Token, Lexer, from_hex };
 
 #[derive(Debug)]
 pub enum Expression{
-    String(String), Number(f32), Negation(Box<Expression>), Boolean(String), Array(Vec<Expression>),
+    String(String), Number(u16), Boolean(String), Array(Vec<Expression>), Reference(String),
     Binary{ left: Box<Expression>, op:String, right: Box<Expression>},
     Variable{ name: String, dt: String, value: Option<Box<Expression>>},
     Assignment{ variable: String, exp: Box<Expression> },
@@ -15,10 +13,6 @@ pub enum Expression{
     FunctionDefinition{name: String, args: Vec<Expression>, rtype: Option<String>, body: Vec<Expression>},
     FunctionCall{name: String, args: Vec<Expression>}, None }
 
-fn variant_equal(a: &Token, b: &Token)->bool{
-    return std::mem::discriminant(a) == std::mem::discriminant(b);
-}
-
 pub struct Parser{ lexer:Box<Lexer>, errors: Vec<String> , current: Token }
 impl Parser{
     pub fn new(data:&str)->Self{
@@ -27,12 +21,10 @@ impl Parser{
         return parser;
     }
 
-    pub fn has_next(&mut self)->bool{ self.lexer.has_next() }
-    
     fn next_token(&mut self)->bool{
         while self.lexer.has_next(){
             match self.lexer.get_next_token(){
-                Err(error) =>{ self.errors.push(String::from(error)); }
+                Err(error) =>{ self.errors.push(error); }
                 Ok(token) =>{
                     self.current = token;
                     return true;
@@ -55,10 +47,15 @@ impl Parser{
             init = value;
         }else if let Token::String(value) = token{
             init = value;
-        } 
+        }
         init
     }
 
+    // the error for where the parser is, with the line of the source
+    fn error(&mut self, message: String){
+        self.errors.push(format!("line {}: {}", self.lexer.line(), message));
+    }
+
     pub fn get_next(&mut self)->Expression{
         while !matches!(self.current, Token::None){
             if let Token::Name(name) = &self.current{
@@ -69,25 +66,31 @@ impl Parser{
                 }
             }
             let token = self.pop_token();
-            self.errors.push(format!("Unexpected token: {:?}", token));
+            self.error(format!("Unexpected token: {:?}", token));
         }
         return Expression::None;
     }
 
+    // a call or an assignment, both end with a semi colon
     fn get(&mut self)->Expression{
         let init = self.pop_token();
-        if let Token::OpenBracket = self.current{
+        let statement = if let Token::OpenBracket = self.current{
             self.pop_token();
-            return Expression::FunctionCall{ name: Parser::unwrap(init), args: self.get_argument_passing() };
+            Expression::FunctionCall{ name: Parser::unwrap(init), args: self.get_argument_passing() }
         }else if let Token::Equal = self.current{
             self.pop_token();
-            return Expression::Assignment{ variable: Parser::unwrap(init), exp: Box::new(self.make_conditional()) };
-        }
+            Expression::Assignment{ variable: Parser::unwrap(init), exp: Box::new(self.make_conditional()) }
+        }else{
+            self.error(format!("expected a call or an assignment after {}", Parser::unwrap(init)));
+            Expression::None
+        };
 
-        if !matches!(self.current, Token::SemiColon){
-            self.errors.push(format!("expected a semi colon ';'"));
+        if matches!(self.current, Token::SemiColon){
+            self.next_token();
+        }else{
+            self.error(String::from("expected a semi colon ';'"));
         }
-        return Expression::None;
+        return statement;
     }
 
     fn initilaization(&mut self)->Expression{
@@ -98,7 +101,8 @@ impl Parser{
         while self.next_token(){
             if let Token::Name(value) = &self.current {
                 if is_keyword(value.as_str()) && step == 0{
-                    self.errors.push(format!("the word: {} is a reserve word expecting a {}", value, if step == 0 { "name" } else { "Data type" }));
+                    let message = format!("the word: {} is a reserve word expecting a {}", value, if step == 0 { "name" } else { "Data type" });
+                    self.error(message);
                 }else if  name.is_none() && step == 0 {
                     name = Option::from(value.clone());
                     step = 1;
@@ -118,14 +122,18 @@ impl Parser{
                         self.next_token();
                         return Expression::Variable{ name: name.unwrap(), dt: data_type.unwrap(), value };
                     }
+                    self.error(String::from("expected a semi colon ';'"));
+                    return Expression::None;
                 }
             }else if let Token::SemiColon = &self.current {
                 if step == 3 {
                     self.next_token();
                     return Expression::Variable{ name: name.unwrap(), dt: data_type.unwrap(), value: None};
                 }
+                break;
             }
         }
+        self.error(String::from("expected let <name>: <type> = <value>;"));
         return Expression::None;
     }
 
@@ -140,19 +148,21 @@ impl Parser{
                     dt = Option::from(value.clone());
                     step = 2;
                 }else if is_keyword(value.as_str()){
-                    self.errors.push(format!("the word: {} is a reserve word expecting a {}", value, if step == 0 { "name" } else { "Data type" }));
+                    let message = format!("the word: {} is a reserve word expecting a {}", value, if step == 0 { "name" } else { "Data type" });
+                    self.error(message);
                 }
             }else if let Token::Colon = self.current{
                 if step == 0{ step = 1; }
             }else if let Token::Equal = self.current{
                 if step == 2{
+                    self.next_token();
                     value = Some(Box::new(self.make_conditional()));
                     step = 3;
                 }
-            }else if matches!(self.current, Token::Coma) || matches!(self.current, Token::ClosingBracket){
-                if step == 2 || step == 3{
-                    return Expression::ArgumentDefinition{ name, dt: dt.as_ref().unwrap().clone(), value };
-                }
+            }
+            // a default value leaves the parser on the coma or bracket after it
+            if (matches!(self.current, Token::Coma) || matches!(self.current, Token::ClosingBracket)) && (step == 2 || step == 3){
+                return Expression::ArgumentDefinition{ name, dt: dt.as_ref().unwrap().clone(), value };
             }
         }
         return Expression::None;
@@ -168,7 +178,8 @@ impl Parser{
         while self.next_token(){
             if let Token::Name(value) = &self.current {
                 if step == 0 && is_keyword(value.as_str()){
-                    self.errors.push(format!("the word: {} is a reserve word expecting a {}", value, if step == 0 { "name" } else { "Data type" }));
+                    let message = format!("the word: {} is a reserve word expecting a {}", value, if step == 0 { "name" } else { "Data type" });
+                    self.error(message);
                 }else if  step == 0 && name.is_none() {
                     name = Option::from(value.clone());
                     step = 1;
@@ -177,6 +188,8 @@ impl Parser{
                     step = 5;
                 }else if step == 2{
                     args.push(self.get_argument_definition());
+                    // the last argument stops on the closing bracket, which ends the list
+                    if let Token::ClosingBracket = self.current{ step = 3; }
                 }
             }else if let Token::OpenBracket = self.current {
                 if step == 1{ step = 2; }
@@ -191,12 +204,14 @@ impl Parser{
                         body.push(self.get_next());
                     }
                     if let Token::None = self.current {
-                        self.errors.push(format!("Unexpected end of tokens expecting a closing bracket ')'"));
+                        self.error(String::from("Unexpected end of tokens expecting a closing bracket '}'"));
                     }
+                    self.next_token();
                     return Expression::FunctionDefinition{ name:name.unwrap(), args, rtype, body };
                 }
             }
         }
+        self.error(String::from("expected fun <name>(<arguments>) { <statements> }"));
         return Expression::None;
     }
 
@@ -207,88 +222,98 @@ impl Parser{
         loop{
             if matches!(self.current, Token::Name(_)) || matches!(self.current, Token::Colon) || matches!(self.current, Token::Coma){
                 let token = self.pop_token();
-                if let Token::SemiColon = &token{
-                    if name.is_some() && step == 1{
-                        args.push( Expression::ArgumentPassing{ name: name.as_ref().unwrap().clone(), value: Box::new(self.make_conditional())});
+                if let Token::Colon = &token{
+                    if let (Some(name), 1) = (&name, step){
+                        args.push( Expression::ArgumentPassing{ name: name.clone(), value: Box::new(self.make_conditional())});
                         step = 2;
                     }else{
-                        self.errors.push(format!("Unexpected column expecting an argument"));
+                        self.error(String::from("Unexpected column expecting an argument"));
                     }
                 }else if let Token::Coma = &token{
-                    if step == 1 {
-                        if name.is_some(){
-                            self.errors.push(format!("Unexpected token: {:?} exprcting colon(:)", self.current));    
-                        }
+                    if step == 1 && name.is_some(){
+                        let message = format!("Unexpected token: {:?} exprcting colon(:)", self.current);
+                        self.error(message);
                     }
                     name = None;
                     step = 0;
                 }else if let Token::Name(value) = &token{
                     if name.is_some() && step == 1{
-                        self.errors.push(format!("Unexpected token: {:?} expecting a column(:)", &name));
+                        let message = format!("Unexpected token: {:?} expecting a column(:)", &name);
+                        self.error(message);
                     }else if step == 0{
                         if is_keyword(value){
-                            self.errors.push(format!("the word: {} is a reserve word", value));
+                            self.error(format!("the word: {} is a reserve word", value));
                         }else{
                             name = Some(value.clone());
                             step = 1;
                         }
-                    } 
+                    }
                 }
             } else if matches!(self.current, Token::ClosingBracket) || matches!(self.current, Token::None){
                 self.next_token();
                 break;
             } else{
                 let token = self.pop_token();
-                self.errors.push(format!("Unexpected token: {:?}", token));
+                self.error(format!("Unexpected token: {:?}", token));
             }
         }
         return args;
     }
 
+    // binary operators are left associative, factors bind tighter than terms and terms than conditions
     pub fn make_conditional(&mut self)->Expression{
-        let left = self.make_term();
-        if let Token::Conditional(op) = self.current.clone(){
+        let mut left = self.make_term();
+        while let Token::Conditional(op) = self.current.clone(){
             self.next_token();
-            return Expression::Binary{ left: Box::new(left), op: op.clone(), right: Box::new(self.make_conditional()) }
+            left = Expression::Binary{ left: Box::new(left), op: op.clone(), right: Box::new(self.make_term()) };
         }
         return left;
     }
 
     pub fn make_term(&mut self)->Expression{
-        let left = self.make_factor();
-        if let Token::Term(op) = self.current{
+        let mut left = self.make_factor();
+        while let Token::Term(op) = self.current{
             self.next_token();
-            return Expression::Binary{ left: Box::new(left), op: String::from(op), right: Box::new(self.make_conditional()) }
+            left = Expression::Binary{ left: Box::new(left), op: String::from(op), right: Box::new(self.make_factor()) };
         }
         return left;
     }
 
     pub fn make_factor(&mut self)->Expression{
-        let left = self.make_value();
-        if let Token::Factor(op) = self.current{
+        let mut left = self.make_value();
+        while let Token::Factor(op) = self.current{
             self.next_token();
-            return Expression::Binary{ left: Box::new(left), op: String::from(op), right: Box::new(self.make_conditional()) }
+            left = Expression::Binary{ left: Box::new(left), op: String::from(op), right: Box::new(self.make_value()) };
         }
         return left;
     }
 
     fn make_value(&mut self)->Expression{
         while !matches!(self.current, Token::None){
-            if matches!(self.current, Token::String(_)) || matches!(self.current, Token::Boolean(_)) || matches!(self.current, Token::Number(_)){
+            if matches!(self.current, Token::String(_)) || matches!(self.current, Token::Boolean(_)) || matches!(self.current, Token::Number(_)) || matches!(self.current, Token::Name(_)){
                 let init = self.pop_token();
                 if let Token::String(value) = &init{
                     return Expression::String(value.clone());
                 }else if let Token::Boolean(value) = &init{
                     return Expression::Boolean(value.clone());
+                }else if let Token::Name(value) = &init{
+                    return Expression::Reference(value.clone());
                 }else if let Token::Number(value) = &init{
-                    return Expression::Number(value.parse().unwrap());
+                    match from_hex(value){
+                        Ok(number) => return Expression::Number(number),
+                        Err(error) =>{
+                            self.error(error);
+                            return Expression::None;
+                        }
+                    }
                 }
             }else if matches!(self.current, Token::OpenSquareBracket){
                 self.next_token();
                 return self.make_array();
             }else{
-                self.errors.push(format!("Unexpected token: {:?} expecting an string literal or boolean value", &self.current));
-                self.next_token();
+                let message = format!("Unexpected token: {:?} expecting a value", &self.current);
+                self.error(message);
+                return Expression::None;
             }
         }
         return Expression::None;
@@ -302,18 +327,17 @@ impl Parser{
             if matches!(self.current, Token::ClosingSquareBracket){
                 self.next_token();
                 break;
-            } if matches!(self.current, Token::Coma){
+            }else if matches!(self.current, Token::Coma){
                 self.next_token();
             }else{
-                self.errors.push(format!("Unexpected token: {:?}, expected coma(,) or closing square bracket(])", self.current));
+                let message = format!("Unexpected token: {:?}, expected coma(,) or closing square bracket(])", self.current);
+                self.error(message);
+                break;
             }
         }
         return Expression::Array(array);
     }
 
-    pub fn print_errors(&self){
-        for error in &self.errors{
-            println!("{}", error);
-        }
-    }
-}
\ No newline at end of file
+    pub fn is_done(&self)->bool{ matches!(self.current, Token::None) }
+    pub fn errors(&self)->&[String]{ &self.errors }
+}
@@ -1,20 +1,16 @@
-const KeyWords:[&str; 4] = ["in", "as", "is", "to"];
-const DataType: [&str; 5] = ["num" ,"str", "bool", "char", "array"];
-const DeclareWords: [&str; 2] = ["let", "fun"];
-const Controls:[&str; 6] = ["for", "if", "when", "else", "while", "do"];
-const Conditions: [&str; 4] = ["and", "or", "equals", "not"];
-const BoolValues: [&str; 2] = ["true", "false"];
+const KEY_WORDS:[&str; 4] = ["in", "as", "is", "to"];
+const DATA_TYPE: [&str; 5] = ["num" ,"str", "bool", "char", "array"];
+const DECLARE_WORDS: [&str; 2] = ["let", "fun"];
+const CONTROLS:[&str; 6] = ["for", "if", "when", "else", "while", "do"];
+const CONDITIONS: [&str; 4] = ["and", "or", "equals", "not"];
+const BOOL_VALUES: [&str; 2] = ["true", "false"];
 
 pub fn word_in(list: &[&str], key: &str)->bool{
     return list.contains(&key);
 }
 
 pub fn is_keyword(word: &str)->bool{
-    return word_in(&KeyWords, word) | word_in(&DataType, word) | word_in(&DeclareWords, word) | word_in(&Controls, word) | word_in(&Conditions, word) | word_in(&BoolValues, word);
+    return word_in(&KEY_WORDS, word) | word_in(&DATA_TYPE, word) | word_in(&DECLARE_WORDS, word) | word_in(&CONTROLS, word) | word_in(&CONDITIONS, word) | word_in(&BOOL_VALUES, word);
 }
 
-pub fn is_datatype(word: &str)->bool { return word_in(&DataType, word); }
-
-pub fn char_in(list: &str, key: char)-> bool{
-    return list.contains(key);
-}
\ No newline at end of file
+pub fn is_datatype(word: &str)->bool { return word_in(&DATA_TYPE, word); }
@@ -1,8 +1,14 @@
-use rand::Rng;
 use crate::chip::KeyPad;
 use crate::chip::Memory;
 use crate::chip::Opcode;
 use crate::chip::Screen;
+use crate::chip::Audio;
+use crate::chip::random::Random;
+use crate::chip::quirks::{ Quirks, IndexIncrement };
+use crate::chip::error::{ ChipError, FaultAction, FaultPolicy };
+use crate::chip::state::{ StateWriter, StateReader };
+
+const STACK_SIZE: usize = 16;
 
 pub struct Delay{ sound: u8, timer: u8 }
 impl Delay{
@@ -24,33 +30,47 @@ impl Delay{
     pub fn get_timer(&self)-> u8{ self.timer }
 }
 
-pub struct Registers{ v:[u8; 16], r:[u8; 8] }
+// v holds V0-VF, r the SCHIP RPL user flags (8 on the HP48, XO-CHIP extends them to 16)
+pub struct Registers{ v:[u8; 16], r:[u8; 16] }
 impl Registers{
-    fn new()->Self{ Registers{ v: [0; 16], r: [0; 8]} }
+    fn new()->Self{ Registers{ v: [0; 16], r: [0; 16]} }
 
     fn reset(&mut self){
-        for i in 0..self.v.len(){ self.v[i] = 0; }
-        for i in 0..self.r.len(){ self.r[i] = 0; }
+        self.v.fill(0);
+        self.r.fill(0);
     }
 }
 
 pub struct CPU{
-    stack: Vec<u16>,            // stack pointer
+    stack: [u16; STACK_SIZE],   // return addresses
+    sp: usize,                  // stack pointer
     registers: Registers,       // 15 8-bit Registers v0-v15 and v16 carry flag 
     i: u16,                     // 16-bit index register
     pc: u16,                    // 16-bit program counter
-    delay: Delay
+    delay: Delay,
+    quirks: Quirks,
+    policy: FaultPolicy
 }
 
 impl CPU{
     pub fn new()->Self{
-        return CPU{ stack: Vec::new(), registers: Registers::new(), i:0, pc:0x200, delay: Delay::new() }
+        return CPU{
+            stack: [0; STACK_SIZE], sp: 0, registers: Registers::new(), i:0, pc:0x200, delay: Delay::new(),
+            quirks: Quirks::default(), policy: FaultPolicy::default()
+        }
     }
 
+    pub fn get_quirks(&self)->&Quirks{ &self.quirks }
+    pub fn set_quirks(&mut self, quirks: Quirks){ self.quirks = quirks; }
+
+    pub fn get_policy(&self)->&FaultPolicy{ &self.policy }
+    pub fn set_policy(&mut self, policy: FaultPolicy){ self.policy = policy; }
+
     pub fn reset(&mut self){
         self.pc = 0x200;    // clear program counter
         self.i = 0;         // reset current index register
-        self.stack.clear(); // reset stack pointer
+        self.stack.fill(0);
+        self.sp = 0;        // reset stack pointer
 
         // clear registers
         self.registers.reset();
@@ -58,285 +78,722 @@ impl CPU{
         self.delay.reset();
     }
 
-    pub fn fetch(&self, memory: &Memory)->u16{
-        return ((memory.get(self.pc as usize) as u16) << 8) | (memory.get((self.pc + 1) as usize) as u16);
+    pub fn get_register(&self, x: usize)->u8{ self.registers.v[x] }
+    pub fn set_register(&mut self, x: usize, value: u8){ self.registers.v[x] = value; }
+
+    pub fn get_flag(&self, x: usize)->u8{ self.registers.r[x] }
+    pub fn set_flag(&mut self, x: usize, value: u8){ self.registers.r[x] = value; }
+
+    pub fn get_index(&self)->u16{ self.i }
+    pub fn set_index(&mut self, i: u16){ self.i = i; }
+
+    pub fn get_pc(&self)->u16{ self.pc }
+    pub fn set_pc(&mut self, pc: u16){ self.pc = pc; }
+
+    pub fn get_stack(&self)->&[u16]{ &self.stack[..self.sp] }
+
+    pub fn timers(&self)->&Delay{ &self.delay }
+    pub fn timers_mut(&mut self)->&mut Delay{ &mut self.delay }
+
+    pub fn save_state(&self, state: &mut StateWriter){
+        state.bytes(&self.registers.v);
+        state.bytes(&self.registers.r);
+        state.u16(self.i);
+        state.u16(self.pc);
+        state.u8(self.sp as u8);
+        for address in self.stack{
+            state.u16(address);
+        }
+        state.u8(self.delay.timer);
+        state.u8(self.delay.sound);
+        self.quirks.save_state(state);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader)->Result<(), ChipError>{
+        self.registers.v.copy_from_slice(state.bytes(16)?);
+        self.registers.r.copy_from_slice(state.bytes(16)?);
+        self.i = state.u16()?;
+        self.pc = state.u16()?;
+        self.sp = state.u8()? as usize;
+        if self.sp > STACK_SIZE{
+            return Err(ChipError::InvalidState(String::from("stack pointer out of range")));
+        }
+        for address in self.stack.iter_mut(){
+            *address = state.u16()?;
+        }
+        self.delay.timer = state.u8()?;
+        self.delay.sound = state.u8()?;
+        self.quirks = Quirks::load_state(state)?;
+        Ok(())
+    }
+
+    // an instruction on the last byte of memory takes its second byte from address 0
+    pub fn fetch(&self, memory: &Memory)->Result<u16, ChipError>{
+        return Ok(((memory.peek(self.pc as usize)? as u16) << 8) | (memory.peek(self.offset(1) as usize)? as u16));
+    }
+
+    // the address bytes past pc, wrapping around at the end of memory so that a rom running off it starts over at 0
+    fn offset(&self, bytes: u16)->u16{
+        return ((self.pc as usize + bytes as usize) % self.quirks.memory_size.max(1)) as u16;
+    }
+
+    fn advance(&mut self, bytes: u16){ self.pc = self.offset(bytes); }
+
+    // moves past the next instruction, which is 4 bytes long for the XO-CHIP F000 NNNN long load
+    fn skip(&mut self, condition: bool, memory: &Memory)->Result<(), ChipError>{
+        self.advance(2);
+        if condition{
+            self.advance(if self.fetch(memory)? == 0xf000 { 4 } else { 2 });
+        }
+        Ok(())
     }
 
     pub fn clear_screen(&mut self, screen: &mut Screen){
-        screen.clear();
-        self.pc += 2;
+        screen.clear_planes();
+        self.advance(2);
+    }
+
+    pub fn scroll_down(&mut self, opcode: &Opcode, screen: &mut Screen){
+        screen.scroll_down(opcode.n() as usize);
+        self.advance(2);
+    }
+
+    pub fn scroll_up(&mut self, opcode: &Opcode, screen: &mut Screen){
+        screen.scroll_up(opcode.n() as usize);
+        self.advance(2);
     }
 
-    pub fn call(&mut self, opcode: &Opcode){
-        self.stack.push(self.pc + 2);
+    pub fn plane(&mut self, opcode: &Opcode, screen: &mut Screen){
+        screen.set_planes(opcode.x() as u8);
+        self.advance(2);
+    }
+
+    pub fn scroll_right(&mut self, screen: &mut Screen){
+        screen.scroll_right(4);
+        self.advance(2);
+    }
+
+    pub fn scroll_left(&mut self, screen: &mut Screen){
+        screen.scroll_left(4);
+        self.advance(2);
+    }
+
+    pub fn lores(&mut self, screen: &mut Screen){
+        screen.set_extended(false);
+        self.advance(2);
+    }
+
+    pub fn hires(&mut self, screen: &mut Screen){
+        screen.set_extended(true);
+        self.advance(2);
+    }
+
+    // on overflow wrapping overwrites the bottom of the stack, ignoring skips the call
+    pub fn call(&mut self, opcode: &Opcode)->Result<(), ChipError>{
+        if self.sp == STACK_SIZE{
+            match self.policy.stack_overflow{
+                FaultAction::Halt => return Err(ChipError::StackOverflow),
+                FaultAction::Ignore =>{
+                    self.advance(2);
+                    return Ok(());
+                },
+                FaultAction::Wrap => self.sp = 0
+            }
+        }
+        self.stack[self.sp] = self.offset(2);
+        self.sp += 1;
         self.pc = opcode.nnn();
+        Ok(())
     }
     
-    pub fn ret(&mut self){ self.pc = self.stack.pop().unwrap(); }
+    // on underflow wrapping returns to whatever the top slot of the stack holds, ignoring skips the return
+    pub fn ret(&mut self)->Result<(), ChipError>{
+        if self.sp == 0{
+            match self.policy.stack_underflow{
+                FaultAction::Halt => return Err(ChipError::StackUnderflow),
+                FaultAction::Ignore =>{
+                    self.advance(2);
+                    return Ok(());
+                },
+                FaultAction::Wrap => self.sp = STACK_SIZE
+            }
+        }
+        self.sp -= 1;
+        self.pc = self.stack[self.sp];
+        Ok(())
+    }
     pub fn jump(&mut self, opcode: &Opcode){ self.pc = opcode.nnn(); }
 
-    pub fn if_eq(&mut self, opcode: &Opcode){
+    pub fn if_eq(&mut self, opcode: &Opcode, memory: &Memory)->Result<(), ChipError>{
         let vx = self.registers.v[opcode.x()] as u16;
-        self.pc += if vx == opcode.kk() { 4 } else { 2 };
+        return self.skip(vx == opcode.kk(), memory);
     }
     
-    pub fn if_not_eq(&mut self, opcode: &Opcode){
+    pub fn if_not_eq(&mut self, opcode: &Opcode, memory: &Memory)->Result<(), ChipError>{
         let vx = self.registers.v[opcode.x()] as u16;
-        self.pc += if vx != opcode.kk() { 4 } else { 2 };
+        return self.skip(vx != opcode.kk(), memory);
     }
     
-    pub fn if_eq_reg(&mut self, opcode: &Opcode){
+    pub fn if_eq_reg(&mut self, opcode: &Opcode, memory: &Memory)->Result<(), ChipError>{
         let vx = self.registers.v[opcode.x()];
         let vy = self.registers.v[opcode.y()];
-        self.pc += if vx == vy { 4 } else { 2 };
+        return self.skip(vx == vy, memory);
+    }
+
+    // 5xy2 stores vx through vy at I, in reverse order when x > y, without touching I
+    pub fn save_range(&mut self, opcode: &Opcode, memory: &mut Memory)->Result<(), ChipError>{
+        let (x, y) = (opcode.x(), opcode.y());
+        let count = x.abs_diff(y);
+        for i in 0..=count{
+            let register = if x <= y { x + i } else { x - i };
+            memory.save(self.i as usize + i, self.registers.v[register])?;
+        }
+        self.advance(2);
+        Ok(())
+    }
+
+    pub fn load_range(&mut self, opcode: &Opcode, memory: &Memory)->Result<(), ChipError>{
+        let (x, y) = (opcode.x(), opcode.y());
+        let count = x.abs_diff(y);
+        for i in 0..=count{
+            let register = if x <= y { x + i } else { x - i };
+            self.registers.v[register] = memory.get(self.i as usize + i)?;
+        }
+        self.advance(2);
+        Ok(())
     }
 
     pub fn set(&mut self, opcode: &Opcode){
         self.registers.v[opcode.x()] = opcode.kk() as u8;
-        self.pc += 2;
+        self.advance(2);
     }
     
     pub fn add(&mut self, opcode: &Opcode){
         let init = self.registers.v[opcode.x()] as u16 + opcode.kk();
 		self.registers.v[opcode.x()] = (if init >= 256 { init - 256 } else { init }) as u8;
-        self.pc += 2;
+        self.advance(2);
     }
     
     pub fn assign(&mut self, opcode: &Opcode){
         self.registers.v[opcode.x()] = self.registers.v[opcode.y()];
-        self.pc += 2;
+        self.advance(2);
     }
     
     pub fn bit_or(&mut self, opcode: &Opcode){
         self.registers.v[opcode.x()] |= self.registers.v[opcode.y()];
-        self.pc += 2;
+        if self.quirks.vf_reset{ self.registers.v[0xf] = 0; }
+        self.advance(2);
     }
     
     pub fn bit_and(&mut self, opcode: &Opcode){
         self.registers.v[opcode.x()] &= self.registers.v[opcode.y()];
-        self.pc += 2;
+        if self.quirks.vf_reset{ self.registers.v[0xf] = 0; }
+        self.advance(2);
     }
     
     pub fn bit_xor(&mut self, opcode: &Opcode){
         self.registers.v[opcode.x()] ^= self.registers.v[opcode.y()];
-        self.pc += 2;
+        if self.quirks.vf_reset{ self.registers.v[0xf] = 0; }
+        self.advance(2);
     }
     
+    // the flag is written after the result so that vf as a destination ends up holding the flag
     pub fn add_reg(&mut self, opcode: &Opcode){
         let x = opcode.x();
         let y = opcode.y();
-        let init = self.registers.v[x] as u16 + self.registers.v[y] as u16;
-        self.registers.v[0xf] = ((init & 0xff00) >> 8) as u8; 
-        self.registers.v[x] = (init & 0x00ff) as u8; 
-        self.pc += 2;
+        let (result, carry) = self.registers.v[x].overflowing_add(self.registers.v[y]);
+        self.registers.v[x] = result;
+        self.registers.v[0xf] = carry as u8;
+        self.advance(2);
     }
     
     pub fn sub_reg(&mut self, opcode: &Opcode){
         let x = opcode.x();
         let y = opcode.y();
 
-        self.registers.v[0xf] = if self.registers.v[x] > self.registers.v[y]{ 1 } else { 0 };
-        self.registers.v[x] = ((self.registers.v[x] as u16 - self.registers.v[y] as u16) & 0x00ff) as u8;
-        self.pc += 2;
+        let (result, borrow) = self.registers.v[x].overflowing_sub(self.registers.v[y]);
+        self.registers.v[x] = result;
+        self.registers.v[0xf] = !borrow as u8;
+        self.advance(2);
     }
 
     pub fn shift_right(&mut self, opcode: &Opcode){
         let x = opcode.x();
-       
-        self.registers.v[0xf] = self.registers.v[x] & 0x01;
-        self.registers.v[x] = self.registers.v[x] >> 1;
-        self.pc += 2;
+        let source = self.registers.v[if self.quirks.shift_vy { opcode.y() } else { x }];
+
+        self.registers.v[x] = source >> 1;
+        self.registers.v[0xf] = source & 0x01;
+        self.advance(2);
     }
     
     pub fn sub_copy(&mut self, opcode: &Opcode){
         let x = opcode.x();
         let y = opcode.y();
 
-        self.registers.v[0xf] = if self.registers.v[y] > self.registers.v[x]{ 1 } else { 0 };
-        self.registers.v[x] = self.registers.v[y] - self.registers.v[x];
-        self.pc += 2;
+        let (result, borrow) = self.registers.v[y].overflowing_sub(self.registers.v[x]);
+        self.registers.v[x] = result;
+        self.registers.v[0xf] = !borrow as u8;
+        self.advance(2);
     }
     
     pub fn shift_left(&mut self, opcode: &Opcode){
         let x = opcode.x();
-        /*if shift_quirk{
-            let res = self.registers.v[x] << 1;
-            self.registers.v[x] = res;
-            self.registers.v[0xf] = (self.registers.v[x] & 0x80) >> 7;
-        }else {
-            let y = opcode.y();
-
-            let res = self.registers.v[y] << 1;
-            self.registers.v[x] = res;
-            self.registers.v[0xf] = (self.registers.v[y] & 0x80) >> 7;
-            self.registers.v[y] = res;
-        }*/
-        
-        self.registers.v[0xf] = self.registers.v[x] >> 7;
-        self.registers.v[x] = self.registers.v[x] << 1;
-        self.pc += 2;
-    }
-
-    pub fn if_not_eq_reg(&mut self, opcode: &Opcode){
+        let source = self.registers.v[if self.quirks.shift_vy { opcode.y() } else { x }];
+
+        self.registers.v[x] = source << 1;
+        self.registers.v[0xf] = source >> 7;
+        self.advance(2);
+    }
+
+    pub fn if_not_eq_reg(&mut self, opcode: &Opcode, memory: &Memory)->Result<(), ChipError>{
         let vx = self.registers.v[opcode.x()];
         let vy = self.registers.v[opcode.y()];
-        self.pc += if vx != vy { 4 } else { 2 };
+        return self.skip(vx != vy, memory);
     }
     
     pub fn set_i(&mut self, opcode: &Opcode){
         self.i = opcode.nnn();  // set i = nnn
-        self.pc += 2;
+        self.advance(2);
+    }
+
+    // F000 NNNN, the address is the word following the instruction
+    pub fn set_i_long(&mut self, memory: &Memory)->Result<(), ChipError>{
+        self.advance(2);
+        self.i = self.fetch(memory)?;
+        self.advance(2);
+        Ok(())
     }
     
     pub fn jump_v0(&mut self, opcode: &Opcode){
-        self.pc = opcode.nnn() + (self.registers.v[0] as u16);
+        let offset = self.registers.v[if self.quirks.jump_vx { opcode.x() } else { 0 }];
+        self.pc = opcode.nnn() + (offset as u16);
     }
     
-    pub fn and_rand(&mut self, opcode: &Opcode){
-        let mut rng = rand::thread_rng();
-        let rand: u16 = rng.gen_range(0..256);
-        self.registers.v[opcode.x()] = (((opcode.kk()) + rand) & 0xff) as u8;
-        self.pc += 2;
+    pub fn and_rand(&mut self, opcode: &Opcode, random: &mut dyn Random){
+        self.registers.v[opcode.x()] = random.next() & opcode.kk() as u8;
+        self.advance(2);
+    }
+
+    pub fn load_pattern(&mut self, audio: &mut Audio, memory: &Memory)->Result<(), ChipError>{
+        let mut pattern = [0; 16];
+        for (i, byte) in pattern.iter_mut().enumerate(){
+            *byte = memory.get(self.i as usize + i)?;
+        }
+        audio.load_pattern(pattern);
+        self.advance(2);
+        Ok(())
+    }
+
+    pub fn set_pitch(&mut self, opcode: &Opcode, audio: &mut Audio){
+        audio.set_pitch(self.registers.v[opcode.x()]);
+        self.advance(2);
     }
 
     pub fn get_delay(&mut self, opcode: &Opcode){
         self.registers.v[opcode.x()] = self.delay.get_timer();
-        self.pc += 2;
+        self.advance(2);
     }
     
     pub fn set_delay(&mut self, opcode: &Opcode){
         self.delay.set_timer(self.registers.v[opcode.x()]);
-        self.pc += 2;
+        self.advance(2);
     }
     
     pub fn set_sound(&mut self, opcode: &Opcode){
         self.delay.set_sound(self.registers.v[opcode.x()]);
-        self.pc += 2;
+        self.advance(2);
     }
 
     pub fn add_i(&mut self, opcode: &Opcode){
-        self.i += self.registers.v[opcode.x()] as u16;
-        self.pc += 2;
+        self.i = self.i.wrapping_add(self.registers.v[opcode.x()] as u16);
+        if self.quirks.add_i_overflow{
+            self.registers.v[0xf] = (self.i > 0x0fff) as u8;
+        }
+        self.advance(2);
     }
     
+    // only the low nibble of vx picks a digit, anything higher would point I past the font
     pub fn get_font(&mut self, opcode: &Opcode){
-        self.i = (self.registers.v[opcode.x()] as u16) * 5;
-        self.pc += 2;
+        self.i = (self.registers.v[opcode.x()] & 0x0f) as u16 * 5;
+        self.advance(2);
     }
     
     pub fn get_font16(&mut self, opcode: &Opcode){
-        self.i = (self.registers.v[opcode.x()] as u16) * 10 + 80;
-        self.pc += 2;
+        self.i = (self.registers.v[opcode.x()] & 0x0f) as u16 * 10 + 80;
+        self.advance(2);
     }
 
-    pub fn bcd(&mut self, opcode: &Opcode, memory: &mut Memory){
+    pub fn bcd(&mut self, opcode: &Opcode, memory: &mut Memory)->Result<(), ChipError>{
         let vx = self.registers.v[opcode.x()];
-        memory.save(self.i as usize, vx / 100);
-        memory.save((self.i + 1) as usize, (vx % 100) / 10);
-        memory.save((self.i + 2) as usize, vx % 10);
-        self.pc += 2
+        memory.save(self.i as usize, vx / 100)?;
+        memory.save(self.i as usize + 1, (vx % 100) / 10)?;
+        memory.save(self.i as usize + 2, vx % 10)?;
+        self.advance(2);
+        Ok(())
     }
     
-    pub fn reg_dump(&mut self, opcode: &Opcode, memory: &mut Memory){
+    pub fn reg_dump(&mut self, opcode: &Opcode, memory: &mut Memory)->Result<(), ChipError>{
         let vx = opcode.x();
-        for i in 0..vx{
-            memory.save((self.i) as usize, self.registers.v[i as usize]); 
-            self.i += 1;
+        for i in 0..=vx{
+            memory.save((self.i as usize) + i, self.registers.v[i])?; 
         }
-        //self.i += vx as u16;
-        self.pc += 2;
+        self.increment_index(vx);
+        self.advance(2);
+        Ok(())
     }
     
-    pub fn reg_load(&mut self, opcode: &Opcode, memory: &Memory){
+    pub fn reg_load(&mut self, opcode: &Opcode, memory: &Memory)->Result<(), ChipError>{
         let vx = opcode.x();
-        for i in 0..vx{
-            self.registers.v[i as usize] = memory.get((self.i) as usize); 
-            self.i += 1;
+        for i in 0..=vx{
+            self.registers.v[i] = memory.get((self.i as usize) + i)?; 
         }
-        //self.i += vx as u16;
-        self.pc += 2;
+        self.increment_index(vx);
+        self.advance(2);
+        Ok(())
     }
 
-    /*pub fn user_reg_dump(&mut self, opcode: &Opcode){
-        let vx = opcode.x();
-        for i in 0..vx + 1{
-            self.r[i as usize] = self.registers.v[i as usize]; 
-            self.i += 1;
+    fn increment_index(&mut self, vx: usize){
+        match self.quirks.load_store{
+            IndexIncrement::Unchanged => {},
+            IndexIncrement::X => self.i = self.i.wrapping_add(vx as u16),
+            IndexIncrement::XPlusOne => self.i = self.i.wrapping_add(vx as u16 + 1)
         }
-        self.pc += 2;
+    }
+
+    pub fn user_reg_dump(&mut self, opcode: &Opcode){
+        let vx = opcode.x();
+        self.registers.r[..=vx].copy_from_slice(&self.registers.v[..=vx]);
+        self.advance(2);
     }
     
     pub fn user_reg_load(&mut self, opcode: &Opcode){
         let vx = opcode.x();
-        for i in 0..vx + 1{
-            self.registers.v[i as usize] = self.r[i as usize]; 
-            self.i += 1;
-        }
-        self.pc += 2;
-    }*/
+        self.registers.v[..=vx].copy_from_slice(&self.registers.r[..=vx]);
+        self.advance(2);
+    }
 
-    pub fn if_key(&mut self, opcode: &Opcode, keys: &KeyPad){
-        self.pc = if keys.get(self.registers.v[opcode.x()]as usize){ 4 } else { 2 };
+    pub fn if_key(&mut self, opcode: &Opcode, keys: &KeyPad, memory: &Memory)->Result<(), ChipError>{
+        let pressed = keys.get((self.registers.v[opcode.x()] & 0x0f) as usize);
+        return self.skip(pressed, memory);
     }
     
-    pub fn if_not_key(&mut self, opcode: &Opcode, keys: &KeyPad){
-        self.pc = if !keys.get(self.registers.v[opcode.x()] as usize){ 4 } else { 2 };
+    pub fn if_not_key(&mut self, opcode: &Opcode, keys: &KeyPad, memory: &Memory)->Result<(), ChipError>{
+        let pressed = keys.get((self.registers.v[opcode.x()] & 0x0f) as usize);
+        return self.skip(!pressed, memory);
     }
 
+    // the buzzer itself is rendered by Audio for as long as the sound timer runs
     pub fn update(&mut self){
-        if self.delay.get_sound() == 1 {
-            println!("BEEP!");
-        }
         self.delay.update();
     }
 
-    pub fn wait_key(&mut self, opcode: &Opcode, keys: &mut KeyPad){
-        for i in 0..0x10{
-            if keys.get(i){
-                self.registers.v[(opcode & 0x0f00) as usize] = i as u8;
-                keys.clear_key(i);
-                self.pc += 2; 
-            }
-        } 
+    // only keys going down or up from now on count, the chip then stops fetching until one does
+    pub fn wait_key(&mut self, opcode: &Opcode, keys: &mut KeyPad)->usize{
+        keys.clear_events();
+        self.advance(2);
+        return opcode.x();
+    }
+
+    // unless halting on it, an invalid opcode is skipped over
+    pub fn invalid(&mut self, opcode: &Opcode)->Result<(), ChipError>{
+        if self.policy.invalid_opcode == FaultAction::Halt{
+            return Err(ChipError::InvalidOpcode{ pc: self.pc, opcode: opcode.code() });
+        }
+        self.advance(2);
+        Ok(())
     }
 
-    // Function that draw in Xor mode
-    /*pub fn draw(&mut self, opcode: &Opcode, screen: &mut Screen, memory: &Memory){
-        //display n-byte sprite starting at memory location I at (vx/vy), set vf=collision
-        let height = opcode.n();
+    pub fn draw(&mut self, opcode: &Opcode, screen: &mut Screen, memory: &Memory)->Result<(), ChipError>{
         let x = opcode.x();
         let y = opcode.y();
-        let mut sprite: Vec<u16> = Vec::new();
-        if height == 0 && screen.is_extended(){
-            for i in (0..32).step_by(2){
-                let left:u16 = (memory.get(self.i as usize + i) as u16) << 8;
-                let right: u16 = memory.get(self.i as usize + i + 1) as u16;
-                sprite.push(left | right);
+
+        // Dxy0 draws a 16x16 sprite made of 2 byte rows, with two planes selected
+        // the data for the second plane directly follows the first one
+        let (width, size) = if opcode.n() == 0 { (16, 32) } else { (8, opcode.n() as usize) };
+        let mut address = self.i as usize;
+        let mut vf = 0;
+        for plane in [0x01, 0x02]{
+            if screen.get_planes() & plane == 0{
+                continue;
             }
-            self.registers.v[0xf] = screen.schip_draw(self.registers.v[x] as u16, self.registers.v[y] as u16, sprite);
-        }else{
-            for i in 0..height{
-                sprite.push(memory.get(self.i as usize + i as usize) as u16); 
+
+            let mut sprite: Vec<u16> = Vec::new();
+            if width == 16{
+                for i in (0..size).step_by(2){
+                    let left:u16 = (memory.get(address + i)? as u16) << 8;
+                    let right: u16 = memory.get(address + i + 1)? as u16;
+                    sprite.push(left | right);
+                }
+            }else{
+                for i in 0..size{
+                    let init = memory.get(address + i)? as u16;
+                    sprite.push(init); 
+                }
             }
-            self.registers.v[0xf] = screen.draw(self.registers.v[x] as u16 , self.registers.v[y] as u16, height, sprite);
+            address += size;
+            vf = vf.max(screen.draw(self.registers.v[x] as u16 , self.registers.v[y] as u16, sprite, width, self.quirks.clip_sprites, plane));
         }
-        self.pc += 2;
-    }*/
-
-    pub fn invalid(&mut self, opcode: &Opcode){
-        println!("unknown or invalid command: {}", opcode);
+        self.registers.v[0xf] = vf;
+        self.advance(2);
+        Ok(())
     }
+}
 
-    pub fn draw(&mut self, opcode: &Opcode, screen: &mut Screen, memory: &Memory){
-        let x = opcode.x();
-        let y = opcode.y();
+impl Default for CPU{
+    fn default()->Self{ CPU::new() }
+}
 
-        let mut sprite: Vec<u16> = Vec::new();
-        for i in 0..opcode.n(){
-            let init = memory.get((self.i + i) as usize) as u16;
-            sprite.push(init); 
+#[cfg(test)]
+mod tests{
+    use crate::chip::{ Chip, Quirks };
+
+    // a chip on the given quirks with the rom loaded, nothing run yet
+    fn load(quirks: Quirks, rom: &[u8])->Chip{
+        let mut chip = Chip::with_quirks(quirks);
+        chip.load_rom(rom).unwrap();
+        return chip;
+    }
+
+    // the lit pixels of a row of the screen, from x up to x + width
+    fn row(chip: &Chip, x: usize, y: usize, width: usize)->Vec<u8>{
+        return (x..x + width).map(| x | chip.screen().get(x, y)).collect();
+    }
+
+    #[test]
+    fn pc_wraps_around_the_end_of_memory(){
+        let mut chip = Chip::with_quirks(Quirks::preset("xo-chip").unwrap());
+        chip.load_rom(&[0x00, 0xe0]).unwrap();
+        chip.memory_mut().poke(0xfffe, 0x22).unwrap();   // call 0x200
+        chip.memory_mut().poke(0xffff, 0x00).unwrap();
+        chip.cpu_mut().set_pc(0xfffe);
+        chip.step().unwrap();
+        assert_eq!(chip.cpu().get_stack(), &[0x0000]);
+        chip.cpu_mut().set_pc(0xfffe);
+        chip.memory_mut().poke(0xfffe, 0x60).unwrap();   // v0 = 0
+        chip.step().unwrap();
+        assert_eq!(chip.cpu().get_pc(), 0x0000);
+
+        // 60xx on the last byte, its second byte is the first of the font at 0
+        chip.memory_mut().poke(0xffff, 0x60).unwrap();
+        chip.cpu_mut().set_pc(0xffff);
+        chip.step().unwrap();
+        assert_eq!((chip.cpu().get_register(0), chip.cpu().get_pc()), (0xf0, 0x0001));
+
+        // the same on 4K of memory, after JP 0xFFF
+        let mut chip = Chip::new();
+        chip.load_rom(&[0x1f, 0xff]).unwrap();
+        chip.memory_mut().poke(0xfff, 0x61).unwrap();
+        chip.run(2).unwrap();
+        assert_eq!((chip.cpu().get_register(1), chip.cpu().get_pc()), (0xf0, 0x0001));
+    }
+
+    #[test]
+    fn hires_and_lores_switch_the_display_and_clear_it(){
+        // 00FF A000 D015 00FE
+        let mut chip = load(Quirks::schip_modern(), &[0x00, 0xff, 0xa0, 0x00, 0xd0, 0x15, 0x00, 0xfe]);
+        chip.step().unwrap();
+        assert!(chip.screen().is_extended());
+        assert_eq!((chip.screen().width(), chip.screen().height()), (128, 64));
+        chip.run(2).unwrap();
+        assert_eq!(row(&chip, 0, 0, 5), [1, 1, 1, 1, 0]);
+        chip.step().unwrap();
+        assert!(!chip.screen().is_extended());
+        assert_eq!((chip.screen().width(), chip.screen().height()), (64, 32));
+        assert_eq!(row(&chip, 0, 0, 5), [0; 5]);
+    }
+
+    #[test]
+    fn scrolling_moves_the_display_down_right_and_left(){
+        // A000 D015 00C2 00FB 00FC: the 0 of the font, F0 90 90 90 F0
+        let mut chip = load(Quirks::schip_modern(), &[0xa0, 0x00, 0xd0, 0x15, 0x00, 0xc2, 0x00, 0xfb, 0x00, 0xfc]);
+        chip.run(3).unwrap();
+        assert_eq!(row(&chip, 0, 0, 8), [0; 8]);
+        assert_eq!(row(&chip, 0, 2, 8), [1, 1, 1, 1, 0, 0, 0, 0]);
+        assert_eq!(row(&chip, 0, 3, 8), [1, 0, 0, 1, 0, 0, 0, 0]);
+        chip.step().unwrap();
+        assert_eq!(row(&chip, 0, 2, 8), [0, 0, 0, 0, 1, 1, 1, 1]);
+        chip.step().unwrap();
+        assert_eq!(row(&chip, 0, 2, 8), [1, 1, 1, 1, 0, 0, 0, 0]);
+
+        // pixels scrolled off an edge are gone rather than wrapped
+        let mut chip = load(Quirks::schip_modern(), &[0x00, 0xff, 0xa0, 0x00, 0xd0, 0x15, 0x00, 0xfc, 0x00, 0xcf]);
+        chip.run(4).unwrap();
+        assert_eq!(row(&chip, 124, 0, 4), [0; 4]);
+        assert_eq!(row(&chip, 0, 0, 4), [0; 4]);
+        chip.step().unwrap();
+        assert!((0..64).all(| y | row(&chip, 0, y, 128).iter().all(| pixel | *pixel == 0)));
+    }
+
+    #[test]
+    fn dxy0_draws_16_by_16_sprites_and_counts_colliding_rows_in_hires(){
+        // 00FF A300 D000 D000 D001 6138 D010
+        let mut chip = load(Quirks::schip_modern(), &[0x00, 0xff, 0xa3, 0x00, 0xd0, 0x00, 0xd0, 0x00, 0xd0, 0x01, 0x61, 0x38, 0xd0, 0x10]);
+        for address in (0x300..0x320).step_by(2){
+            chip.memory_mut().poke(address, 0x80).unwrap();
+            chip.memory_mut().poke(address + 1, 0x01).unwrap();
+        }
+        chip.run(3).unwrap();
+        for y in 0..16{
+            let mut lit = [0u8; 17];
+            lit[0] = 1;
+            lit[15] = 1;
+            assert_eq!(row(&chip, 0, y, 17), lit);
+        }
+        assert_eq!(row(&chip, 0, 16, 16), [0; 16]);
+        assert_eq!(chip.cpu().get_register(0xf), 0);
+
+        // every one of the 16 rows collides
+        chip.step().unwrap();
+        assert_eq!(chip.cpu().get_register(0xf), 16);
+        // a one row sprite, drawn twice over its own row
+        chip.step().unwrap();
+        assert_eq!(chip.cpu().get_register(0xf), 0);
+        chip.cpu_mut().set_pc(0x208);
+        chip.step().unwrap();
+        assert_eq!(chip.cpu().get_register(0xf), 1);
+
+        // at y 56 the 8 rows clipped off the bottom count like collisions
+        chip.run(2).unwrap();
+        assert_eq!(chip.cpu().get_register(0xf), 8);
+        assert_eq!(row(&chip, 0, 63, 1), [1]);
+    }
+
+    #[test]
+    fn lores_collisions_set_vf_to_1(){
+        // A300 D000 D000
+        let mut chip = load(Quirks::schip_modern(), &[0xa3, 0x00, 0xd0, 0x00, 0xd0, 0x00]);
+        for address in 0x300..0x320{
+            chip.memory_mut().poke(address, 0xff).unwrap();
         }
-        self.registers.v[0xf] = screen.draw(self.registers.v[x] as u16 , self.registers.v[y] as u16, sprite);
-        self.pc += 2;
+        chip.run(2).unwrap();
+        assert_eq!(chip.cpu().get_register(0xf), 0);
+        chip.step().unwrap();
+        assert_eq!(chip.cpu().get_register(0xf), 1);
+    }
+
+    #[test]
+    fn font_opcodes_only_look_at_the_low_nibble(){
+        // 601A F029 F030
+        let mut chip = load(Quirks::schip_modern(), &[0x60, 0x1a, 0xf0, 0x29, 0xf0, 0x30]);
+        chip.run(2).unwrap();
+        assert_eq!(chip.cpu().get_index(), 0xa * 5);
+        // the big A, not 0x1a digits past the big font
+        chip.step().unwrap();
+        assert_eq!(chip.cpu().get_index(), 80 + 0xa * 10);
+        let address = chip.cpu().get_index() as usize;
+        assert_eq!(chip.memory().get(address + 10).unwrap(), chip.memory().get(80 + 0xb * 10).unwrap());
+    }
+
+    #[test]
+    fn user_flags_keep_registers_apart_from_memory(){
+        // 6001 6102 6203 F275 6000 6100 6200 F185
+        let mut chip = load(Quirks::schip_modern(), &[0x60, 0x01, 0x61, 0x02, 0x62, 0x03, 0xf2, 0x75, 0x60, 0x00, 0x61, 0x00, 0x62, 0x00, 0xf1, 0x85]);
+        chip.run(4).unwrap();
+        assert_eq!([chip.cpu().get_flag(0), chip.cpu().get_flag(1), chip.cpu().get_flag(2), chip.cpu().get_flag(3)], [1, 2, 3, 0]);
+        assert_eq!(chip.cpu().get_index(), 0);
+        chip.run(4).unwrap();
+        // only v0 to v1 are read back
+        assert_eq!([chip.cpu().get_register(0), chip.cpu().get_register(1), chip.cpu().get_register(2)], [1, 2, 0]);
+    }
+
+    #[test]
+    fn register_ranges_are_stored_and_loaded_in_either_order(){
+        // A300 6011 6122 6233 5022 5202 6000 6100 6200 A302 5203 A303 5013
+        let mut chip = load(Quirks::xo_chip(), &[
+            0xa3, 0x00, 0x60, 0x11, 0x61, 0x22, 0x62, 0x33, 0x50, 0x22, 0x52, 0x02,
+            0x60, 0x00, 0x61, 0x00, 0x62, 0x00, 0xa3, 0x02, 0x52, 0x03, 0xa3, 0x03, 0x50, 0x13
+        ]);
+        chip.run(5).unwrap();
+        assert_eq!((0x300..0x303).map(| address | chip.memory().get(address).unwrap()).collect::<Vec<u8>>(), [0x11, 0x22, 0x33]);
+        // v2 down to v0 goes in reverse, I stays where it is
+        chip.memory_mut().poke(0x303, 0x44).unwrap();
+        chip.step().unwrap();
+        assert_eq!((0x300..0x304).map(| address | chip.memory().get(address).unwrap()).collect::<Vec<u8>>(), [0x33, 0x22, 0x11, 0x44]);
+        assert_eq!(chip.cpu().get_index(), 0x300);
+
+        // 11 44 00 from 0x302 go into v2, v1 and v0
+        chip.run(5).unwrap();
+        assert_eq!([chip.cpu().get_register(0), chip.cpu().get_register(1), chip.cpu().get_register(2)], [0x00, 0x44, 0x11]);
+        chip.run(2).unwrap();
+        assert_eq!([chip.cpu().get_register(0), chip.cpu().get_register(1)], [0x44, 0x00]);
+        assert_eq!(chip.cpu().get_index(), 0x303);
+    }
+
+    #[test]
+    fn f000_loads_a_16_bit_index_from_the_next_word(){
+        // F000 ABCD 6001
+        let mut chip = load(Quirks::xo_chip(), &[0xf0, 0x00, 0xab, 0xcd, 0x60, 0x01]);
+        chip.step().unwrap();
+        assert_eq!((chip.cpu().get_index(), chip.cpu().get_pc()), (0xabcd, 0x204));
+        chip.step().unwrap();
+        assert_eq!(chip.cpu().get_register(0), 1);
+    }
+
+    #[test]
+    fn skips_jump_over_the_whole_f000_instruction(){
+        // 3000 F000 1234 6001: the skip lands on 6001 rather than in the middle of the long load
+        let mut chip = load(Quirks::xo_chip(), &[0x30, 0x00, 0xf0, 0x00, 0x12, 0x34, 0x60, 0x01]);
+        chip.step().unwrap();
+        assert_eq!(chip.cpu().get_pc(), 0x206);
+        chip.step().unwrap();
+        assert_eq!((chip.cpu().get_register(0), chip.cpu().get_index()), (1, 0));
+
+        // other instructions are still 2 bytes to skip, and a skip not taken runs the long load
+        let mut chip = load(Quirks::xo_chip(), &[0x40, 0x00, 0xf0, 0x00, 0x12, 0x34, 0x30, 0x00, 0x60, 0x02, 0x60, 0x03]);
+        chip.run(2).unwrap();
+        assert_eq!((chip.cpu().get_index(), chip.cpu().get_pc()), (0x1234, 0x206));
+        chip.run(2).unwrap();
+        assert_eq!(chip.cpu().get_register(0), 3);
+    }
+
+    #[test]
+    fn planes_select_where_sprites_are_drawn(){
+        // F201 A300 D011 F301 D011 F101 D011: the second sprite of a two plane draw follows the first
+        let mut chip = load(Quirks::xo_chip(), &[0xf2, 0x01, 0xa3, 0x00, 0xd0, 0x11, 0xf3, 0x01, 0xd0, 0x11, 0xf1, 0x01, 0xd0, 0x11]);
+        chip.memory_mut().poke(0x300, 0xc0).unwrap();
+        chip.memory_mut().poke(0x301, 0x60).unwrap();
+        chip.step().unwrap();
+        assert_eq!(chip.screen().get_planes(), 2);
+        chip.run(2).unwrap();
+        assert_eq!(row(&chip, 0, 0, 4), [2, 2, 0, 0]);
+
+        // the first plane gets c0 and the second 60, which collides at x 1
+        chip.run(2).unwrap();
+        assert_eq!(row(&chip, 0, 0, 4), [3, 1, 2, 0]);
+        assert_eq!(chip.cpu().get_register(0xf), 1);
+
+        // back on the first plane only, the second is left alone
+        chip.run(2).unwrap();
+        assert_eq!(row(&chip, 0, 0, 4), [2, 0, 2, 0]);
+        assert_eq!(chip.cpu().get_register(0xf), 1);
+
+        // no plane at all draws nothing and never collides
+        let mut chip = load(Quirks::xo_chip(), &[0xf0, 0x01, 0xa3, 0x00, 0xd0, 0x11]);
+        chip.memory_mut().poke(0x300, 0xff).unwrap();
+        chip.run(3).unwrap();
+        assert_eq!(row(&chip, 0, 0, 8), [0; 8]);
+        assert_eq!(chip.cpu().get_register(0xf), 0);
+    }
+
+    #[test]
+    fn clearing_and_scrolling_only_touch_the_selected_planes(){
+        // F301 A300 D011 F201 00E0: 80 in the first plane and c0 in the second, then the second is cleared
+        let mut chip = load(Quirks::xo_chip(), &[0xf3, 0x01, 0xa3, 0x00, 0xd0, 0x11, 0xf2, 0x01, 0x00, 0xe0]);
+        chip.memory_mut().poke(0x300, 0x80).unwrap();
+        chip.memory_mut().poke(0x301, 0xc0).unwrap();
+        chip.run(3).unwrap();
+        assert_eq!(row(&chip, 0, 0, 3), [3, 2, 0]);
+        chip.run(2).unwrap();
+        assert_eq!(row(&chip, 0, 0, 3), [1, 0, 0]);
+
+        // F301 A300 D011 F101 00FB 00D1: the first plane moves right and then up off the screen, the second stays
+        let mut chip = load(Quirks::xo_chip(), &[0xf3, 0x01, 0xa3, 0x00, 0xd0, 0x11, 0xf1, 0x01, 0x00, 0xfb, 0x00, 0xd1]);
+        chip.memory_mut().poke(0x300, 0x80).unwrap();
+        chip.memory_mut().poke(0x301, 0xc0).unwrap();
+        chip.run(5).unwrap();
+        assert_eq!(row(&chip, 0, 0, 6), [2, 2, 0, 0, 1, 0]);
+        chip.step().unwrap();
+        assert_eq!(row(&chip, 0, 0, 6), [2, 2, 0, 0, 0, 0]);
+        assert_eq!(row(&chip, 0, 31, 6), [0; 6]);
     }
 }
@@ -0,0 +1,547 @@
+use crate::chip::{ Chip, ChipState };
+use crate::chip::error::ChipError;
+use crate::chip::memory::{ Access, Watch };
+
+// Breakpoint conditions are small expressions over the machine state, e.g. "V3 == 0x10 && I > 0x300".
+// Operands are numbers (decimal, or hex with 0x), the registers V0-VF, I, PC, SP, DT and ST,
+// and [expr] for the byte of memory at expr. Comparisons, !, &&, || and parentheses combine them,
+// any non-zero value is true.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operand{ V(usize), I, PC, SP, DT, ST }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operator{ Eq, Ne, Lt, Le, Gt, Ge, And, Or }
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Expression{
+    Number(i64),
+    Register(Operand),
+    Memory(Box<Expression>),
+    Not(Box<Expression>),
+    Binary(Box<Expression>, Operator, Box<Expression>)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Condition{ source: String, expression: Expression }
+
+fn tokenize(source: &str)->Result<Vec<String>, String>{
+    let mut tokens = Vec::new();
+    let chars: Vec<char> = source.chars().collect();
+    let mut i = 0;
+    while i < chars.len(){
+        let c = chars[i];
+        if c.is_whitespace(){
+            i += 1;
+        }else if c.is_ascii_alphanumeric(){
+            let start = i;
+            while i < chars.len() && chars[i].is_ascii_alphanumeric(){ i += 1; }
+            tokens.push(chars[start..i].iter().collect());
+        }else{
+            let pair: String = chars[i..chars.len().min(i + 2)].iter().collect();
+            if ["==", "!=", "<=", ">=", "&&", "||"].contains(&pair.as_str()){
+                tokens.push(pair);
+                i += 2;
+            }else if "<>!()[]".contains(c){
+                tokens.push(c.to_string());
+                i += 1;
+            }else{
+                return Err(format!("unexpected character {}", c));
+            }
+        }
+    }
+    return Ok(tokens);
+}
+
+struct Parser{ tokens: Vec<String>, position: usize }
+
+impl Parser{
+    fn peek(&self)->Option<&str>{ self.tokens.get(self.position).map(| token | token.as_str()) }
+
+    fn next(&mut self)->Result<String, String>{
+        let token = self.tokens.get(self.position).cloned().ok_or_else(|| String::from("unexpected end of condition"))?;
+        self.position += 1;
+        return Ok(token);
+    }
+
+    fn expect(&mut self, token: &str)->Result<(), String>{
+        let next = self.next()?;
+        if next != token{
+            return Err(format!("expected {}, got {}", token, next));
+        }
+        Ok(())
+    }
+
+    fn or(&mut self)->Result<Expression, String>{
+        let mut left = self.and()?;
+        while self.peek() == Some("||"){
+            self.position += 1;
+            left = Expression::Binary(Box::new(left), Operator::Or, Box::new(self.and()?));
+        }
+        return Ok(left);
+    }
+
+    fn and(&mut self)->Result<Expression, String>{
+        let mut left = self.comparison()?;
+        while self.peek() == Some("&&"){
+            self.position += 1;
+            left = Expression::Binary(Box::new(left), Operator::And, Box::new(self.comparison()?));
+        }
+        return Ok(left);
+    }
+
+    fn comparison(&mut self)->Result<Expression, String>{
+        let left = self.unary()?;
+        let operator = match self.peek(){
+            Some("==") => Operator::Eq,
+            Some("!=") => Operator::Ne,
+            Some("<") => Operator::Lt,
+            Some("<=") => Operator::Le,
+            Some(">") => Operator::Gt,
+            Some(">=") => Operator::Ge,
+            _ => return Ok(left)
+        };
+        self.position += 1;
+        return Ok(Expression::Binary(Box::new(left), operator, Box::new(self.unary()?)));
+    }
+
+    fn unary(&mut self)->Result<Expression, String>{
+        let token = self.next()?;
+        return match token.as_str(){
+            "!" => Ok(Expression::Not(Box::new(self.unary()?))),
+            "(" =>{
+                let expression = self.or()?;
+                self.expect(")")?;
+                Ok(expression)
+            },
+            "[" =>{
+                let expression = self.or()?;
+                self.expect("]")?;
+                Ok(Expression::Memory(Box::new(expression)))
+            },
+            _ => Parser::operand(&token)
+        };
+    }
+
+    fn operand(token: &str)->Result<Expression, String>{
+        let upper = token.to_uppercase();
+        let register = match upper.as_str(){
+            "I" => Some(Operand::I),
+            "PC" => Some(Operand::PC),
+            "SP" => Some(Operand::SP),
+            "DT" => Some(Operand::DT),
+            "ST" => Some(Operand::ST),
+            _ if upper.len() == 2 && upper.starts_with('V') => usize::from_str_radix(&upper[1..], 16).ok().map(Operand::V),
+            _ => None
+        };
+        if let Some(register) = register{
+            return Ok(Expression::Register(register));
+        }
+
+        let number = match upper.strip_prefix("0X"){
+            Some(hex) => i64::from_str_radix(hex, 16),
+            None => upper.parse::<i64>()
+        };
+        return number.map(Expression::Number).map_err(|_| format!("unknown operand {}", token));
+    }
+}
+
+impl Condition{
+    pub fn parse(source: &str)->Result<Self, String>{
+        let mut parser = Parser{ tokens: tokenize(source)?, position: 0 };
+        let expression = parser.or()?;
+        if let Some(token) = parser.peek(){
+            return Err(format!("unexpected {}", token));
+        }
+        return Ok(Condition{ source: source.to_string(), expression });
+    }
+
+    pub fn source(&self)->&str{ &self.source }
+
+    pub fn eval(&self, chip: &Chip)->bool{ Condition::value(&self.expression, chip) != 0 }
+
+    fn value(expression: &Expression, chip: &Chip)->i64{
+        return match expression{
+            Expression::Number(number) => *number,
+            Expression::Register(register) =>{
+                let cpu = chip.cpu();
+                (match register{
+                    Operand::V(x) => cpu.get_register(*x) as u16,
+                    Operand::I => cpu.get_index(),
+                    Operand::PC => cpu.get_pc(),
+                    Operand::SP => cpu.get_stack().len() as u16,
+                    Operand::DT => cpu.timers().get_timer() as u16,
+                    Operand::ST => cpu.timers().get_sound() as u16
+                }) as i64
+            },
+            Expression::Memory(address) =>{
+                let address = Condition::value(address, chip);
+                if address < 0 { 0 } else { chip.memory().peek(address as usize).unwrap_or(0) as i64 }
+            },
+            Expression::Not(inner) => (Condition::value(inner, chip) == 0) as i64,
+            Expression::Binary(left, operator, right) =>{
+                let left = Condition::value(left, chip);
+                // && and || short circuit like their Rust counterparts
+                match operator{
+                    Operator::And if left == 0 => return 0,
+                    Operator::Or if left != 0 => return 1,
+                    _ => {}
+                }
+                let right = Condition::value(right, chip);
+                (match operator{
+                    Operator::Eq => left == right,
+                    Operator::Ne => left != right,
+                    Operator::Lt => left < right,
+                    Operator::Le => left <= right,
+                    Operator::Gt => left > right,
+                    Operator::Ge => left >= right,
+                    Operator::And | Operator::Or => right != 0
+                }) as i64
+            }
+        };
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Breakpoint{ pub address: u16, pub condition: Option<Condition>, pub enabled: bool }
+
+// why the debugger last stopped the chip
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hit{
+    Breakpoint(u16),
+    Watchpoint{ address: usize, access: Access, pc: u16 },
+    Step(u16)       // a step, step over, step out or run to cursor finished at this address
+}
+
+// where a step over, step out or run to cursor stops
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Until{
+    Address{ address: u16, depth: Option<usize> },  // reaching address with at most depth calls on the stack
+    Return{ depth: usize }                          // returning below depth calls
+}
+
+// Runs the chip one instruction at a time, stopping it in the BreakpointHit state on a breakpoint,
+// a watched memory access or at the end of a step. Resuming the chip carries on from there.
+// The scheduler drives it in place of Chip::frame, frontends call the stepping commands directly.
+pub struct Debugger{
+    breakpoints: Vec<Breakpoint>, watchpoints: Vec<Watch>,
+    until: Option<Until>, stopped_at: Option<u16>, hit: Option<Hit>
+}
+
+impl Debugger{
+    pub fn new()->Self{
+        Debugger{ breakpoints: Vec::new(), watchpoints: Vec::new(), until: None, stopped_at: None, hit: None }
+    }
+
+    pub fn breakpoints(&self)->&[Breakpoint]{ &self.breakpoints }
+
+    // replaces any breakpoint already set at the address
+    pub fn add_breakpoint(&mut self, address: u16){
+        self.remove_breakpoint(address);
+        self.breakpoints.push(Breakpoint{ address, condition: None, enabled: true });
+    }
+
+    pub fn add_conditional_breakpoint(&mut self, address: u16, condition: &str)->Result<(), String>{
+        let condition = Condition::parse(condition)?;
+        self.remove_breakpoint(address);
+        self.breakpoints.push(Breakpoint{ address, condition: Some(condition), enabled: true });
+        Ok(())
+    }
+
+    pub fn remove_breakpoint(&mut self, address: u16){
+        self.breakpoints.retain(| breakpoint | breakpoint.address != address);
+    }
+
+    pub fn toggle_breakpoint(&mut self, address: u16){
+        if self.breakpoints.iter().any(| breakpoint | breakpoint.address == address){
+            self.remove_breakpoint(address);
+        }else{
+            self.add_breakpoint(address);
+        }
+    }
+
+    pub fn set_enabled(&mut self, address: u16, enabled: bool){
+        for breakpoint in self.breakpoints.iter_mut().filter(| breakpoint | breakpoint.address == address){
+            breakpoint.enabled = enabled;
+        }
+    }
+
+    pub fn watchpoints(&self)->&[Watch]{ &self.watchpoints }
+
+    // watches length bytes from start
+    pub fn add_watchpoint(&mut self, start: usize, length: usize, read: bool, write: bool){
+        self.watchpoints.push(Watch{ start, end: start + length.max(1), read, write });
+    }
+
+    pub fn remove_watchpoint(&mut self, start: usize){
+        self.watchpoints.retain(| watch | watch.start != start);
+    }
+
+    pub fn clear(&mut self){
+        self.breakpoints.clear();
+        self.watchpoints.clear();
+        self.until = None;
+    }
+
+    pub fn last_hit(&self)->Option<Hit>{ self.hit }
+
+    fn stop(&mut self, chip: &mut Chip, hit: Hit){
+        let pc = chip.cpu().get_pc();
+        self.hit = Some(hit);
+        self.until = None;
+        self.stopped_at = Some(pc);
+        chip.break_at(pc);
+    }
+
+    // checked before the instruction at pc runs
+    fn check_before(&mut self, chip: &Chip)->Option<Hit>{
+        let pc = chip.cpu().get_pc();
+
+        // resuming at a breakpoint must run the instruction it stopped on
+        if self.stopped_at.take() == Some(pc){
+            return None;
+        }
+        if let Some(Until::Address{ address, depth }) = self.until{
+            if pc == address && depth.is_none_or(| depth | chip.cpu().get_stack().len() <= depth){
+                return Some(Hit::Step(pc));
+            }
+        }
+        let hit = self.breakpoints.iter().any(| breakpoint |{
+            breakpoint.enabled && breakpoint.address == pc && breakpoint.condition.as_ref().is_none_or(| condition | condition.eval(chip))
+        });
+        return if hit { Some(Hit::Breakpoint(pc)) } else { None };
+    }
+
+    // checked once the instruction ran, pc is the instruction it ran
+    fn check_after(&mut self, chip: &Chip, pc: u16)->Option<Hit>{
+        if let Some((address, access)) = chip.memory().take_hit(){
+            return Some(Hit::Watchpoint{ address, access, pc });
+        }
+        if let Some(Until::Return{ depth }) = self.until{
+            if chip.cpu().get_stack().len() < depth{
+                return Some(Hit::Step(chip.cpu().get_pc()));
+            }
+        }
+        return None;
+    }
+
+    // runs up to count instructions, stopping early on a hit or once the chip no longer runs
+    pub fn run(&mut self, chip: &mut Chip, count: usize)->Result<(), ChipError>{
+        chip.memory_mut().set_watches(&self.watchpoints);
+        for _ in 0..count{
+            if !chip.is_running(){
+                break;
+            }
+            // waiting for a key runs no instruction, breakpoints would stop on the same one over and over
+            if chip.is_waiting(){
+                chip.step()?;
+                continue;
+            }
+            if let Some(hit) = self.check_before(chip){
+                self.stop(chip, hit);
+                break;
+            }
+            let pc = chip.cpu().get_pc();
+            chip.step()?;
+            if let Some(hit) = self.check_after(chip, pc){
+                self.stop(chip, hit);
+                break;
+            }
+        }
+        return Ok(());
+    }
+
+    // Chip::frame with the checks between instructions
+    pub fn frame(&mut self, chip: &mut Chip, instructions: usize)->Result<(), ChipError>{
+        if !chip.is_running(){
+            return Ok(());
+        }
+        self.run(chip, instructions)?;
+        chip.end_frame();
+        return Ok(());
+    }
+
+    // runs the next instruction and leaves the chip paused
+    pub fn step(&mut self, chip: &mut Chip)->Result<(), ChipError>{
+        chip.memory_mut().set_watches(&self.watchpoints);
+        let pc = chip.cpu().get_pc();
+        self.until = None;
+        let result = chip.step();
+        self.hit = Some(self.check_after(chip, pc).unwrap_or(Hit::Step(chip.cpu().get_pc())));
+        chip.pause();
+        return result;
+    }
+
+    // steps over a CALL by running until it returns, any other instruction is a single step
+    pub fn step_over(&mut self, chip: &mut Chip)->Result<(), ChipError>{
+        let pc = chip.cpu().get_pc();
+        let opcode = chip.memory().peek(pc as usize).unwrap_or(0);
+        if opcode & 0xf0 != 0x20{
+            return self.step(chip);
+        }
+        self.until = Some(Until::Address{ address: pc.wrapping_add(2), depth: Some(chip.cpu().get_stack().len()) });
+        self.resume(chip);
+        Ok(())
+    }
+
+    // runs until the current subroutine returns to its caller
+    pub fn step_out(&mut self, chip: &mut Chip)->Result<(), ChipError>{
+        let depth = chip.cpu().get_stack().len();
+        if depth == 0{
+            return self.step(chip);
+        }
+        self.until = Some(Until::Return{ depth });
+        self.resume(chip);
+        Ok(())
+    }
+
+    pub fn run_to(&mut self, chip: &mut Chip, address: u16){
+        self.until = Some(Until::Address{ address, depth: None });
+        self.resume(chip);
+    }
+
+    // carries on from a stop, without stopping again on the breakpoint at pc
+    pub fn resume(&mut self, chip: &mut Chip){
+        if chip.state() != ChipState::Running{
+            self.stopped_at = Some(chip.cpu().get_pc());
+        }
+        chip.resume();
+    }
+}
+
+impl Default for Debugger{
+    fn default()->Self{ Debugger::new() }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    //  200  LD V0, 5       20a  LD V1, 7
+    //  202  CALL 20a       20c  LD I, 300
+    //  204  ADD V0, 1      20e  LD [I], V1
+    //  206  JP 206         210  RET
+    const ROM: [u8; 18] = [0x60, 0x05, 0x22, 0x0a, 0x70, 0x01, 0x12, 0x06, 0x00, 0x00, 0x61, 0x07, 0xa3, 0x00, 0xf1, 0x55, 0x00, 0xee];
+
+    fn chip(rom: &[u8])->Chip{
+        let mut chip = Chip::new();
+        chip.load_rom(rom).unwrap();
+        return chip;
+    }
+
+    fn eval(source: &str, chip: &Chip)->bool{ Condition::parse(source).unwrap().eval(chip) }
+
+    #[test]
+    fn bad_conditions_are_errors(){
+        assert_eq!(Condition::parse(""), Err(String::from("unexpected end of condition")));
+        assert_eq!(Condition::parse("V3 =="), Err(String::from("unexpected end of condition")));
+        assert_eq!(Condition::parse("(V0 == 1"), Err(String::from("unexpected end of condition")));
+        assert_eq!(Condition::parse("[I == 1)"), Err(String::from("expected ], got )")));
+        assert_eq!(Condition::parse("V3 $ 1"), Err(String::from("unexpected character $")));
+        assert_eq!(Condition::parse("VG == 1"), Err(String::from("unknown operand VG")));
+        assert_eq!(Condition::parse("V0 V1"), Err(String::from("unexpected V1")));
+        // comparisons do not chain
+        assert_eq!(Condition::parse("1 < 2 == 1"), Err(String::from("unexpected ==")));
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or_and_not_tightest(){
+        let chip = chip(&ROM);
+        assert!(eval("1 || 0 && 0", &chip));
+        assert!(!eval("(1 || 0) && 0", &chip));
+        assert!(eval("!0 == 1", &chip));
+        assert!(!eval("!(0 == 0)", &chip));
+        assert!(eval("0 && [0x10000] || 1", &chip));
+    }
+
+    #[test]
+    fn conditions_read_registers_and_memory(){
+        let mut chip = chip(&ROM);
+        chip.cpu_mut().set_register(3, 0x10);
+        chip.cpu_mut().set_index(0x300);
+        chip.memory_mut().poke(0x300, 0x42).unwrap();
+        chip.cpu_mut().timers_mut().set_timer(9);
+        assert!(eval("V3 == 0x10 && I > 0x2ff", &chip));
+        assert!(eval("v3 == 16 && vf == 0", &chip));
+        assert!(eval("[I] == 0x42 && [0x300] == 66 && [V3] == [0x10]", &chip));
+        assert!(eval("PC == 0x200 && SP == 0 && DT == 9 && ST == 0", &chip));
+        assert!(!eval("V3 != 0x10", &chip));
+    }
+
+    #[test]
+    fn conditional_breakpoints_stop_only_when_they_hold(){
+        // ADD V0, 1 then JP 200
+        let mut chip = chip(&[0x70, 0x01, 0x12, 0x00]);
+        let mut debugger = Debugger::new();
+        debugger.add_conditional_breakpoint(0x200, "V0 == 3").unwrap();
+        debugger.run(&mut chip, 100).unwrap();
+        assert_eq!(debugger.last_hit(), Some(Hit::Breakpoint(0x200)));
+        assert_eq!(chip.state(), ChipState::BreakpointHit(0x200));
+        assert_eq!(chip.cpu().get_register(0), 3);
+
+        // resuming runs the instruction the breakpoint stopped on
+        debugger.resume(&mut chip);
+        debugger.run(&mut chip, 10).unwrap();
+        assert!(chip.is_running());
+        assert_eq!(chip.cpu().get_register(0), 8);
+
+        debugger.add_breakpoint(0x202);
+        debugger.set_enabled(0x202, false);
+        debugger.run(&mut chip, 10).unwrap();
+        assert!(chip.is_running());
+        assert!(debugger.add_conditional_breakpoint(0x200, "V0 ==").is_err());
+    }
+
+    #[test]
+    fn watchpoints_stop_after_the_access(){
+        let mut chip = chip(&ROM);
+        let mut debugger = Debugger::new();
+        debugger.add_watchpoint(0x301, 1, false, true);
+        debugger.run(&mut chip, 100).unwrap();
+        assert_eq!(debugger.last_hit(), Some(Hit::Watchpoint{ address: 0x301, access: Access::Write, pc: 0x20e }));
+        assert_eq!(chip.cpu().get_pc(), 0x210);
+
+        // LD I, 200 then LD V0, [I] reads the rom
+        let mut chip = self::chip(&[0xa2, 0x00, 0xf0, 0x65, 0x12, 0x04]);
+        let mut debugger = Debugger::new();
+        debugger.add_watchpoint(0x200, 2, true, false);
+        debugger.run(&mut chip, 100).unwrap();
+        assert_eq!(debugger.last_hit(), Some(Hit::Watchpoint{ address: 0x200, access: Access::Read, pc: 0x202 }));
+        assert_eq!(chip.cpu().get_register(0), 0xa2);
+    }
+
+    #[test]
+    fn step_over_runs_the_whole_call(){
+        let mut chip = chip(&ROM);
+        let mut debugger = Debugger::new();
+        debugger.step(&mut chip).unwrap();
+        assert_eq!(debugger.last_hit(), Some(Hit::Step(0x202)));
+        assert!(chip.is_paused());
+
+        debugger.step_over(&mut chip).unwrap();
+        debugger.run(&mut chip, 100).unwrap();
+        assert_eq!(debugger.last_hit(), Some(Hit::Step(0x204)));
+        assert_eq!(chip.cpu().get_stack(), []);
+        assert_eq!(chip.cpu().get_register(1), 7);
+
+        // anything else is a single step
+        debugger.step_over(&mut chip).unwrap();
+        assert_eq!(debugger.last_hit(), Some(Hit::Step(0x206)));
+        assert_eq!(chip.cpu().get_register(0), 6);
+    }
+
+    #[test]
+    fn run_to_and_step_out(){
+        let mut chip = chip(&ROM);
+        let mut debugger = Debugger::new();
+        debugger.run_to(&mut chip, 0x20c);
+        debugger.run(&mut chip, 100).unwrap();
+        assert_eq!(debugger.last_hit(), Some(Hit::Step(0x20c)));
+        assert_eq!(chip.cpu().get_stack(), [0x204]);
+
+        debugger.step_out(&mut chip).unwrap();
+        debugger.run(&mut chip, 100).unwrap();
+        assert_eq!(debugger.last_hit(), Some(Hit::Step(0x204)));
+        assert_eq!(chip.cpu().get_stack(), []);
+        assert_eq!(chip.cpu().get_register(0), 5);
+    }
+}
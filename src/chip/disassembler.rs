@@ -0,0 +1,234 @@
+use std::collections::{ BTreeMap, BTreeSet };
+
+use crate::chip::Opcode;
+
+const START: usize = 0x200;
+// data bytes written on a line
+const ROW: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Byte{ Unknown, Code, Operand }  // code is the first byte of an instruction, operand the ones after it
+
+// how an instruction passes control on, as the cpu runs it
+enum Flow{ Next, Stop, Jump(usize), Call(usize), Skip, Index(usize), Long }
+
+// Disassembles a whole rom by following the control flow from 0x200 through jumps, calls and skips.
+// Bytes never reached are data, and addresses loaded into I name the sprites. Every jump, call and
+// sprite target gets a label, and the output is source the Assemblier turns back into the same rom:
+// words that are no instruction, or whose text would assemble differently, are written as data.
+pub struct Disassembler{ rom: Vec<u8>, bytes: Vec<Byte>, calls: BTreeSet<usize>, jumps: BTreeSet<usize>, sprites: BTreeSet<usize>, labels: BTreeMap<usize, String> }
+
+impl Disassembler{
+    pub fn new()->Self{
+        Disassembler{ rom: Vec::new(), bytes: Vec::new(), calls: BTreeSet::new(), jumps: BTreeSet::new(), sprites: BTreeSet::new(), labels: BTreeMap::new() }
+    }
+
+    // traces the rom, finding the code and naming the labels
+    pub fn init(&mut self, rom: &[u8]){
+        self.rom = rom.to_vec();
+        self.bytes = vec![Byte::Unknown; rom.len()];
+        self.calls.clear();
+        self.jumps.clear();
+        self.sprites.clear();
+
+        let mut pending = vec![START];
+        while let Some(address) = pending.pop(){
+            let code = match self.word(address){
+                Some(code) => code,
+                None => continue
+            };
+            let offset = address - START;
+            let size = if code == 0xf000 { 4 } else { 2 };
+            // code already traced, an instruction overlapping another one, or running off the rom
+            if !valid(code) || offset + size > self.rom.len() || self.bytes[offset..offset + size].iter().any(| byte | *byte != Byte::Unknown){
+                continue;
+            }
+            self.bytes[offset] = Byte::Code;
+            for byte in self.bytes[offset + 1..offset + size].iter_mut(){
+                *byte = Byte::Operand;
+            }
+
+            let next = address + size;
+            match flow(code){
+                Flow::Next => pending.push(next),
+                Flow::Stop => {},
+                Flow::Jump(target) =>{
+                    self.jumps.insert(target);
+                    pending.push(target);
+                },
+                Flow::Call(target) =>{
+                    self.calls.insert(target);
+                    pending.push(target);
+                    pending.push(next);
+                },
+                // a skipped F000 is skipped whole
+                Flow::Skip =>{
+                    pending.push(next);
+                    pending.push(next + if self.word(next) == Some(0xf000) { 4 } else { 2 });
+                },
+                Flow::Index(target) =>{
+                    self.sprites.insert(target);
+                    pending.push(next);
+                },
+                Flow::Long =>{
+                    if let Some(target) = self.word(address + 2){
+                        self.sprites.insert(target as usize);
+                    }
+                    pending.push(next);
+                }
+            }
+        }
+        self.name();
+    }
+
+    // labels are given by what points at an address, the start of every other block is named after its contents
+    fn name(&mut self){
+        self.labels.clear();
+        self.labels.insert(START, String::from("start"));
+        let targets = [(&self.calls, "sub"), (&self.jumps, "label"), (&self.sprites, "sprite")];
+        for (addresses, prefix) in targets.iter(){
+            for address in addresses.iter(){
+                if self.labelable(*address){
+                    self.labels.entry(*address).or_insert_with(|| format!("{}_{:03X}", prefix, address));
+                }
+            }
+        }
+        for offset in 1..self.bytes.len(){
+            let (previous, byte) = (self.bytes[offset - 1], self.bytes[offset]);
+            let code = byte == Byte::Code && previous == Byte::Unknown;
+            let data = byte == Byte::Unknown && previous != Byte::Unknown;
+            if code || data{
+                let address = START + offset;
+                self.labels.entry(address).or_insert_with(|| format!("{}_{:03X}", if code { "code" } else { "data" }, address));
+            }
+        }
+    }
+
+    // the assembler only knows labels at the start of a block, which cannot be inside an instruction
+    fn labelable(&self, address: usize)->bool{
+        return address >= START && address < START + self.rom.len() && self.bytes[address - START] != Byte::Operand;
+    }
+
+    // the labels found by init, in address order
+    pub fn labels(&self)->Vec<(String, u16)>{
+        return self.labels.iter().map(| (address, name) | (name.clone(), *address as u16)).collect();
+    }
+
+    // the address and word of every instruction reached, in address order
+    pub fn instructions(&self)->Vec<(u16, u16)>{
+        return self.bytes.iter().enumerate().filter(| (_, byte) | **byte == Byte::Code)
+            .filter_map(| (offset, _) | self.word(START + offset).map(| code | ((START + offset) as u16, code))).collect();
+    }
+
+    // bytes of the rom taken by instructions, the rest is data
+    pub fn code_size(&self)->usize{
+        return self.bytes.iter().filter(| byte | **byte != Byte::Unknown).count();
+    }
+
+    // a block for each label, as commands when it starts with code and as a sprite otherwise
+    pub fn run(&self)->String{
+        let mut source = String::new();
+        let mut offset = 0;
+        while offset < self.rom.len(){
+            let address = START + offset;
+            let name = &self.labels[&address];
+            let code = self.bytes[offset] == Byte::Code;
+            if address == START{
+                source.push_str("start:\n");
+            }else{
+                source.push_str(&format!("\n{}.{}:\n", name, if code { "commands" } else { "sprite" }));
+            }
+
+            let end = self.labels.range(address + 1..).next().map_or(self.rom.len(), | (next, _) | next - START);
+            while offset < end{
+                if self.bytes[offset] == Byte::Code{
+                    let size = if self.word(START + offset) == Some(0xf000) { 4 } else { 2 };
+                    source.push_str(&format!("    {:<24}// {:03X}\n", self.instruction(START + offset), START + offset));
+                    offset += size;
+                }else{
+                    // data runs until the next instruction or the end of the block
+                    let mut row = Vec::new();
+                    while offset < end && self.bytes[offset] != Byte::Code && row.len() < ROW{
+                        row.push(format!("0x{:02X}", self.rom[offset]));
+                        offset += 1;
+                    }
+                    source.push_str(&format!("    {} ;\n", row.join(" ")));
+                }
+            }
+        }
+        return source;
+    }
+
+    fn word(&self, address: usize)->Option<u16>{
+        if address < START || address + 1 >= START + self.rom.len(){
+            return None;
+        }
+        return Some((self.rom[address - START] as u16) << 8 | self.rom[address - START + 1] as u16);
+    }
+
+    fn instruction(&self, address: usize)->String{
+        let code = self.word(address).unwrap_or(0);
+        let opcode = Opcode::new(code);
+        let target = self.labels.get(&(opcode.nnn() as usize));
+        return match (code & 0xf000, target){
+            (0x1000, Some(label)) => format!("JP {}", label),
+            (0x2000, Some(label)) => format!("CALL {}", label),
+            (0xb000, Some(label)) => format!("JP V0, {}", label),
+            (0xa000, Some(label)) => format!("LD I, {}", label),
+            (0x1000 | 0x2000 | 0xb000, None) => data(&[(code >> 8) as u8, code as u8]),
+            _ if code == 0xf000 =>{
+                let long = self.word(address + 2).unwrap_or(0);
+                match self.labels.get(&(long as usize)){
+                    Some(label) => format!("LD I, LONG {}", label),
+                    None => format!("LD I, LONG 0x{:04X}", long)
+                }
+            },
+            _ if canonical(code) => opcode.dessemble(),
+            _ => data(&[(code >> 8) as u8, code as u8])
+        };
+    }
+}
+
+impl Default for Disassembler{
+    fn default()->Self{ Disassembler::new() }
+}
+
+fn data(bytes: &[u8])->String{
+    return format!("{} ;", bytes.iter().map(| byte | format!("0x{:02X}", byte)).collect::<Vec<_>>().join(" "));
+}
+
+// the words the cpu runs, decoded as loosely as it does
+fn valid(code: u16)->bool{
+    return match code & 0xf000{
+        0x0000 => matches!(code & 0x00ff, 0xe0 | 0xee | 0xfb..=0xff) || matches!(code & 0x00f0, 0xc0 | 0xd0),
+        0x5000 => matches!(code & 0x000f, 0x0 | 0x2 | 0x3),
+        0x8000 => matches!(code & 0x000f, 0x0..=0x7 | 0xe),
+        0xe000 => matches!(code & 0x000f, 0xe | 0x1),
+        0xf000 => matches!(code & 0x00ff, 0x01 | 0x07 | 0x0a | 0x15 | 0x18 | 0x1e | 0x29 | 0x30 | 0x33 | 0x3a | 0x55 | 0x65 | 0x75 | 0x85) || code == 0xf000 || code == 0xf002,
+        _ => true
+    };
+}
+
+// whether the text of dessemble assembles back to the very same word
+fn canonical(code: u16)->bool{
+    return valid(code) && match code & 0xf000{
+        0x0000 => code & 0x0f00 == 0,
+        0x9000 => code & 0x000f == 0,
+        0xe000 => matches!(code & 0x00ff, 0x9e | 0xa1),
+        _ => true
+    };
+}
+
+fn flow(code: u16)->Flow{
+    let target = (code & 0x0fff) as usize;
+    return match code & 0xf000{
+        0x0000 if code & 0x00ff == 0xee || code & 0x00ff == 0xfd => Flow::Stop,
+        // the jump table of a JP V0 starts at its address
+        0x1000 | 0xb000 => Flow::Jump(target),
+        0x2000 => Flow::Call(target),
+        0x3000 | 0x4000 | 0x5000 | 0x9000 | 0xe000 if code & 0xf00f != 0x5002 && code & 0xf00f != 0x5003 => Flow::Skip,
+        0xa000 => Flow::Index(target),
+        0xf000 if code == 0xf000 => Flow::Long,
+        _ => Flow::Next
+    };
+}
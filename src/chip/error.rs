@@ -0,0 +1,69 @@
+use std::fmt::{ Display, Formatter };
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChipError{
+    StackUnderflow,
+    StackOverflow,
+    MemoryOutOfBounds{ addr: usize },
+    InvalidOpcode{ pc: u16, opcode: u16 },
+    RomTooLarge,
+    InvalidState(String),
+    RomMismatch,
+    Desync{ frame: usize },
+    InvalidTimeline{ line: usize, message: String },
+    InvalidPreset{ line: usize, message: String },
+    InvalidKeyMap{ line: usize, message: String },
+    InvalidPalette(String),
+    InvalidSource(String),
+    Io(String)
+}
+
+impl Display for ChipError{
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result{
+        match self{
+            ChipError::StackUnderflow => write!(f, "return with an empty stack"),
+            ChipError::StackOverflow => write!(f, "call with a full stack"),
+            ChipError::MemoryOutOfBounds{ addr } => write!(f, "memory access out of bounds at {:#06x}", addr),
+            ChipError::InvalidOpcode{ pc, opcode } => write!(f, "unknown or invalid opcode {:04X} at {:#05x}", opcode, pc),
+            ChipError::RomTooLarge => write!(f, "rom does not fit in memory"),
+            ChipError::InvalidState(message) => write!(f, "invalid save state: {}", message),
+            ChipError::RomMismatch => write!(f, "save state was made with a different rom"),
+            ChipError::Desync{ frame } => write!(f, "movie desynced at frame {}", frame),
+            ChipError::InvalidTimeline{ line, message } => write!(f, "key timeline line {}: {}", line, message),
+            ChipError::InvalidPreset{ line, message } => write!(f, "shader preset line {}: {}", line, message),
+            ChipError::InvalidKeyMap{ line, message } => write!(f, "key map line {}: {}", line, message),
+            ChipError::InvalidPalette(message) => write!(f, "invalid palette: {}", message),
+            ChipError::InvalidSource(message) => write!(f, "cannot assemble: {}", message),
+            ChipError::Io(message) => write!(f, "{}", message)
+        }
+    }
+}
+
+impl std::error::Error for ChipError{}
+
+impl From<std::io::Error> for ChipError{
+    fn from(error: std::io::Error)->Self{ ChipError::Io(error.to_string()) }
+}
+
+// what to do when a fault happens: stop with the error, carry on as if the instruction did nothing,
+// or wrap the stack pointer/address around. Wrapping does not apply to invalid opcodes, which are skipped instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FaultAction{ Halt, Ignore, Wrap }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FaultPolicy{
+    pub stack_underflow: FaultAction,
+    pub stack_overflow: FaultAction,
+    pub memory_out_of_bounds: FaultAction,
+    pub invalid_opcode: FaultAction
+}
+
+impl FaultPolicy{
+    pub fn all(action: FaultAction)->Self{
+        FaultPolicy{ stack_underflow: action, stack_overflow: action, memory_out_of_bounds: action, invalid_opcode: action }
+    }
+}
+
+impl Default for FaultPolicy{
+    fn default()->Self{ FaultPolicy::all(FaultAction::Halt) }
+}
@@ -1,10 +0,0 @@
-ui.heading("My egui Application");
-ui.horizontal(|ui| {
-    ui.label("Your name: ");
-    ui.text_edit_singleline(&mut name);
-});
-ui.add(egui::Slider::new(&mut age, 0..=120).text("age"));
-if ui.button("Click each year").clicked() {
-    age += 1;
-}
-ui.label(format!("Hello '{}', age {}", name, age));
\ No newline at end of file
@@ -0,0 +1,183 @@
+use crate::chip::Chip;
+use crate::chip::utils::fontset;
+
+use egui::{ Color32, RichText, Sense, TextStyle };
+
+const COLUMNS: usize = 16;
+// number of draws a freshly written byte takes to fade out
+const FLASH: u8 = 30;
+// bytes highlighted from I, enough for the tallest sprite
+const UNDER_I: usize = 16;
+
+const FONT: Color32 = Color32::from_rgb(120, 170, 255);
+const OUTSIDE: Color32 = Color32::from_rgb(110, 110, 110);
+const PC: Color32 = Color32::from_rgb(130, 110, 0);
+const INDEX: Color32 = Color32::from_rgb(0, 80, 120);
+
+// A hex and ascii view of the whole memory. The font and rom are coloured, the bytes at pc and under I
+// highlighted and the bytes saved to lately flash. A selected byte can be edited while the chip is stopped.
+pub struct MemoryView{
+    flash: Vec<u8>,
+    selected: Option<usize>, value: String,
+    address: String, scroll: Option<usize>, follow: bool,
+    message: Option<String>
+}
+
+impl MemoryView{
+    pub fn new()->Self{
+        MemoryView{ flash: Vec::new(), selected: None, value: String::new(), address: String::new(), scroll: None, follow: false, message: None }
+    }
+
+    pub fn show(&mut self, context: &egui::Context, chip: &mut Chip){
+        self.update(chip);
+        egui::Window::new("Memory").default_pos([10.0, 10.0]).show(context, | ui |{
+            ui.horizontal(| ui |{
+                ui.label("Go to");
+                let goto = ui.add(egui::TextEdit::singleline(&mut self.address).desired_width(80.0));
+                if (goto.lost_focus() && ui.input().key_pressed(egui::Key::Enter)) || ui.button("Go").clicked(){
+                    match resolve(chip, &self.address){
+                        Some(address) =>{
+                            self.select(chip, address);
+                            self.scroll = Some(address);
+                            self.message = None;
+                        },
+                        None => self.message = Some(format!("no address or label {}", self.address.trim()))
+                    }
+                }
+                ui.checkbox(&mut self.follow, "Follow I");
+            });
+            self.editor(ui, chip);
+            if let Some(message) = &self.message{
+                ui.colored_label(Color32::RED, message);
+            }
+            ui.separator();
+            self.rows(ui, chip);
+        });
+    }
+
+    // keeps the flash of every byte saved to since the last frame at its brightest, the others fade
+    fn update(&mut self, chip: &Chip){
+        let memory = chip.memory();
+        self.flash.resize(memory.len(), 0);
+        for (address, flash) in self.flash.iter_mut().enumerate(){
+            *flash = if memory.was_written(address) { FLASH } else { flash.saturating_sub(1) };
+        }
+        if self.follow{
+            self.scroll = Some(chip.cpu().get_index() as usize);
+        }
+    }
+
+    fn select(&mut self, chip: &Chip, address: usize){
+        self.selected = Some(address);
+        self.value = format!("{:02X}", chip.memory().peek(address).unwrap_or(0));
+    }
+
+    // editing a running chip would race the program, so the value is only taken while it is stopped
+    fn editor(&mut self, ui: &mut egui::Ui, chip: &mut Chip){
+        let address = match self.selected{
+            Some(address) => address,
+            None => return
+        };
+        ui.horizontal(| ui |{
+            ui.monospace(format!("{:04X}", address));
+            let stopped = !chip.is_running();
+            let edit = ui.add_enabled(stopped, egui::TextEdit::singleline(&mut self.value).desired_width(30.0));
+            if edit.lost_focus() && ui.input().key_pressed(egui::Key::Enter){
+                match u8::from_str_radix(self.value.trim(), 16){
+                    Ok(value) =>{
+                        if let Err(error) = chip.memory_mut().poke(address, value){
+                            self.message = Some(error.to_string());
+                        }
+                    },
+                    Err(_) => self.message = Some(format!("{} is not a hex byte", self.value.trim()))
+                }
+            }
+            if !stopped{
+                ui.label("pause to edit");
+            }
+        });
+    }
+
+    fn rows(&mut self, ui: &mut egui::Ui, chip: &Chip){
+        let row_height = ui.text_style_height(&TextStyle::Monospace);
+        let total = chip.memory().len().div_ceil(COLUMNS);
+        let mut area = egui::ScrollArea::vertical().auto_shrink([false; 2]);
+        if let Some(address) = self.scroll.take(){
+            // a couple of rows are left above the target for context
+            let row = (address / COLUMNS).saturating_sub(2);
+            area = area.vertical_scroll_offset(row as f32 * (row_height + ui.spacing().item_spacing.y));
+        }
+        area.show_rows(ui, row_height, total, | ui, range |{
+            for row in range{
+                ui.horizontal(| ui |{
+                    ui.spacing_mut().item_spacing.x = 4.0;
+                    self.row(ui, chip, row * COLUMNS);
+                });
+            }
+        });
+    }
+
+    fn row(&mut self, ui: &mut egui::Ui, chip: &Chip, start: usize){
+        let memory = chip.memory();
+        let end = (start + COLUMNS).min(memory.len());
+        ui.monospace(RichText::new(format!("{:04X}", start)).color(OUTSIDE));
+        let mut ascii = String::new();
+        for address in start..end{
+            let value = memory.peek(address).unwrap_or(0);
+            ascii.push(if value.is_ascii_graphic() || value == b' ' { value as char } else { '.' });
+            let mut text = RichText::new(format!("{:02X}", value)).monospace().color(self.color(chip, address));
+            if let Some(background) = self.background(chip, address){
+                text = text.background_color(background);
+            }
+            if ui.add(egui::Label::new(text).sense(Sense::click())).clicked(){
+                self.select(chip, address);
+            }
+        }
+        ui.monospace(ascii);
+    }
+
+    // the font and rom stand out from the memory no program was loaded in
+    fn color(&self, chip: &Chip, address: usize)->Color32{
+        if address < fontset().len(){
+            return FONT;
+        }
+        if address >= 0x200 && address < 0x200 + chip.rom_size(){
+            return Color32::WHITE;
+        }
+        return OUTSIDE;
+    }
+
+    fn background(&self, chip: &Chip, address: usize)->Option<Color32>{
+        let pc = chip.cpu().get_pc() as usize;
+        let index = chip.cpu().get_index() as usize;
+        if self.selected == Some(address){
+            return Some(Color32::from_gray(90));
+        }
+        if address == pc || address == pc + 1{
+            return Some(PC);
+        }
+        let flash = self.flash.get(address).copied().unwrap_or(0);
+        if flash > 0{
+            return Some(Color32::from_rgb((80 + 120 * flash as u32 / FLASH as u32) as u8, 0, 0));
+        }
+        if address >= index && address < index + UNDER_I{
+            return Some(INDEX);
+        }
+        return None;
+    }
+}
+
+// an address in hex, with an optional 0x or $, a label of the rom, or pc and i
+fn resolve(chip: &Chip, text: &str)->Option<usize>{
+    let text = text.trim();
+    let address = if text.eq_ignore_ascii_case("pc"){
+        Some(chip.cpu().get_pc() as usize)
+    }else if text.eq_ignore_ascii_case("i"){
+        Some(chip.cpu().get_index() as usize)
+    }else if let Some(address) = chip.get_label(text){
+        Some(address as usize)
+    }else{
+        usize::from_str_radix(text.trim_start_matches("0x").trim_start_matches('$'), 16).ok()
+    };
+    return address.filter(| address | *address < chip.memory().len());
+}
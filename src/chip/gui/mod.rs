@@ -0,0 +1,140 @@
+mod memory;
+use memory::MemoryView;
+
+use crate::chip::{ Chip, Opcode };
+use crate::chip::renderer::{ Disposable, Painter };
+use crate::chip::scheduler::Scheduler;
+
+use egui::{ Color32, RichText };
+use glutin::event::WindowEvent;
+use glutin::event_loop::EventLoopWindowTarget;
+use glutin::window::Window;
+
+// instructions shown before and after pc in the disassembly
+const BEFORE: u16 = 8;
+const AFTER: u16 = 16;
+
+// The debugger panels, drawn with egui over the screen. They are hidden until toggled.
+pub struct Gui{ context: egui::Context, state: egui_winit::State, painter: Box<Painter>, visible: bool, memory: Box<MemoryView> }
+
+impl Gui{
+    pub unsafe fn new<T>(event_loop: &EventLoopWindowTarget<T>)->Self{
+        Gui{ context: egui::Context::default(), state: egui_winit::State::new(event_loop), painter: Box::new(Painter::new()), visible: false, memory: Box::new(MemoryView::new()) }
+    }
+
+    pub fn toggle(&mut self){ self.visible = !self.visible; }
+
+    // true while a panel has the keyboard, the keys should then not reach the chip
+    pub fn wants_keyboard(&self)->bool{ self.visible && self.context.wants_keyboard_input() }
+
+    // events are only collected while visible, hidden panels would otherwise queue them up forever
+    pub fn on_event(&mut self, event: &WindowEvent){
+        if self.visible{
+            self.state.on_event(&self.context, event);
+        }
+    }
+
+    pub unsafe fn draw(&mut self, window: &Window, chip: &mut Chip, scheduler: &mut Scheduler){
+        if !self.visible{
+            return;
+        }
+        let input = self.state.take_egui_input(window);
+        let memory = &mut self.memory;
+        let output = self.context.run(input, | context |{
+            panels(context, chip, scheduler);
+            memory.show(context, chip);
+        });
+        self.state.handle_platform_output(window, &self.context, output.platform_output);
+
+        let primitives = self.context.tessellate(output.shapes);
+        let size = window.inner_size();
+        self.painter.paint([size.width, size.height], self.context.pixels_per_point(), &primitives, &output.textures_delta);
+    }
+}
+
+impl Disposable for Gui{
+    fn dispose(&mut self){ self.painter.dispose(); }
+}
+
+fn panels(context: &egui::Context, chip: &mut Chip, scheduler: &mut Scheduler){
+    egui::SidePanel::right("debugger").resizable(false).show(context, | ui |{
+        ui.label(format!("{:?}", chip.state()));
+        ui.horizontal(| ui |{
+            if ui.button("Run").clicked(){
+                match scheduler.debugger_mut(){
+                    Some(debugger) => debugger.resume(chip),
+                    None => chip.resume()
+                }
+            }
+            if ui.button("Pause").clicked(){
+                chip.pause();
+            }
+            if ui.button("Step").clicked(){
+                let result = match scheduler.debugger_mut(){
+                    Some(debugger) => debugger.step(chip),
+                    None =>{
+                        chip.pause();
+                        chip.step()
+                    }
+                };
+                if let Err(error) = result{
+                    println!("{}", error);
+                }
+            }
+        });
+        ui.separator();
+        registers(ui, chip);
+        ui.separator();
+        stack(ui, chip);
+        ui.separator();
+        disassembly(ui, chip, scheduler);
+    });
+}
+
+fn registers(ui: &mut egui::Ui, chip: &Chip){
+    let cpu = chip.cpu();
+    egui::Grid::new("registers").show(ui, | ui |{
+        for x in 0..16{
+            ui.monospace(format!("V{:X} {:02X}", x, cpu.get_register(x)));
+            if x % 4 == 3{
+                ui.end_row();
+            }
+        }
+        ui.monospace(format!("I  {:04X}", cpu.get_index()));
+        ui.monospace(format!("PC {:04X}", cpu.get_pc()));
+        ui.monospace(format!("DT {:02X}", cpu.timers().get_timer()));
+        ui.monospace(format!("ST {:02X}", cpu.timers().get_sound()));
+        ui.end_row();
+    });
+}
+
+// the return addresses, innermost call first
+fn stack(ui: &mut egui::Ui, chip: &Chip){
+    let stack = chip.cpu().get_stack();
+    ui.label(format!("Stack ({})", stack.len()));
+    if stack.is_empty(){
+        ui.monospace("empty");
+    }
+    for (depth, address) in stack.iter().enumerate().rev(){
+        ui.monospace(format!("{:2} {:04X}", depth, address));
+    }
+}
+
+// the instructions around pc, the current one highlighted and breakpoints marked with a *
+fn disassembly(ui: &mut egui::Ui, chip: &Chip, scheduler: &Scheduler){
+    let pc = chip.cpu().get_pc();
+    let memory = chip.memory();
+    let start = pc.saturating_sub(BEFORE * 2);
+    ui.label("Disassembly");
+    for address in (start..pc.saturating_add(AFTER * 2)).step_by(2){
+        let (high, low) = match (memory.peek(address as usize), memory.peek(address as usize + 1)){
+            (Ok(high), Ok(low)) => (high, low),
+            _ => break
+        };
+        let code = (high as u16) << 8 | low as u16;
+        let breakpoint = scheduler.debugger().is_some_and(| debugger | debugger.breakpoints().iter().any(| breakpoint | breakpoint.address == address));
+        let line = format!("{}{:04X}  {:04X}  {}", if breakpoint { "*" } else { " " }, address, code, Opcode::new(code).dessemble());
+        let text = RichText::new(line).monospace();
+        ui.label(if address == pc { text.color(Color32::YELLOW) } else { text });
+    }
+}
@@ -0,0 +1,406 @@
+use std::fmt::{ Display, Formatter };
+use std::fs;
+
+use crate::chip::{ Chip, ChipState, Palette, Screen };
+use crate::chip::error::ChipError;
+
+// The keys held down over a headless run. Each line of a timeline file gives the frame the keys change on
+// and the hex keys held from then on, or - for none; # starts a comment.
+//
+//   # press 5 for a few frames, then hold 4 and 6 together
+//   30  5
+//   34  -
+//   60  4 6
+//   90  -
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Timeline{ events: Vec<(u64, u16)> }
+
+impl Timeline{
+    pub fn new()->Self{ Timeline{ events: Vec::new() } }
+
+    // holds the keys in mask from frame on, replacing whatever was held
+    pub fn hold(&mut self, frame: u64, mask: u16){
+        match self.events.binary_search_by_key(&frame, | (at, _) | *at){
+            Ok(index) => self.events[index].1 = mask,
+            Err(index) => self.events.insert(index, (frame, mask))
+        }
+    }
+
+    // the keys held from this very frame, if they change on it
+    pub fn keys_at(&self, frame: u64)->Option<u16>{
+        return self.events.binary_search_by_key(&frame, | (at, _) | *at).ok().map(| index | self.events[index].1);
+    }
+
+    pub fn len(&self)->usize{ self.events.len() }
+    pub fn is_empty(&self)->bool{ self.events.is_empty() }
+
+    pub fn parse(text: &str)->Result<Self, ChipError>{
+        let mut timeline = Timeline::new();
+        for (index, line) in text.lines().enumerate(){
+            let error = | message: String | ChipError::InvalidTimeline{ line: index + 1, message };
+            let line = line.split('#').next().unwrap_or("");
+            let mut words = line.split_whitespace();
+            let frame = match words.next(){
+                Some(frame) => frame.parse::<u64>().map_err(|_| error(format!("{} is not a frame number", frame)))?,
+                None => continue
+            };
+            let mut mask = 0u16;
+            for word in words{
+                if word == "-"{
+                    continue;
+                }
+                match u8::from_str_radix(word, 16){
+                    Ok(key) if key < 16 => mask |= 1 << key,
+                    _ => return Err(error(format!("{} is not a key, keys go from 0 to F", word)))
+                }
+            }
+            timeline.hold(frame, mask);
+        }
+        return Ok(timeline);
+    }
+
+    pub fn load_file(path: &str)->Result<Self, ChipError>{
+        return Timeline::parse(&fs::read_to_string(path)?);
+    }
+}
+
+// where a headless run ended: the frames and instructions it got through, why it stopped and the cpu at the end
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Report{
+    pub frames: u64,
+    pub instructions: u64,
+    pub state: ChipState,
+    pub registers: [u8; 16],
+    pub index: u16, pub pc: u16,
+    pub stack: Vec<u16>,
+    pub delay: u8, pub sound: u8,
+    pub screen_hash: u32
+}
+
+impl Report{
+    pub fn new(chip: &Chip, frames: u64, instructions: u64)->Self{
+        let cpu = chip.cpu();
+        let mut registers = [0u8; 16];
+        for (x, register) in registers.iter_mut().enumerate(){
+            *register = cpu.get_register(x);
+        }
+        Report{
+            frames, instructions, state: chip.state(), registers,
+            index: cpu.get_index(), pc: cpu.get_pc(), stack: cpu.get_stack().to_vec(),
+            delay: cpu.timers().get_timer(), sound: cpu.timers().get_sound(),
+            screen_hash: screen_hash(chip.screen())
+        }
+    }
+
+    pub fn fault(&self)->Option<&ChipError>{
+        return match &self.state{
+            ChipState::Faulted(error) => Some(error),
+            _ => None
+        };
+    }
+}
+
+impl Display for Report{
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result{
+        match &self.state{
+            ChipState::Faulted(error) => writeln!(f, "state:  faulted, {}", error)?,
+            state => writeln!(f, "state:  {:?}", state)?
+        }
+        writeln!(f, "frames: {}, {} instructions", self.frames, self.instructions)?;
+        let registers: Vec<String> = self.registers.iter().enumerate().map(| (x, value) | format!("V{:X}={:02X}", x, value)).collect();
+        writeln!(f, "{}", registers.join(" "))?;
+        writeln!(f, "I={:03X} PC={:03X} DT={:02X} ST={:02X} stack={:03X?}", self.index, self.pc, self.delay, self.sound, self.stack)?;
+        write!(f, "screen: {:08X}", self.screen_hash)
+    }
+}
+
+// Runs a chip with no window, a frame at a time as the window would at 60 Hz, feeding the keys of a timeline.
+// The random numbers restart from a fixed seed so that every run of a rom ends on the same screen.
+pub struct Headless{ instructions_per_frame: usize, seed: u64, timeline: Timeline }
+
+impl Headless{
+    pub fn new(instructions_per_frame: usize)->Self{
+        Headless{ instructions_per_frame, seed: 0, timeline: Timeline::new() }
+    }
+
+    pub fn get_seed(&self)->u64{ self.seed }
+    pub fn set_seed(&mut self, seed: u64){ self.seed = seed; }
+
+    pub fn timeline(&self)->&Timeline{ &self.timeline }
+    pub fn set_timeline(&mut self, timeline: Timeline){ self.timeline = timeline; }
+
+    // runs up to frames frames, stopping early when the program exits or faults
+    pub fn run(&self, chip: &mut Chip, frames: u64)->Report{
+        chip.set_seed(self.seed);
+        let start = chip.instructions();
+        let mut frame = 0;
+        while frame < frames && chip.is_running(){
+            if let Some(mask) = self.timeline.keys_at(frame){
+                chip.set_keys(mask);
+            }
+            if chip.frame(self.instructions_per_frame).is_err(){
+                break;
+            }
+            frame += 1;
+        }
+        return Report::new(chip, frame, chip.instructions() - start);
+    }
+}
+
+fn visible(screen: &Screen)->impl Iterator<Item = u8> + '_{
+    return (0..screen.height()).flat_map(move | y | (0..screen.width()).map(move | x | screen.get(x, y)));
+}
+
+// crc32 of the visible pixels and the resolution, what golden images are compared by
+pub fn screen_hash(screen: &Screen)->u32{
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(&[screen.width() as u8, screen.height() as u8]);
+    hasher.update(&visible(screen).collect::<Vec<u8>>());
+    return hasher.finalize();
+}
+
+// a plain text portable bitmap, a pixel lit in any plane is black
+pub fn pbm(screen: &Screen)->Vec<u8>{
+    let mut image = format!("P1\n{} {}\n", screen.width(), screen.height());
+    for y in 0..screen.height(){
+        let row: Vec<&str> = (0..screen.width()).map(| x | if screen.get(x, y) != 0 { "1" } else { "0" }).collect();
+        image.push_str(&row.join(" "));
+        image.push('\n');
+    }
+    return image.into_bytes();
+}
+
+// largest block deflate stores uncompressed
+const STORED: usize = 0xffff;
+
+// a paletted png of the screen's plane bits, in the colours of the palette
+pub fn png(screen: &Screen, palette: &Palette)->Vec<u8>{
+    let mut raw = Vec::new();
+    for y in 0..screen.height(){
+        raw.push(0);    // no filter
+        raw.extend((0..screen.width()).map(| x | screen.get(x, y) & 0x03));
+    }
+    return encode(screen.width() as u32, screen.height() as u32, 3, Some(palette.colors.as_flattened()), &raw);
+}
+
+// a png of an image in rgba, like the one a SoftwareRenderer draws
+pub fn rgba_png(width: u32, height: u32, pixels: &[u8])->Vec<u8>{
+    let mut raw = Vec::new();
+    for row in pixels.chunks_exact((width * 4) as usize).take(height as usize){
+        raw.push(0);
+        raw.extend_from_slice(row);
+    }
+    return encode(width, height, 6, None, &raw);
+}
+
+// a png of 8 bit samples in the given colour type, written with uncompressed deflate blocks so that no compression library is needed
+fn encode(width: u32, height: u32, color_type: u8, palette: Option<&[u8]>, raw: &[u8])->Vec<u8>{
+    let mut zlib = vec![0x78, 0x01];
+    let blocks = raw.len().div_ceil(STORED).max(1);
+    for (index, block) in raw.chunks(STORED).enumerate(){
+        let length = block.len() as u16;
+        zlib.push(if index + 1 == blocks { 1 } else { 0 });
+        zlib.extend_from_slice(&length.to_le_bytes());
+        zlib.extend_from_slice(&(!length).to_le_bytes());
+        zlib.extend_from_slice(block);
+    }
+    zlib.extend_from_slice(&adler32(raw).to_be_bytes());
+
+    let mut header = Vec::new();
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    header.extend_from_slice(&[8, color_type, 0, 0, 0]);    // 8 bit samples, no interlacing
+
+    let mut image = b"\x89PNG\r\n\x1a\n".to_vec();
+    chunk(&mut image, b"IHDR", &header);
+    if let Some(palette) = palette{
+        chunk(&mut image, b"PLTE", palette);
+    }
+    chunk(&mut image, b"IDAT", &zlib);
+    chunk(&mut image, b"IEND", &[]);
+    return image;
+}
+
+fn chunk(image: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]){
+    image.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(kind);
+    hasher.update(data);
+    image.extend_from_slice(kind);
+    image.extend_from_slice(data);
+    image.extend_from_slice(&hasher.finalize().to_be_bytes());
+}
+
+fn adler32(data: &[u8])->u32{
+    let (mut a, mut b) = (1u32, 0u32);
+    for byte in data{
+        a = (a + *byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    return b << 16 | a;
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    // the chunks of a png as kind and data, checking the signature and every crc on the way
+    fn chunks(image: &[u8])->Vec<([u8; 4], Vec<u8>)>{
+        assert_eq!(&image[..8], b"\x89PNG\r\n\x1a\n");
+        let mut chunks = Vec::new();
+        let mut at = 8;
+        while at < image.len(){
+            let length = u32::from_be_bytes(image[at..at + 4].try_into().unwrap()) as usize;
+            let kind: [u8; 4] = image[at + 4..at + 8].try_into().unwrap();
+            let data = image[at + 8..at + 8 + length].to_vec();
+            let crc = u32::from_be_bytes(image[at + 8 + length..at + 12 + length].try_into().unwrap());
+            assert_eq!(crc, crc32fast::hash(&image[at + 4..at + 8 + length]), "crc of {}", String::from_utf8_lossy(&kind));
+            chunks.push((kind, data));
+            at += 12 + length;
+        }
+        return chunks;
+    }
+
+    // the bytes of a zlib stream of stored deflate blocks, checking the block headers and the adler32 at the end
+    fn inflate(zlib: &[u8])->Vec<u8>{
+        assert_eq!(&zlib[..2], &[0x78, 0x01]);
+        let mut raw = Vec::new();
+        let mut at = 2;
+        loop{
+            let last = zlib[at] == 1;
+            let length = u16::from_le_bytes([zlib[at + 1], zlib[at + 2]]);
+            assert_eq!(!length, u16::from_le_bytes([zlib[at + 3], zlib[at + 4]]));
+            raw.extend_from_slice(&zlib[at + 5..at + 5 + length as usize]);
+            at += 5 + length as usize;
+            if last{
+                break;
+            }
+        }
+        assert_eq!(&zlib[at..], &adler32(&raw).to_be_bytes());
+        return raw;
+    }
+
+    #[test]
+    fn timelines_hold_keys_from_their_frame_on(){
+        let timeline = Timeline::parse("# a comment\n30  5\n\n34  -   # let go\n60 4 6\n60 a F\n").unwrap();
+        assert_eq!(timeline.len(), 3);
+        assert_eq!(timeline.keys_at(30), Some(1 << 5));
+        assert_eq!(timeline.keys_at(31), None);
+        assert_eq!(timeline.keys_at(34), Some(0));
+        // a later line for the same frame replaces the keys
+        assert_eq!(timeline.keys_at(60), Some(1 << 0xa | 1 << 0xf));
+        assert!(Timeline::parse("# nothing but comments\n\n").unwrap().is_empty());
+    }
+
+    #[test]
+    fn bad_timelines_are_errors_with_their_line(){
+        assert_eq!(Timeline::parse("1 2\nsoon 5\n"), Err(ChipError::InvalidTimeline{ line: 2, message: String::from("soon is not a frame number") }));
+        assert_eq!(Timeline::parse("-4 1\n"), Err(ChipError::InvalidTimeline{ line: 1, message: String::from("-4 is not a frame number") }));
+        assert_eq!(Timeline::parse("\n\n8 1 10\n"), Err(ChipError::InvalidTimeline{ line: 3, message: String::from("10 is not a key, keys go from 0 to F") }));
+        assert_eq!(Timeline::parse("8 g\n"), Err(ChipError::InvalidTimeline{ line: 1, message: String::from("g is not a key, keys go from 0 to F") }));
+    }
+
+    #[test]
+    fn screen_hashes_only_change_with_the_screen(){
+        let mut screen = Screen::new();
+        // golden hashes are kept in files, the hash of a screen must never change between versions
+        assert_eq!(screen_hash(&screen), 0xec074993);
+        screen.set(0, 0);
+        assert_eq!(screen_hash(&screen), 0x64b1f3f0);
+        let mut other = Screen::new();
+        other.set(0, 0);
+        assert_eq!(screen_hash(&other), screen_hash(&screen));
+        // the same pixels in hires are a different screen
+        other.set_extended(true);
+        other.set(0, 0);
+        assert_ne!(screen_hash(&other), screen_hash(&screen));
+    }
+
+    #[test]
+    fn pbm_has_a_row_of_pixels_per_line(){
+        let mut screen = Screen::new();
+        screen.set(1, 0);
+        screen.set(63, 31);
+        let image = String::from_utf8(pbm(&screen)).unwrap();
+        let lines: Vec<&str> = image.lines().collect();
+        assert_eq!(&lines[..2], &["P1", "64 32"]);
+        assert_eq!(lines.len(), 2 + 32);
+        assert!(lines[2].starts_with("0 1 0 "));
+        assert_eq!(lines[2].split(' ').count(), 64);
+        assert!(lines[33].ends_with(" 0 1"));
+    }
+
+    #[test]
+    fn png_holds_the_plane_bits_in_the_colours_of_the_palette(){
+        let mut screen = Screen::new();
+        screen.set(2, 0);
+        screen.set_planes(3);
+        screen.set(0, 1);
+        let palette = Palette::preset("octo").unwrap();
+        let chunks = chunks(&png(&screen, &palette));
+        let kinds: Vec<&[u8; 4]> = chunks.iter().map(| (kind, _) | kind).collect();
+        assert_eq!(kinds, [b"IHDR", b"PLTE", b"IDAT", b"IEND"]);
+        // 64 by 32, 8 bit samples of a palette
+        assert_eq!(chunks[0].1, [0, 0, 0, 64, 0, 0, 0, 32, 8, 3, 0, 0, 0]);
+        assert_eq!(chunks[1].1, palette.colors.as_flattened());
+        let raw = inflate(&chunks[2].1);
+        assert_eq!(raw.len(), 32 * 65);
+        // every row starts with filter 0
+        assert_eq!(&raw[..4], &[0, 0, 0, 1]);
+        assert_eq!(&raw[65..67], &[0, 3]);
+        assert!(chunks[3].1.is_empty());
+    }
+
+    #[test]
+    fn large_pngs_are_split_into_stored_blocks(){
+        let (width, height) = (128u32, 160u32);
+        let pixels: Vec<u8> = (0..width * height * 4).map(| index | index as u8).collect();
+        let chunks = chunks(&rgba_png(width, height, &pixels));
+        assert_eq!(&chunks[0].1[8..10], &[8, 6]);
+        let zlib = &chunks[1].1;
+        // the first block is not the last one and is as large as a stored block gets
+        assert_eq!(&zlib[2..7], &[0, 0xff, 0xff, 0x00, 0x00]);
+        let raw = inflate(zlib);
+        assert_eq!(raw.len(), (height * (width * 4 + 1)) as usize);
+        let row = (width * 4) as usize;
+        for (y, line) in raw.chunks(row + 1).enumerate(){
+            assert_eq!(line[0], 0);
+            assert_eq!(&line[1..], &pixels[y * row..(y + 1) * row]);
+        }
+    }
+
+    #[test]
+    fn headless_runs_count_frames_and_instructions(){
+        // 7001 1200: v0 counts the instructions run
+        let mut chip = Chip::new();
+        chip.load_rom(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+        let report = Headless::new(4).run(&mut chip, 10);
+        assert_eq!((report.frames, report.instructions, report.state.clone()), (10, 40, ChipState::Running));
+        assert_eq!((report.registers[0], report.pc), (20, 0x200));
+        assert_eq!(report.screen_hash, 0xec074993);
+        assert!(report.fault().is_none());
+    }
+
+    #[test]
+    fn headless_runs_feed_the_timeline_and_stop_when_the_program_does(){
+        // F10A 00FD: waits for a key into v1 then exits
+        let mut chip = Chip::new();
+        chip.load_rom(&[0xf1, 0x0a, 0x00, 0xfd]).unwrap();
+        let mut timeline = Timeline::new();
+        timeline.hold(3, 1 << 7);
+        timeline.hold(5, 0);
+        let mut runner = Headless::new(10);
+        runner.set_timeline(timeline);
+        let report = runner.run(&mut chip, 60);
+        // the vip waits for the key to go up again on frame 5
+        assert_eq!((report.state.clone(), report.registers[1]), (ChipState::Halted, 7));
+        assert_eq!(report.frames, 6);
+
+        // 00EE on an empty stack faults on the very first frame, which does not count
+        let mut chip = Chip::new();
+        chip.load_rom(&[0x00, 0xee]).unwrap();
+        let report = Headless::new(10).run(&mut chip, 60);
+        assert_eq!((report.frames, report.instructions), (0, 1));
+        assert_eq!(report.fault(), Some(&ChipError::StackUnderflow));
+    }
+}
@@ -1,22 +1,279 @@
+use std::fs;
 
-use egui::Event;
+use crate::chip::error::ChipError;
+use crate::chip::state::{ StateWriter, StateReader };
 
-pub struct KeyPad{ keys: [bool; 16] }
+// Besides which keys are down, the keypad keeps the presses and releases that happened since
+// they were last taken, so that Fx0A can wait for a key to go down or come back up.
+pub struct KeyPad{ keys: [bool; 16], pressed: u16, released: u16 }
 
 impl KeyPad{
-    pub fn new()->Self{ KeyPad{ keys:[false; 16] } }
+    pub fn new()->Self{ KeyPad{ keys:[false; 16], pressed: 0, released: 0 } }
 
     pub fn reset(&mut self){
         for i in 0..self.keys.len(){
             self.keys[i] = false;
         }
+        self.clear_events();
     }
 
     pub fn get(&self, key: usize)->bool{ self.keys[key] }
 
     pub fn clear_key(&mut self, key: usize){ self.keys[key] = false; }
 
-    pub fn update(&mut self, event: &Event){
+    // a key that is already down does not count as pressed again, which filters out the host key repeat
+    pub fn press(&mut self, key: usize){
+        if !self.keys[key]{
+            self.pressed |= 1 << key;
+        }
+        self.keys[key] = true;
+    }
+
+    pub fn release(&mut self, key: usize){
+        if self.keys[key]{
+            self.released |= 1 << key;
+        }
+        self.keys[key] = false;
+    }
+
+    pub fn update(&mut self, key: usize, pressed: bool){
+        if pressed { self.press(key); } else { self.release(key); }
+    }
+
+    // the lowest key pressed since the last call, if any
+    pub fn take_press(&mut self)->Option<usize>{ KeyPad::take(&mut self.pressed) }
+
+    // the lowest key released since the last call, if any
+    pub fn take_release(&mut self)->Option<usize>{ KeyPad::take(&mut self.released) }
+
+    // the presses and releases not taken yet, as bitmasks
+    pub fn events(&self)->(u16, u16){ (self.pressed, self.released) }
+    pub fn set_events(&mut self, pressed: u16, released: u16){
+        self.pressed = pressed;
+        self.released = released;
+    }
+
+    pub fn clear_events(&mut self){
+        self.pressed = 0;
+        self.released = 0;
+    }
+
+    fn take(events: &mut u16)->Option<usize>{
+        if *events == 0{
+            return None;
+        }
+        let key = events.trailing_zeros() as usize;
+        *events &= !(1 << key);
+        return Some(key);
+    }
+
+    // the pressed keys as a bitmask, key 0 in the lowest bit
+    pub fn mask(&self)->u16{
+        let mut mask = 0u16;
+        for (key, pressed) in self.keys.iter().enumerate(){
+            if *pressed{ mask |= 1 << key; }
+        }
+        return mask;
+    }
+
+    pub fn set_mask(&mut self, mask: u16){
+        for (key, pressed) in self.keys.iter_mut().enumerate(){
+            *pressed = mask & (1 << key) != 0;
+        }
+    }
+
+    pub fn save_state(&self, state: &mut StateWriter){
+        state.u16(self.mask());
+        state.u16(self.pressed);
+        state.u16(self.released);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader)->Result<(), ChipError>{
+        self.set_mask(state.u16()?);
+        if state.version() >= 3{
+            self.pressed = state.u16()?;
+            self.released = state.u16()?;
+        }
+        Ok(())
+    }
+}
+
+impl Default for KeyPad{
+    fn default()->Self{ KeyPad::new() }
+}
+
+// Maps host keys to the hex keypad by scancode, so the layout follows the physical keys whatever
+// the keyboard language. The default puts the COSMAC VIP keypad on the left of a PC keyboard:
+//
+//   1 2 3 C      1 2 3 4
+//   4 5 6 D      Q W E R
+//   7 8 9 E      A S D F
+//   A 0 B F      Z X C V
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyMap{ scancodes: [u32; 16] }
+
+impl KeyMap{
+    // the PC set 1 scancodes of the keys above, which are also the Linux and Windows ones
+    pub fn new()->Self{
+        KeyMap{ scancodes: [
+            0x2d, 0x02, 0x03, 0x04,     // X 1 2 3
+            0x10, 0x11, 0x12, 0x1e,     // Q W E A
+            0x1f, 0x20, 0x2c, 0x2e,     // S D Z C
+            0x05, 0x13, 0x21, 0x2f      // 4 R F V
+        ]}
+    }
+
+    pub fn get(&self, scancode: u32)->Option<usize>{
+        return self.scancodes.iter().position(| code | *code == scancode);
+    }
+
+    pub fn get_scancode(&self, key: usize)->u32{ self.scancodes[key] }
+    pub fn set_scancode(&mut self, key: usize, scancode: u32){ self.scancodes[key] = scancode; }
+
+    // reads "key = scancode" lines over the default layout, both in hex with an optional 0x,
+    // blank lines and lines starting with # are skipped
+    pub fn parse(text: &str)->Result<Self, ChipError>{
+        let mut map = KeyMap::new();
+        for (number, line) in text.lines().enumerate(){
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#'){
+                continue;
+            }
+            let parsed = line.split_once('=').and_then(| (key, scancode) |{
+                let key = usize::from_str_radix(key.trim().trim_start_matches("0x"), 16).ok().filter(| key | *key < 16)?;
+                let scancode = u32::from_str_radix(scancode.trim().trim_start_matches("0x"), 16).ok()?;
+                Some((key, scancode))
+            });
+            match parsed{
+                Some((key, scancode)) => map.set_scancode(key, scancode),
+                None => return Err(ChipError::InvalidKeyMap{ line: number + 1, message: format!("expected key = scancode, got {}", line) })
+            }
+        }
+        return Ok(map);
+    }
+
+    pub fn load_file(path: &str)->Result<Self, ChipError>{
+        return KeyMap::parse(&fs::read_to_string(path)?);
+    }
+}
+
+impl Default for KeyMap{
+    fn default()->Self{ KeyMap::new() }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::chip::{ Chip, Quirks };
+
+    // a chip that waits for a key into v2 as soon as it starts: F20A 1202
+    fn waiting(wait_release: bool)->Chip{
+        let mut chip = Chip::with_quirks(Quirks{ wait_release, ..Quirks::cosmac_vip() });
+        chip.load_rom(&[0xf2, 0x0a, 0x12, 0x02]).unwrap();
+        chip.step().unwrap();
+        assert!(chip.is_waiting());
+        return chip;
+    }
+
+    #[test]
+    fn presses_and_releases_are_taken_once_lowest_first(){
+        let mut keys = KeyPad::new();
+        keys.press(9);
+        keys.press(3);
+        // held down by the key repeat of the host
+        keys.press(3);
+        assert_eq!((keys.take_press(), keys.take_press(), keys.take_press()), (Some(3), Some(9), None));
+        assert_eq!(keys.take_release(), None);
+
+        // a key that is not down cannot be released
+        keys.release(5);
+        keys.release(9);
+        assert_eq!((keys.take_release(), keys.take_release()), (Some(9), None));
+        assert_eq!(keys.mask(), 1 << 3);
+
+        keys.press(1);
+        keys.clear_events();
+        assert_eq!((keys.take_press(), keys.mask()), (None, 1 << 1 | 1 << 3));
+    }
+
+    #[test]
+    fn fx0a_waits_for_a_press_or_for_a_release(){
+        let mut chip = waiting(false);
+        chip.press_key(7);
+        chip.step().unwrap();
+        assert_eq!((chip.is_waiting(), chip.cpu().get_register(2)), (false, 7));
+
+        // on the vip the key has to come back up
+        let mut chip = waiting(true);
+        chip.press_key(7);
+        chip.step().unwrap();
+        assert!(chip.is_waiting());
+        chip.release_key(7);
+        chip.step().unwrap();
+        assert_eq!((chip.is_waiting(), chip.cpu().get_register(2)), (false, 7));
+    }
+
+    #[test]
+    fn keys_held_before_fx0a_do_not_end_the_wait(){
+        let mut chip = Chip::with_quirks(Quirks{ wait_release: false, ..Quirks::cosmac_vip() });
+        chip.load_rom(&[0xf2, 0x0a, 0x12, 0x02]).unwrap();
+        chip.press_key(4);
+        chip.step().unwrap();
+        chip.run(10).unwrap();
+        assert!(chip.is_waiting());
+        chip.press_key(6);
+        chip.step().unwrap();
+        assert_eq!(chip.cpu().get_register(2), 6);
+    }
+
+    #[test]
+    fn timers_keep_running_while_waiting(){
+        // 6010 F015 F018 F10A
+        let mut chip = Chip::new();
+        chip.load_rom(&[0x60, 0x10, 0xf0, 0x15, 0xf0, 0x18, 0xf1, 0x0a]).unwrap();
+        chip.run(4).unwrap();
+        for _ in 0..5{
+            chip.frame(10).unwrap();
+        }
+        assert_eq!((chip.cpu().timers().get_timer(), chip.cpu().timers().get_sound()), (0x10 - 5, 0x10 - 5));
+        assert_eq!((chip.is_waiting(), chip.cpu().get_pc()), (true, 0x208));
+    }
+
+    #[test]
+    fn states_taken_while_waiting_keep_waiting(){
+        let mut chip = waiting(true);
+        // a press not yet released is part of the state
+        chip.press_key(5);
+        let state = chip.save_state();
+        chip.release_key(5);
+        chip.step().unwrap();
+        assert!(!chip.is_waiting());
+
+        chip.load_state(&state).unwrap();
+        assert_eq!((chip.is_waiting(), chip.waiting_register(), chip.cpu().get_register(2)), (true, Some(2), 0));
+        chip.release_key(5);
+        chip.step().unwrap();
+        assert_eq!((chip.is_waiting(), chip.cpu().get_register(2)), (false, 5));
+
+        // the same on a fresh chip
+        let mut other = waiting(true);
+        other.load_state(&state).unwrap();
+        assert_eq!(other.keys().mask(), 1 << 5);
+        other.set_keys(0);
+        other.step().unwrap();
+        assert_eq!(other.cpu().get_register(2), 5);
+    }
+
+    #[test]
+    fn keymap_file_overrides_the_default_layout(){
+        let map = KeyMap::parse("# arrows for the paddles\n1 = 0x48\n4 = 50\n\n").unwrap();
+        assert_eq!(map.get(0x48), Some(1));
+        assert_eq!(map.get(0x50), Some(4));
+        assert_eq!(map.get(0x2d), Some(0));
+    }
 
+    #[test]
+    fn keymap_errors_name_the_line(){
+        assert_eq!(KeyMap::parse("1 = 2\n10 = 3").map(|_| ()), Err(ChipError::InvalidKeyMap{ line: 2, message: String::from("expected key = scancode, got 10 = 3") }));
     }
-}
\ No newline at end of file
+}
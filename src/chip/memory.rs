@@ -1,9 +1,66 @@
+use std::cell::Cell;
+
 use crate::chip::utils::fontset;
+use crate::chip::error::{ ChipError, FaultAction };
+use crate::chip::state::{ StateWriter, StateReader };
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access{ Read, Write }
+
+// an address range, end excluded, watched for reads, writes or both
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Watch{ pub start: usize, pub end: usize, pub read: bool, pub write: bool }
+
+impl Watch{
+    fn matches(&self, address: usize, access: Access)->bool{
+        let wanted = match access{ Access::Read => self.read, Access::Write => self.write };
+        return wanted && address >= self.start && address < self.end;
+    }
+}
 
-pub struct Memory{ memory: [u8; 4096] }
+// get and save go through the watches, the first access to hit one is kept until taken.
+// Instruction fetches and tools looking at memory use peek and poke, which are not watched.
+// The addresses saved to are marked, end_frame keeps the marks of the frame that just ended.
+pub struct Memory{
+    memory: Vec<u8>, policy: FaultAction, watches: Vec<Watch>, hit: Cell<Option<(usize, Access)>>,
+    written: Vec<bool>, last_written: Vec<bool>
+}
 
 impl Memory{
-    pub fn new()->Self { Memory{ memory: [0; 4096] }}
+    pub fn new()->Self { Memory::with_size(4096) }
+
+    // 4 KiB for CHIP-8 and SCHIP, 64 KiB for XO-CHIP
+    pub fn with_size(size: usize)->Self {
+        Memory{
+            memory: vec![0; size], policy: FaultAction::Halt, watches: Vec::new(), hit: Cell::new(None),
+            written: vec![false; size], last_written: vec![false; size]
+        }
+    }
+
+    pub fn get_watches(&self)->&[Watch]{ &self.watches }
+    pub fn set_watches(&mut self, watches: &[Watch]){
+        self.watches.clear();
+        self.watches.extend_from_slice(watches);
+    }
+
+    // the address and kind of the first watched access since the last call
+    pub fn take_hit(&self)->Option<(usize, Access)>{ self.hit.take() }
+
+    fn watch(&self, address: usize, access: Access){
+        if self.hit.get().is_none() && self.watches.iter().any(| watch | watch.matches(address, access)){
+            self.hit.set(Some((address, access)));
+        }
+    }
+
+    // how accesses past the end of memory are handled
+    pub fn set_policy(&mut self, policy: FaultAction){ self.policy = policy; }
+
+    pub fn resize(&mut self, size: usize){
+        self.memory.resize(size, 0);
+        self.written = vec![false; size];
+        self.last_written = vec![false; size];
+    }
+
     pub fn clear(&mut self){
         let fontset = fontset();
         for i in 0..self.memory.len(){
@@ -11,17 +68,89 @@ impl Memory{
         }
     }
 
-    pub fn load(&mut self, data: &[u8]){
-        for i in 0..data.len(){
-            self.memory[i + 0x200] = data[i];
+    pub fn load(&mut self, data: &[u8])->Result<(), ChipError>{
+        if 0x200 + data.len() > self.memory.len(){
+            return Err(ChipError::RomTooLarge);
+        }
+        self.memory[0x200..0x200 + data.len()].copy_from_slice(data);
+        Ok(())
+    }
+
+    // resolves an address against the fault policy, None when the access is to be ignored
+    fn address(&self, address: usize)->Result<Option<usize>, ChipError>{
+        if address < self.memory.len(){
+            return Ok(Some(address));
+        }
+        match self.policy{
+            FaultAction::Halt => Err(ChipError::MemoryOutOfBounds{ addr: address }),
+            FaultAction::Ignore => Ok(None),
+            FaultAction::Wrap => Ok(Some(address % self.memory.len()))
+        }
+    }
+
+    // ignored reads return 0
+    pub fn get(&self, index: usize)->Result<u8, ChipError>{
+        self.watch(index, Access::Read);
+        return self.peek(index);
+    }
+
+    pub fn peek(&self, index: usize)->Result<u8, ChipError>{
+        return Ok(match self.address(index)?{
+            Some(index) => self.memory[index],
+            None => 0
+        });
+    }
+
+    pub fn save(&mut self, address:usize, data: u8)->Result<(), ChipError>{
+        self.watch(address, Access::Write);
+        if let Some(address) = self.address(address)?{
+            self.memory[address] = data;
+            self.written[address] = true;
+        }
+        Ok(())
+    }
+
+    // writes without watching nor marking, for tools editing memory
+    pub fn poke(&mut self, address: usize, data: u8)->Result<(), ChipError>{
+        if let Some(address) = self.address(address)?{
+            self.memory[address] = data;
         }
+        Ok(())
+    }
+
+    pub fn end_frame(&mut self){
+        std::mem::swap(&mut self.written, &mut self.last_written);
+        self.written.iter_mut().for_each(| written | *written = false);
+    }
+
+    // whether the address was saved to during the last frame or the one running
+    pub fn was_written(&self, address: usize)->bool{
+        return self.written.get(address).copied().unwrap_or(false) || self.last_written.get(address).copied().unwrap_or(false);
     }
 
-    pub fn get(&self, index: usize)->u8{
-        return self.memory[index];
+    pub fn len(&self)->usize{ self.memory.len() }
+    pub fn is_empty(&self)->bool{ self.memory.is_empty() }
+
+    pub fn data(&self)->&[u8]{ &self.memory }
+
+    pub fn save_state(&self, state: &mut StateWriter){
+        state.u32(self.memory.len() as u32);
+        state.bytes(&self.memory);
     }
 
-    pub fn save(&mut self, address:usize, data: u8){
-        self.memory[address] = data;
+    // the memory has to be sized for the quirks of the state beforehand, a state of any other size is refused
+    pub fn load_state(&mut self, state: &mut StateReader)->Result<(), ChipError>{
+        let size = state.u32()? as usize;
+        if size == 0 || size != self.memory.len(){
+            return Err(ChipError::InvalidState(format!("{} bytes of memory where the quirks have {}", size, self.memory.len())));
+        }
+        self.memory = state.bytes(size)?.to_vec();
+        self.written = vec![false; size];
+        self.last_written = vec![false; size];
+        Ok(())
     }
+}
+
+impl Default for Memory{
+    fn default()->Self{ Memory::new() }
 }
\ No newline at end of file
@@ -1,27 +1,50 @@
-use crate::chip::compiler::Opcode;
-use crate::chip::screen::renderer::Disposable;
-use std::time::SystemTime;
-use std::time::UNIX_EPOCH;
-
-use glutin::ContextBuilder;
-use glutin::window::WindowBuilder;
-use glutin::event::{Event, WindowEvent};
-use glutin::event_loop::{ ControlFlow, EventLoop};
+pub use crate::chip::compiler::Opcode;
 
 use std::io::Read;
 use std::fs::File;
 
-mod cpu;
-use cpu::CPU;
+pub mod cpu;
+pub use cpu::CPU;
+
+pub mod screen;
+pub use screen::Screen;
+
+pub mod memory;
+pub use memory::{ Memory, Access, Watch };
+
+pub mod keys;
+pub use keys::{ KeyPad, KeyMap };
+
+pub mod quirks;
+pub use quirks::Quirks;
+
+pub mod audio;
+pub use audio::{ Audio, Waveform, Sink, BufferSink, NullSink, WavSink };
+#[cfg(feature = "host-audio")]
+pub use audio::DeviceSink;
+
+pub mod error;
+pub use error::{ ChipError, FaultAction, FaultPolicy };
 
-mod screen;
-use screen::Screen;
+pub mod state;
 
-mod memory;
-use memory::Memory;
+pub mod rewind;
+pub use rewind::Rewind;
 
-mod keys;
-use keys::KeyPad;
+pub mod random;
+pub use random::{ Random, XorShift, VipRandom };
+
+pub mod movie;
+pub use movie::Movie;
+
+pub mod headless;
+pub use headless::{ Headless, Timeline, Report };
+
+pub mod debugger;
+pub use debugger::{ Debugger, Breakpoint, Condition, Hit };
+
+pub mod scheduler;
+pub use scheduler::{ Scheduler, Clock, SystemClock, ManualClock };
 
 mod compiler;
 pub use compiler::Compiler; 
@@ -29,218 +52,469 @@ pub use compiler::Compiler;
 mod assembler;
 pub use assembler::Assemblier;
 
+mod disassembler;
+pub use disassembler::Disassembler;
+
 pub mod utils;
 
+pub mod renderer;
+pub use renderer::{ Renderer, SoftwareRenderer, Preset };
+
+pub mod palette;
+pub use palette::Palette;
+
+mod gui;
 
-static mut DELTA_TIME: f64 = 0.0;
-static mut LAST_TIME:f64 = 0.0;
+mod window;
+pub use window::{ start, Settings };
 
-struct Chip{
+// why the chip is or is not executing instructions
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChipState{
+    Running,
+    Paused,
+    WaitingForKey,          // blocked on Fx0A, the timers keep running
+    Halted,                 // the program exited through 00FD
+    Faulted(ChipError),
+    BreakpointHit(u16)
+}
+
+pub struct Chip{
     cpu: Box<CPU>, opcode: Box<Opcode>,
-    memory: Box<Memory>, screen: Box<Screen>, keys: Box<KeyPad>, loaded: bool
+    memory: Box<Memory>, screen: Box<Screen>, keys: Box<KeyPad>, audio: Box<Audio>, loaded: bool,
+    random: Box<dyn Random>, seed: u64,
+    drawn: bool,    // a sprite was drawn since the last vertical blank
+    halted: bool,   // the program exited through 00FD
+    waiting: Option<usize>, // Fx0A is waiting for a key to store in this register
+    paused: bool, fault: Option<ChipError>, breakpoint: Option<u16>,
+    rom_hash: u32,  // crc32 of the loaded rom, save states only load onto the rom they were taken from
+    rom_size: usize,
+    instructions: u64,  // executed since the last reset
+    labels: Vec<(String, u16)>  // the labels of an assembled rom, or the ones the disassembler made up for a binary one
 }
 
 impl Chip{
-    fn new(screen: Screen)->Self{
-        return Chip{
-            cpu: Box::new(CPU::new()), opcode:Box::new(Opcode::new(0)), screen: Box::new(screen),
-            memory: Box::new(Memory::new()), keys: Box::new(KeyPad::new()), loaded: false
-        }
+    pub fn new()->Self{ Chip::with_quirks(Quirks::default()) }
+
+    // without an explicit seed every chip draws different random numbers
+    pub fn with_quirks(quirks: Quirks)->Self{
+        let seed = rand::random();
+        let mut chip = Chip{
+            cpu: Box::new(CPU::new()), opcode:Box::new(Opcode::new(0)), screen: Box::new(Screen::new()),
+            memory: Box::new(Memory::with_size(quirks.memory_size)), keys: Box::new(KeyPad::new()), audio: Box::new(Audio::new()), loaded: false, drawn: false, halted: false, waiting: None,
+            paused: false, fault: None, breakpoint: None,
+            rom_hash: 0, rom_size: 0, instructions: 0, labels: Vec::new(), random: Box::new(XorShift::new(seed)), seed
+        };
+        chip.cpu.set_quirks(quirks);
+        chip.reset();
+        return chip;
+    }
+
+    pub fn policy(&self)->&FaultPolicy{ self.cpu.get_policy() }
+    pub fn set_policy(&mut self, policy: FaultPolicy){
+        self.memory.set_policy(policy.memory_out_of_bounds);
+        self.cpu.set_policy(policy);
     }
 
+    pub fn quirks(&self)->&Quirks{ self.cpu.get_quirks() }
+    pub fn set_quirks(&mut self, quirks: Quirks){
+        self.memory.resize(quirks.memory_size);
+        self.cpu.set_quirks(quirks);
+    }
+
+    // marks the start of a new 60 Hz frame, releasing a draw held back by the display wait quirk
+    pub fn vblank(&mut self){ self.drawn = false; }
+
     pub fn reset(&mut self){
         self.cpu.reset();
         self.opcode.clear();
         self.screen.clear();
         self.memory.clear();
         self.keys.reset();
+        self.audio.reset();
         self.loaded = false;
+        self.drawn = false;
+        self.halted = false;
+        self.waiting = None;
+        self.paused = false;
+        self.fault = None;
+        self.breakpoint = None;
+        self.rom_hash = 0;
+        self.rom_size = 0;
+        self.instructions = 0;
+        self.labels.clear();
+        self.random.reseed(self.seed);
     }
 
-    pub fn load(&mut self, rom: &str){
-        match &mut File::open(rom){
-            Ok(file) =>{
-                let mut init: Vec<u8> = Vec::new();
-                if rom.ends_with(".c8"){
-                    if let Err(error) = file.read_to_end(init.as_mut()){
-                        panic!("{}", error);
-                    }
-                }else if rom.ends_with(".asm"){
-                    let mut data = String::new();
-                    if let Err(error) = file.read_to_string(&mut data){
-                        panic!("{}", error);
-                    }
-                    let mut assembler = Assemblier::new();
-                    assembler.init(data.as_ref());
-                    init = assembler.run();
-                }
-                self.memory.load(init.as_ref());
-                self.loaded = true;
-            },
-            Err(error) =>{ panic!("{}", error); }
+    // .asm files are assembled first, anything else is loaded as a binary rom
+    pub fn load(&mut self, rom: &str)-> Result<(), ChipError>{
+        let mut file = File::open(rom)?;
+        let mut init: Vec<u8> = Vec::new();
+        let labels: Vec<(String, u16)>;
+        if rom.ends_with(".asm"){
+            let mut data = String::new();
+            file.read_to_string(&mut data)?;
+            let mut assembler = Assemblier::new();
+            assembler.init(data.as_ref()).map_err(ChipError::InvalidSource)?;
+            init = assembler.run().map_err(ChipError::InvalidSource)?;
+            labels = assembler.labels().to_vec();
+        }else{
+            file.read_to_end(init.as_mut())?;
+            let mut disassembler = Disassembler::new();
+            disassembler.init(init.as_ref());
+            labels = disassembler.labels();
         }
+        self.load_rom(init.as_ref())?;
+        self.labels = labels;
+        return Ok(());
+    }
+
+    pub fn load_rom(&mut self, rom: &[u8])-> Result<(), ChipError>{
+        self.memory.load(rom)?;
+        self.loaded = true;
+        self.rom_hash = state::rom_hash(rom);
+        self.rom_size = rom.len();
+        self.labels.clear();
+        return Ok(());
     }
 
     pub fn is_loaded(&self)->bool{
         return self.loaded;
     }
 
-    pub fn run(&mut self)-> bool {
-        let opcode:Opcode  = Opcode::new(self.cpu.fetch(self.memory.as_ref()));
-        match &opcode & 0xf000{
+    pub fn is_halted(&self)->bool{
+        return self.halted;
+    }
+
+    // a fault or breakpoint outranks a pause, which outranks waiting for a key
+    pub fn state(&self)->ChipState{
+        if let Some(error) = &self.fault{
+            return ChipState::Faulted(error.clone());
+        }
+        if self.halted{
+            return ChipState::Halted;
+        }
+        if let Some(address) = self.breakpoint{
+            return ChipState::BreakpointHit(address);
+        }
+        if self.paused{
+            return ChipState::Paused;
+        }
+        if self.waiting.is_some(){
+            return ChipState::WaitingForKey;
+        }
+        return ChipState::Running;
+    }
+
+    // waiting for a key counts as running, the chip has to keep looking at the keypad
+    pub fn is_running(&self)->bool{
+        return matches!(self.state(), ChipState::Running | ChipState::WaitingForKey);
+    }
+
+    // the number of instructions executed since the last reset, runners report it and bench times it
+    pub fn instructions(&self)->u64{ self.instructions }
+
+    pub fn is_paused(&self)->bool{ self.paused }
+    pub fn pause(&mut self){ self.paused = true; }
+
+    // carries on after a pause, a breakpoint or a fault, a halted program stays halted until reset
+    pub fn resume(&mut self){
+        self.paused = false;
+        self.breakpoint = None;
+        self.fault = None;
+    }
+
+    // stops as if a breakpoint at address was hit, resume carries on from there
+    pub fn break_at(&mut self, address: u16){ self.breakpoint = Some(address); }
+
+    // Fx0A is blocking, no instruction is fetched until a key is pressed or released
+    pub fn is_waiting(&self)->bool{ self.waiting.is_some() }
+
+    // register the next key will be stored in while waiting
+    pub fn waiting_register(&self)->Option<usize>{ self.waiting }
+
+    pub fn rom_hash(&self)->u32{ self.rom_hash }
+
+    // the rom is loaded at 0x200
+    pub fn rom_size(&self)->usize{ self.rom_size }
+
+    pub fn labels(&self)->&[(String, u16)]{ &self.labels }
+    pub fn get_label(&self, name: &str)->Option<u16>{
+        return self.labels.iter().find(| (label, _) | label.eq_ignore_ascii_case(name)).map(| (_, address) | *address);
+    }
+
+    // restarts the random numbers of Cxkk from seed, runs with the same seed and input are identical
+    pub fn seed(&self)->u64{ self.seed }
+    pub fn set_seed(&mut self, seed: u64){
+        self.seed = seed;
+        self.random.reseed(seed);
+    }
+
+    // swaps the generator, e.g. for VipRandom, which starts over from the current seed
+    pub fn set_random(&mut self, mut random: Box<dyn Random>){
+        random.reseed(self.seed);
+        self.random = random;
+    }
+
+    pub fn save_state(&self)->Vec<u8>{ state::save(self) }
+    pub fn load_state(&mut self, data: &[u8])->Result<(), ChipError>{ state::load(self, data) }
+
+    pub fn cpu(&self)->&CPU{ &self.cpu }
+    pub fn cpu_mut(&mut self)->&mut CPU{ &mut self.cpu }
+
+    pub fn memory(&self)->&Memory{ &self.memory }
+    pub fn memory_mut(&mut self)->&mut Memory{ &mut self.memory }
+
+    pub fn screen(&self)->&Screen{ &self.screen }
+    pub fn keys(&self)->&KeyPad{ &self.keys }
+    pub fn keys_mut(&mut self)->&mut KeyPad{ &mut self.keys }
+
+    pub fn audio(&self)->&Audio{ &self.audio }
+    pub fn audio_mut(&mut self)->&mut Audio{ &mut self.audio }
+
+    // renders the samples for the current sound timer state
+    pub fn render_audio(&mut self, out: &mut [f32]){
+        let playing = self.cpu.timers().get_sound() > 0;
+        self.audio.render(playing, out);
+    }
+
+    pub fn play(&mut self, samples: usize, sink: &mut dyn Sink){
+        let mut buffer = vec![0.0; samples];
+        self.render_audio(&mut buffer);
+        sink.write(&buffer);
+    }
+
+    // last opcode fetched by step
+    pub fn opcode(&self)->&Opcode{ &self.opcode }
+
+    pub fn press_key(&mut self, key: usize){ self.keys.press(key); }
+    pub fn release_key(&mut self, key: usize){ self.keys.release(key); }
+
+    // presses exactly the keys in the bitmask, releasing the others
+    pub fn set_keys(&mut self, mask: u16){
+        for key in 0..16{
+            if mask & (1 << key) != 0 { self.press_key(key); } else { self.release_key(key); }
+        }
+    }
+
+    // runs up to count instructions, stopping early once the chip is no longer running
+    pub fn run(&mut self, count: usize)-> Result<(), ChipError> {
+        for _ in 0..count{
+            if !self.is_running(){
+                break;
+            }
+            self.step()?;
+        }
+        return Ok(());
+    }
+
+    // one 60 Hz frame: the instructions, then a single tick of the delay and sound timers.
+    // Nothing happens, the timers included, unless the chip is running.
+    pub fn frame(&mut self, instructions: usize)-> Result<(), ChipError> {
+        if !self.is_running(){
+            return Ok(());
+        }
+        self.run(instructions)?;
+        self.end_frame();
+        return Ok(());
+    }
+
+    // the end of a frame for runners that execute the instructions themselves, like the debugger
+    pub fn end_frame(&mut self){
+        self.cpu.update();
+        self.memory.end_frame();
+        self.vblank();
+    }
+
+    // executes a single instruction, also while paused or stopped at a breakpoint so the debugger can step.
+    // A fault is returned and kept as the state of the chip until it is resumed or reset.
+    pub fn step(&mut self)-> Result<(), ChipError> {
+        if self.halted || self.fault.is_some(){
+            return Ok(());
+        }
+        self.breakpoint = None;
+        self.instructions += 1;
+        let result = self.execute();
+        if let Err(error) = &result{
+            self.fault = Some(error.clone());
+        }
+        return result;
+    }
+
+    fn execute(&mut self)-> Result<(), ChipError> {
+        if let Some(x) = self.waiting{
+            let key = if self.cpu.get_quirks().wait_release { self.keys.take_release() } else { self.keys.take_press() };
+            if let Some(key) = key{
+                self.cpu.set_register(x, key as u8);
+                self.waiting = None;
+            }
+            return Ok(());
+        }
+        *self.opcode = Opcode::new(self.cpu.fetch(self.memory.as_ref())?);
+        let opcode: &Opcode = &self.opcode;
+        match opcode & 0xf000{
             0x0000 =>{
-                match &opcode & 0x00ff{
+                match opcode & 0x00ff{
                     0x00e0 => self.cpu.clear_screen(self.screen.as_mut()), // clear screen
-                    0x00ee => self.cpu.ret(), // pop stack pointer
-                    _ => { self.cpu.invalid(&opcode); return false; }
+                    0x00ee => self.cpu.ret()?, // pop stack pointer
+                    0x00fb => self.cpu.scroll_right(self.screen.as_mut()),  // scroll the display right by 4 pixels
+                    0x00fc => self.cpu.scroll_left(self.screen.as_mut()),   // scroll the display left by 4 pixels
+                    0x00fd => self.halted = true,        // exit the interpreter
+                    0x00fe => self.cpu.lores(self.screen.as_mut()),         // switch to the 64x32 display
+                    0x00ff => self.cpu.hires(self.screen.as_mut()),         // switch to the 128x64 display
+                    code if code & 0x00f0 == 0x00c0 => self.cpu.scroll_down(opcode, self.screen.as_mut()), // scroll the display down by n lines
+                    code if code & 0x00f0 == 0x00d0 => self.cpu.scroll_up(opcode, self.screen.as_mut()),   // scroll the display up by n lines
+                    _ => self.cpu.invalid(opcode)?,
                 }
             },
-            0x1000 => self.cpu.jump(&opcode),      // jump to address in opcode
-            0x2000 => self.cpu.call(&opcode),      // jump to address in opcode,
-            0x3000 => self.cpu.if_eq(&opcode),      //skip next instruction if vx is equal to kk
-            0x4000 => self.cpu.if_not_eq(&opcode),   //skip next instruction if vx is not equal to kk
-            0x5000 => self.cpu.if_eq_reg(&opcode),   //skip next instruction if vx is equal to vy
-            0x6000 => self.cpu.set(&opcode),       // set vx = kk
-            0x7000 => self.cpu.add(&opcode),       // 7XNN - add kk to vx
+            0x1000 => self.cpu.jump(opcode),      // jump to address in opcode
+            0x2000 => self.cpu.call(opcode)?,      // jump to address in opcode,
+            0x3000 => self.cpu.if_eq(opcode, self.memory.as_ref())?,      //skip next instruction if vx is equal to kk
+            0x4000 => self.cpu.if_not_eq(opcode, self.memory.as_ref())?,   //skip next instruction if vx is not equal to kk
+            0x5000 =>{
+                match opcode & 0x000f{
+                    0x0000 => self.cpu.if_eq_reg(opcode, self.memory.as_ref())?,   //skip next instruction if vx is equal to vy
+                    0x0002 => self.cpu.save_range(opcode, self.memory.as_mut())?,  // store vx through vy in memory starting at location I
+                    0x0003 => self.cpu.load_range(opcode, self.memory.as_ref())?,  // read vx through vy from memory starting at location I
+                    _ => self.cpu.invalid(opcode)?,
+                }
+            },
+            0x6000 => self.cpu.set(opcode),       // set vx = kk
+            0x7000 => self.cpu.add(opcode),       // 7XNN - add kk to vx
             0x8000 =>{
-                match &opcode & 0x000f{
-                    0x0000 => self.cpu.assign(&opcode),    // set vx to vy
-                    0x0001 => self.cpu.bit_or(&opcode),     // vx = vx or vy
-                    0x0002 => self.cpu.bit_and(&opcode),    // vx = vx and vy
-                    0x0003 => self.cpu.bit_xor(&opcode),    // vx = vx xor vy
-                    0x0004 => self.cpu.add_reg(&opcode),    // vx = vx + vy, vf = carry
-                    0x0005 => self.cpu.sub_reg(&opcode),    // vx = vx-vy vf-not borrow if vx > vy vf = 1 else vf = 0
-                    0x0006 => self.cpu.shift_right(&opcode),// SHR vx{, vy}  vx = vx shr 1
-                    0x0007 => self.cpu.sub_copy(&opcode),   // vx = vy-vx vf-not borrow if vy > vx vf = 1 else vf = 0
-                    0x000e => self.cpu.shift_left(&opcode),
-                    _ => { self.cpu.invalid(&opcode); return false; }
+                match opcode & 0x000f{
+                    0x0000 => self.cpu.assign(opcode),    // set vx to vy
+                    0x0001 => self.cpu.bit_or(opcode),     // vx = vx or vy
+                    0x0002 => self.cpu.bit_and(opcode),    // vx = vx and vy
+                    0x0003 => self.cpu.bit_xor(opcode),    // vx = vx xor vy
+                    0x0004 => self.cpu.add_reg(opcode),    // vx = vx + vy, vf = carry
+                    0x0005 => self.cpu.sub_reg(opcode),    // vx = vx-vy vf-not borrow if vx > vy vf = 1 else vf = 0
+                    0x0006 => self.cpu.shift_right(opcode),// SHR vx{, vy}  vx = vx shr 1
+                    0x0007 => self.cpu.sub_copy(opcode),   // vx = vy-vx vf-not borrow if vy > vx vf = 1 else vf = 0
+                    0x000e => self.cpu.shift_left(opcode),
+                    _ => self.cpu.invalid(opcode)?,
+                }
+            },
+            0x9000 => self.cpu.if_not_eq_reg(opcode, self.memory.as_ref())?,    // skip next instruction if vx not equal to vy
+            0xa000 => self.cpu.set_i(opcode),          // set i = nnn
+            0xb000 => self.cpu.jump_v0(opcode),
+            0xc000 => self.cpu.and_rand(opcode, self.random.as_mut()),       // vx = random byte and kk
+            0xd000 =>{ //display n-byte sprite starting at memory location I at (vx/vy), set vf=collision
+                if self.drawn && self.cpu.get_quirks().display_wait{
+                    return Ok(());
                 }
+                self.cpu.draw(opcode, self.screen.as_mut(), self.memory.as_ref())?;
+                self.drawn = true;
             },
-            0x9000 => self.cpu.if_not_eq_reg(&opcode),    // skip next instruction if vx not equal to vy
-            0xa000 => self.cpu.set_i(&opcode),          // set i = nnn
-            0xb000 => self.cpu.jump_v0(&opcode),
-            0xc000 => self.cpu.and_rand(&opcode),       // vx = random byte and kk
-            0xd000 => self.cpu.draw(&opcode, self.screen.as_mut(), self.memory.as_ref()), //display n-byte sprite starting at memory location I at (vx/vy), set vf=collision
             0xe000 =>{ 
-                match &opcode & 0x000f{
-                    0x000e => self.cpu.if_key(&opcode, self.keys.as_ref()), // skip new instruction if keys with the value of vx is pressed
-                    0x0001 => self.cpu.if_not_key(&opcode, self.keys.as_ref()), // skip new instruction if keys with the value of vx is not pressed
-                    _ => { self.cpu.invalid(&opcode); return false; }
+                match opcode & 0x000f{
+                    0x000e => self.cpu.if_key(opcode, self.keys.as_ref(), self.memory.as_ref())?, // skip new instruction if keys with the value of vx is pressed
+                    0x0001 => self.cpu.if_not_key(opcode, self.keys.as_ref(), self.memory.as_ref())?, // skip new instruction if keys with the value of vx is not pressed
+                    _ => self.cpu.invalid(opcode)?,
                 }
             },
             0xf000 =>{
-                match &opcode & 0x00ff{
-                    0x0007 => self.cpu.get_delay(&opcode), // get vx = delay timer table
-                    0x000a => self.cpu.wait_key(&opcode, self.keys.as_mut()),
-                    0x0015 => self.cpu.set_delay(&opcode),      // set delay timer = vx,
-                    0x0018 => self.cpu.set_sound(&opcode),      // set sound timer = vx
-                    0x001e => self.cpu.add_i(&opcode),          // I = I + vx
-                    0x0029 => self.cpu.get_font(&opcode),       // set i = location of 5 bit sprite for digit vx
-                    0x0030 => self.cpu.get_font16(&opcode),     // set i = location of 10 bit sprite for digit vx    
-                    0x0033 => self.cpu.bcd(&opcode, self.memory.as_mut()),           // store BCD representation of vx in memory location I, I + 1, I + 2
-                    0x0055 => self.cpu.reg_dump(&opcode, self.memory.as_mut()),       // read register v0 through vx in memory starting at location I
-                    0x0065 => self.cpu.reg_load(&opcode, self.memory.as_ref()),       // read register v0 through vx in memory starting at location I
-                    //0x0075 => self.cpu.user_reg_dump(&opcode),   // store register v0 through vx in memory starting at location I
-                    //0x0085 => self.cpu.user_reg_load(&opcode),   // read register v0 through vx in memory starting at location I
-                    _ => { self.cpu.invalid(&opcode); return false; }
+                match opcode & 0x00ff{
+                    0x0000 if opcode & 0x0f00 == 0 => self.cpu.set_i_long(self.memory.as_ref())?, // set i = the next 16-bit word
+                    0x0001 => self.cpu.plane(opcode, self.screen.as_mut()),  // select the bit-planes used for drawing
+                    0x0002 if opcode & 0x0f00 == 0 => self.cpu.load_pattern(self.audio.as_mut(), self.memory.as_ref())?, // load the 16 byte audio pattern from I
+                    0x0007 => self.cpu.get_delay(opcode), // get vx = delay timer table
+                    0x000a => self.waiting = Some(self.cpu.wait_key(opcode, self.keys.as_mut())), // wait for a key and store it in vx
+                    0x0015 => self.cpu.set_delay(opcode),      // set delay timer = vx,
+                    0x0018 => self.cpu.set_sound(opcode),      // set sound timer = vx
+                    0x001e => self.cpu.add_i(opcode),          // I = I + vx
+                    0x0029 => self.cpu.get_font(opcode),       // set i = location of 5 bit sprite for digit vx
+                    0x0030 => self.cpu.get_font16(opcode),     // set i = location of 10 bit sprite for digit vx    
+                    0x003a => self.cpu.set_pitch(opcode, self.audio.as_mut()),     // set the audio pitch register = vx
+                    0x0033 => self.cpu.bcd(opcode, self.memory.as_mut())?,           // store BCD representation of vx in memory location I, I + 1, I + 2
+                    0x0055 => self.cpu.reg_dump(opcode, self.memory.as_mut())?,       // read register v0 through vx in memory starting at location I
+                    0x0065 => self.cpu.reg_load(opcode, self.memory.as_ref())?,       // read register v0 through vx in memory starting at location I
+                    0x0075 => self.cpu.user_reg_dump(opcode),   // store register v0 through vx in the user flags
+                    0x0085 => self.cpu.user_reg_load(opcode),   // read register v0 through vx from the user flags
+                    _ => self.cpu.invalid(opcode)?,
                 }
             },
-            _ => { self.cpu.invalid(&opcode); return false; }
+            _ => self.cpu.invalid(opcode)?,
         }
-        self.cpu.update();
-        return true;
+        return Ok(());
     }
 }
 
-pub fn start(file: &str){
-    let event_loop = EventLoop::new();
-    let window = WindowBuilder::new().with_title("Chip-8 Emulator").with_inner_size(glutin::dpi::LogicalSize::new(800, 480));
-    let context = unsafe {
-        let context = ContextBuilder::new().build_windowed(window, &event_loop).unwrap();
-        context.make_current().unwrap()
-    };
+impl Default for Chip{
+    fn default()->Self{ Chip::new() }
+}
 
-    gl::load_with(| symbol | context.get_proc_address(symbol) as *const _);
 
-    let mut chip = Chip::new(Screen::new(800, 480));
+#[cfg(test)]
+mod tests{
+    use super::*;
 
-    unsafe {
-        LAST_TIME = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs_f64();
-        gl::PolygonMode(gl::FRONT_AND_BACK, gl::LINE);
-        gl::FrontFace(gl::CW);
-        gl::CullFace(gl::BACK);
-        gl::Enable(gl::CULL_FACE);
+    fn load(rom: &[u8])->Chip{
+        let mut chip = Chip::new();
+        chip.load_rom(rom).unwrap();
+        return chip;
+    }
 
-        chip.reset();
-        chip.load(file);
-    }
-
-    let mut ok = true;
-
-    event_loop.run(move | event, _, control_flow| {   
-        match event {
-            Event::LoopDestroyed => return,
-            Event::WindowEvent{ event, ..} => match event{
-                WindowEvent::CloseRequested => {
-                    unsafe{
-                        chip.screen.dispose();
-                    }
-                    *control_flow = ControlFlow::Exit
-                },
-                WindowEvent::Resized(size) => {
-                    unsafe{ gl::Viewport(0, 0, size.width as i32, size.height as i32); }
-                    context.resize(size);
-                    chip.screen.resize(size.width, size.height);
-                },
-                WindowEvent::KeyboardInput { input, .. } =>{
-                    println!("{:?}", input);
-                },
-                __ => {}
-            },
-            _ =>{}
-        }
+    #[test]
+    fn waiting_pausing_and_breakpoints_take_turns_in_the_state(){
+        // F00A 00FD
+        let mut chip = load(&[0xf0, 0x0a, 0x00, 0xfd]);
+        assert_eq!(chip.state(), ChipState::Running);
+        chip.step().unwrap();
+        assert_eq!(chip.state(), ChipState::WaitingForKey);
+        assert!(chip.is_running());
 
-        unsafe {
-            let current = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs_f64();
-            DELTA_TIME = current - LAST_TIME;
-            LAST_TIME = current;
-            gl::ClearColor(0.0, 0.0, 0.2, 1.0);
-            gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
-            
-            if chip.is_loaded() && ok{
-                ok = chip.run();
-            }
-            chip.screen.render();
-        }
-        context.swap_buffers().unwrap();
-    });
-}
+        // a pause outranks the wait, a breakpoint outranks the pause
+        chip.pause();
+        assert_eq!(chip.state(), ChipState::Paused);
+        assert!(!chip.is_running());
+        chip.break_at(0x202);
+        assert_eq!(chip.state(), ChipState::BreakpointHit(0x202));
+        chip.resume();
+        assert_eq!(chip.state(), ChipState::WaitingForKey);
 
-/*pub fn start(file: &str, debug: bool){
-    let mut running = true;
-    let mut monitor: Option<PistonWindow> = if debug{
-        Option::Some(WindowSettings::new("monitor", [800, 480]).build().unwrap())
-    }else{
-        Option::None
-    };
+        chip.press_key(5);
+        chip.release_key(5);
+        chip.step().unwrap();
+        assert_eq!((chip.state(), chip.cpu().get_register(0)), (ChipState::Running, 5));
+        chip.step().unwrap();
+        assert_eq!(chip.state(), ChipState::Halted);
 
-    let mut main: PistonWindow = WindowSettings::new("Chip-8", [800, 480]).build().unwrap();
+        // a halted program stays halted, through a pause or a resume
+        chip.pause();
+        assert_eq!(chip.state(), ChipState::Halted);
+        chip.resume();
+        assert_eq!(chip.state(), ChipState::Halted);
+    }
 
-    let mut chip = Chip::new(Screen::new(800, 480));
-    chip.reset();
-    chip.load(file);
+    #[test]
+    fn faults_outrank_every_other_state_until_resumed(){
+        // 00EE on an empty stack
+        let mut chip = load(&[0x00, 0xee]);
+        assert_eq!(chip.step(), Err(ChipError::StackUnderflow));
+        chip.pause();
+        chip.break_at(0x200);
+        assert_eq!(chip.state(), ChipState::Faulted(ChipError::StackUnderflow));
+        // nothing more runs while faulted
+        chip.step().unwrap();
+        assert_eq!(chip.instructions(), 1);
+        chip.resume();
+        assert_eq!(chip.state(), ChipState::Running);
+    }
 
-    while running{
-        if let Some(event) = main.next(){
-            if chip.is_loaded(){
-                chip.run();
-            }
-            chip.screen.render(&mut main, &event);
-            if monitor.is_some(){
-                monitor.as_mut().unwrap().next();
-            }
-        }else{
-            running = false;
-        }
+    #[test]
+    fn reset_clears_every_state(){
+        let mut chip = load(&[0xf0, 0x0a]);
+        chip.step().unwrap();
+        chip.pause();
+        chip.break_at(0x202);
+        chip.reset();
+        assert_eq!(chip.state(), ChipState::Running);
+        assert!(!chip.is_paused() && !chip.is_waiting());
+
+        let mut chip = load(&[0x00, 0xfd]);
+        chip.step().unwrap();
+        chip.reset();
+        assert_eq!(chip.state(), ChipState::Running);
     }
-}*/
\ No newline at end of file
+}
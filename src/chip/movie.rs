@@ -0,0 +1,276 @@
+use std::fs;
+
+use crate::chip::{ Chip, Quirks };
+use crate::chip::error::ChipError;
+use crate::chip::state::{ StateWriter, StateReader };
+
+// Movies record the keypad of every frame so that a run can be played back exactly, along with a hash
+// of the screen and registers at the end of each frame to find the first frame where playback diverges.
+// Besides the keys held, a frame keeps the presses and releases Fx0A had not taken when it started,
+// a key tapped between two frames is never held at a frame boundary but still ends a wait.
+// Recording and playback both start from a freshly loaded rom, the seed and quirks are restored from the movie.
+//
+//   offset  size    field
+//   0       4       magic "C8MV"
+//   4       2       format version
+//   6       4       crc32 of the rom
+//   10      8       random seed
+//   18      4       instructions per frame
+//   22      12      quirks, as in version 4 save states
+//   34      4       frame count n
+//   38      n x 10  frames: bitmasks of the keys held (2), pressed (2) and released (2),
+//                   crc32 of pixels, v0-vf, I and pc (4)
+//   38 + 10n 4      crc32 of everything before it
+pub const MAGIC: &[u8; 4] = b"C8MV";
+pub const VERSION: u16 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MovieFrame{ pub keys: u16, pub pressed: u16, pub released: u16, pub hash: u32 }
+
+pub struct Movie{
+    rom_hash: u32, seed: u64, instructions_per_frame: usize, quirks: Quirks,
+    frames: Vec<MovieFrame>,
+    playing: bool, position: usize, keys: (u16, u16, u16),
+    desync: Option<usize>   // the frame playback diverged on
+}
+
+// what a frame is checked against on playback
+pub fn frame_hash(chip: &Chip)->u32{
+    let mut hasher = crc32fast::Hasher::new();
+    for row in chip.screen().pixels().iter(){
+        hasher.update(row);
+    }
+    for x in 0..16{
+        hasher.update(&[chip.cpu().get_register(x)]);
+    }
+    hasher.update(&chip.cpu().get_index().to_le_bytes());
+    hasher.update(&chip.cpu().get_pc().to_le_bytes());
+    return hasher.finalize();
+}
+
+impl Movie{
+    // starts recording, restarting the random numbers so that playback draws the same ones
+    pub fn record(chip: &mut Chip, instructions_per_frame: usize)->Self{
+        let seed = chip.seed();
+        chip.set_seed(seed);
+        Movie{
+            rom_hash: chip.rom_hash(), seed, instructions_per_frame, quirks: *chip.quirks(),
+            frames: Vec::new(), playing: false, position: 0, keys: (0, 0, 0), desync: None
+        }
+    }
+
+    // rewinds the movie and sets the chip up to play it back
+    pub fn play(&mut self, chip: &mut Chip)->Result<(), ChipError>{
+        if chip.rom_hash() != self.rom_hash{
+            return Err(ChipError::RomMismatch);
+        }
+        chip.set_quirks(self.quirks);
+        chip.set_seed(self.seed);
+        self.playing = true;
+        self.position = 0;
+        self.desync = None;
+        Ok(())
+    }
+
+    pub fn is_playing(&self)->bool{ self.playing }
+
+    // playback is over once every frame was checked, the keypad is then left to the player
+    pub fn is_finished(&self)->bool{ self.playing && self.position >= self.frames.len() }
+
+    pub fn desync(&self)->Option<usize>{ self.desync }
+
+    pub fn get_instructions_per_frame(&self)->usize{ self.instructions_per_frame }
+    pub fn seed(&self)->u64{ self.seed }
+    pub fn frames(&self)->&[MovieFrame]{ &self.frames }
+
+    // index of the next frame to be recorded or played
+    pub fn position(&self)->usize{ if self.playing { self.position } else { self.frames.len() } }
+
+    // called before the frame runs: keeps the keys and their events for recording, or puts back the recorded ones
+    pub fn before(&mut self, chip: &mut Chip){
+        if !self.playing{
+            let (pressed, released) = chip.keys().events();
+            self.keys = (chip.keys().mask(), pressed, released);
+        }else if let Some(frame) = self.frames.get(self.position){
+            chip.keys_mut().set_mask(frame.keys);
+            chip.keys_mut().set_events(frame.pressed, frame.released);
+        }
+    }
+
+    // Called once the frame ran, fails with the frame number at the first frame that differs from the recording.
+    // Playback ends there, the error is only returned once and the keypad goes back to the player.
+    pub fn after(&mut self, chip: &Chip)->Result<(), ChipError>{
+        let hash = frame_hash(chip);
+        if !self.playing{
+            let (keys, pressed, released) = self.keys;
+            self.frames.push(MovieFrame{ keys, pressed, released, hash });
+            return Ok(());
+        }
+        if let Some(frame) = self.frames.get(self.position){
+            if frame.hash != hash{
+                let desync = self.position;
+                self.desync = Some(desync);
+                self.position = self.frames.len();
+                return Err(ChipError::Desync{ frame: desync });
+            }
+            self.position += 1;
+        }
+        Ok(())
+    }
+
+    pub fn save(&self)->Vec<u8>{
+        let mut movie = StateWriter::new();
+        movie.bytes(MAGIC);
+        movie.u16(VERSION);
+        movie.u32(self.rom_hash);
+        movie.u64(self.seed);
+        movie.u32(self.instructions_per_frame as u32);
+        self.quirks.save_state(&mut movie);
+        movie.u32(self.frames.len() as u32);
+        for frame in self.frames.iter(){
+            movie.u16(frame.keys);
+            movie.u16(frame.pressed);
+            movie.u16(frame.released);
+            movie.u32(frame.hash);
+        }
+        let crc = crc32fast::hash(movie.data());
+        movie.u32(crc);
+        return movie.data().to_vec();
+    }
+
+    pub fn load(data: &[u8])->Result<Self, ChipError>{
+        if data.len() < 10 || &data[0..4] != MAGIC{
+            return Err(ChipError::InvalidState(String::from("not a movie")));
+        }
+        let (body, crc) = data.split_at(data.len() - 4);
+        if crc32fast::hash(body) != u32::from_le_bytes(crc.try_into().unwrap()){
+            return Err(ChipError::InvalidState(String::from("checksum mismatch, the movie is corrupted")));
+        }
+
+        let version = u16::from_le_bytes([body[4], body[5]]);
+        if version != VERSION{
+            return Err(ChipError::InvalidState(format!("unsupported movie version {}", version)));
+        }
+
+        // the reader goes by the save state version whose quirks the movie holds
+        let mut movie = StateReader::new(&body[6..], 4);
+        let rom_hash = movie.u32()?;
+        let seed = movie.u64()?;
+        let instructions_per_frame = movie.u32()? as usize;
+        let quirks = Quirks::load_state(&mut movie)?;
+        let count = movie.u32()? as usize;
+        let mut frames = Vec::new();
+        for _ in 0..count{
+            frames.push(MovieFrame{ keys: movie.u16()?, pressed: movie.u16()?, released: movie.u16()?, hash: movie.u32()? });
+        }
+        if !movie.is_empty(){
+            return Err(ChipError::InvalidState(String::from("unexpected data after the frames")));
+        }
+        return Ok(Movie{ rom_hash, seed, instructions_per_frame, quirks, frames, playing: false, position: 0, keys: (0, 0, 0), desync: None });
+    }
+
+    pub fn save_file(&self, path: &str)->Result<(), ChipError>{
+        fs::write(path, self.save())?;
+        Ok(())
+    }
+
+    pub fn load_file(path: &str)->Result<Self, ChipError>{
+        return Movie::load(&fs::read(path)?);
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    // v1 counts the frames key 0 is held down on, its digit is drawn every frame
+    const ROM: [u8; 14] = [0x00, 0xe0, 0x61, 0x00, 0xe1, 0xa1, 0x71, 0x01, 0xf1, 0x29, 0xd0, 0x15, 0x12, 0x00];
+
+    fn chip()->Chip{
+        let mut chip = Chip::new();
+        chip.load_rom(&ROM).unwrap();
+        return chip;
+    }
+
+    fn record(frames: &[u16])->Movie{
+        let mut chip = chip();
+        let mut movie = Movie::record(&mut chip, 7);
+        for keys in frames{
+            chip.set_keys(*keys);
+            movie.before(&mut chip);
+            chip.frame(7).unwrap();
+            movie.after(&chip).unwrap();
+        }
+        return movie;
+    }
+
+    // the result of checking each frame played
+    fn play(movie: &mut Movie, chip: &mut Chip, frames: usize)->Vec<Result<(), ChipError>>{
+        let mut results = Vec::new();
+        for _ in 0..frames{
+            movie.before(chip);
+            chip.frame(7).unwrap();
+            results.push(movie.after(chip));
+        }
+        return results;
+    }
+
+    #[test]
+    fn playback_matches_the_recording(){
+        let mut movie = Movie::load(&record(&[0, 1, 1, 0, 1]).save()).unwrap();
+        let mut chip = chip();
+        movie.play(&mut chip).unwrap();
+        assert!(play(&mut movie, &mut chip, 5).iter().all(| result | result.is_ok()));
+        assert!(movie.is_finished());
+        assert_eq!(movie.desync(), None);
+    }
+
+    #[test]
+    fn desync_is_reported_once_and_ends_playback(){
+        let mut movie = record(&[0, 1, 1, 0, 1]);
+        let mut chip = chip();
+        movie.play(&mut chip).unwrap();
+        chip.memory_mut().poke(0x203, 0x05).unwrap();   // v1 starts from 5
+        let results = play(&mut movie, &mut chip, 5);
+        assert_eq!(results[0], Err(ChipError::Desync{ frame: 0 }));
+        assert!(results[1..].iter().all(| result | result.is_ok()));
+        assert!(movie.is_finished());
+        assert_eq!(movie.desync(), Some(0));
+        assert!(chip.is_running());
+    }
+
+    #[test]
+    fn key_tapped_between_frames_ends_the_wait_on_playback(){
+        // F10A waits for a key into v1, then 1202 loops
+        let rom = [0xf1, 0x0a, 0x12, 0x02];
+        let mut chip = Chip::new();
+        chip.load_rom(&rom).unwrap();
+        let mut movie = Movie::record(&mut chip, 7);
+        for frame in 0..4{
+            if frame == 2{
+                chip.press_key(7);
+                chip.release_key(7);
+            }
+            movie.before(&mut chip);
+            chip.frame(7).unwrap();
+            movie.after(&chip).unwrap();
+        }
+        assert_eq!(chip.cpu().get_register(1), 7);
+
+        let mut movie = Movie::load(&movie.save()).unwrap();
+        let mut chip = Chip::new();
+        chip.load_rom(&rom).unwrap();
+        movie.play(&mut chip).unwrap();
+        assert!(play(&mut movie, &mut chip, 4).iter().all(| result | result.is_ok()));
+        assert_eq!(chip.cpu().get_register(1), 7);
+        assert!(!chip.is_waiting());
+    }
+
+    #[test]
+    fn movie_of_another_rom_is_refused(){
+        let mut movie = record(&[0]);
+        let mut other = Chip::new();
+        other.load_rom(&[0x12, 0x00]).unwrap();
+        assert_eq!(movie.play(&mut other), Err(ChipError::RomMismatch));
+    }
+}
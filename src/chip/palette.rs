@@ -0,0 +1,170 @@
+use std::fmt::{ Display, Formatter };
+use std::fs;
+use std::path::Path;
+
+use crate::chip::error::ChipError;
+use crate::chip::utils::parse_color;
+
+// The colours the screen is drawn in, one for each combination of the two xo-chip planes:
+// the background, the first plane, the second plane and both at once. Plain chip-8 only draws in the first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Palette{ pub colors: [[u8; 3]; 4] }
+
+const fn rgb(value: u32)->[u8; 3]{ [(value >> 16) as u8, (value >> 8) as u8, value as u8] }
+
+const PRESETS: [(&str, [u32; 4]); 7] = [
+    ("blue", [0x000033, 0x3366ff, 0x1a3399, 0x99b3ff]),
+    ("green", [0x0a1a0a, 0x33ff66, 0x1a802f, 0xb3ffc6]),            // green phosphor
+    ("amber", [0x1a0f00, 0xffb000, 0x805800, 0xffe0a0]),
+    ("lcd", [0x9bbc0f, 0x0f380f, 0x8bac0f, 0x306230]),              // dark pixels on a greenish lcd
+    ("high-contrast", [0x000000, 0xffffff, 0xffff00, 0x00ffff]),
+    ("colour-blind", [0x000000, 0x56b4e9, 0xe69f00, 0xf0e442]),     // sky blue, orange and yellow stay apart for every kind of colour blindness
+    ("octo", [0x996600, 0xffcc00, 0xff6600, 0x662200])              // the xo-chip colours of octo
+];
+
+impl Palette{
+    pub fn new(colors: [[u8; 3]; 4])->Self{ Palette{ colors } }
+
+    // the names of the presets, in the order they are cycled through
+    pub fn names()->Vec<&'static str>{ PRESETS.iter().map(| (name, _) | *name).collect() }
+
+    pub fn preset(name: &str)->Option<Self>{
+        let name = match name.to_lowercase().as_str(){
+            "green-phosphor" | "phosphor" => "green",
+            "colour-blind-friendly" | "color-blind" | "colorblind" | "colourblind" => "colour-blind",
+            "contrast" => "high-contrast",
+            name => return PRESETS.iter().find(| (preset, _) | *preset == name).map(| (_, colors) | Palette::from_values(colors))
+        };
+        return Palette::preset(name);
+    }
+
+    fn from_values(values: &[u32; 4])->Self{
+        return Palette{ colors: [rgb(values[0]), rgb(values[1]), rgb(values[2]), rgb(values[3])] };
+    }
+
+    // a preset by name, or colours as rrggbb separated by commas in the order of the planes:
+    // background and foreground, or all four. With two the second plane is drawn halfway between them.
+    pub fn parse(text: &str)->Option<Self>{
+        if let Some(palette) = Palette::preset(text.trim()){
+            return Some(palette);
+        }
+        let colors: Option<Vec<[u8; 3]>> = text.split(',').map(parse_color).collect();
+        return match colors?.as_slice(){
+            [background, foreground] =>{
+                let half = [0, 1, 2].map(| index | ((background[index] as u16 + foreground[index] as u16) / 2) as u8);
+                Some(Palette{ colors: [*background, *foreground, half, *foreground] })
+            },
+            [background, first, second, both] => Some(Palette{ colors: [*background, *first, *second, *both] }),
+            _ => None
+        };
+    }
+
+    // the preset this palette is, if any
+    pub fn name(&self)->Option<&'static str>{
+        return PRESETS.iter().find(| (_, colors) | Palette::from_values(colors) == *self).map(| (name, _) | *name);
+    }
+
+    pub fn load_file(path: &str)->Result<Self, ChipError>{
+        let text = fs::read_to_string(path)?;
+        return Palette::parse(&text).ok_or_else(|| ChipError::InvalidPalette(format!("{} holds {}, expected a preset or colours as rrggbb", path, text.trim())));
+    }
+
+    pub fn save_file(&self, path: &str)->Result<(), ChipError>{
+        fs::write(path, format!("{}\n", self))?;
+        Ok(())
+    }
+
+    // the palette kept for a rom, if it has one
+    pub fn for_rom(rom: &str)->Option<Result<Self, ChipError>>{
+        let path = rom_path(rom);
+        if !Path::new(&path).exists(){
+            return None;
+        }
+        return Some(Palette::load_file(&path));
+    }
+}
+
+// a palette of its own is kept next to the rom, games/pong.ch8 gets games/pong.ch8.palette
+pub fn rom_path(rom: &str)->String{ format!("{}.palette", rom) }
+
+impl Default for Palette{
+    fn default()->Self{ Palette::from_values(&PRESETS[0].1) }
+}
+
+// a preset by its name, any other palette as its four colours
+impl Display for Palette{
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result{
+        if let Some(name) = self.name(){
+            return write!(f, "{}", name);
+        }
+        let colors: Vec<String> = self.colors.iter().map(| color | format!("{:02x}{:02x}{:02x}", color[0], color[1], color[2])).collect();
+        write!(f, "{}", colors.join(","))
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    // a path in the temporary directory only this test process uses
+    fn temporary(name: &str)->String{
+        return std::env::temp_dir().join(format!("chip-8-{}-{}", std::process::id(), name)).to_string_lossy().into_owned();
+    }
+
+    #[test]
+    fn presets_are_found_by_name_and_alias(){
+        assert_eq!(Palette::names().len(), PRESETS.len());
+        for name in Palette::names(){
+            let palette = Palette::preset(name).unwrap();
+            assert_eq!(palette.name(), Some(name));
+            assert_eq!(Palette::parse(&format!(" {}\n", name.to_uppercase())), Some(palette));
+        }
+        assert_eq!(Palette::preset("phosphor"), Palette::preset("green"));
+        assert_eq!(Palette::preset("Colorblind"), Palette::preset("colour-blind"));
+        assert_eq!(Palette::preset("contrast"), Palette::preset("high-contrast"));
+        assert_eq!(Palette::preset("sepia"), None);
+        assert_eq!(Palette::default(), Palette::preset("blue").unwrap());
+        assert_eq!(Palette::preset("lcd").unwrap().colors[0], [0x9b, 0xbc, 0x0f]);
+    }
+
+    #[test]
+    fn colours_are_parsed_in_the_order_of_the_planes(){
+        let palette = Palette::parse("000000,#ffffff").unwrap();
+        // with two colours the second plane is halfway and both planes are the foreground
+        assert_eq!(palette.colors, [[0, 0, 0], [0xff, 0xff, 0xff], [0x7f, 0x7f, 0x7f], [0xff, 0xff, 0xff]]);
+        let palette = Palette::parse("102030, 405060 ,708090,a0B0c0").unwrap();
+        assert_eq!(palette.colors, [[0x10, 0x20, 0x30], [0x40, 0x50, 0x60], [0x70, 0x80, 0x90], [0xa0, 0xb0, 0xc0]]);
+        assert_eq!(palette.name(), None);
+
+        for bad in ["", "000000", "000000,ffffff,888888", "000000,fffff", "000000,gggggg", "000000,ffffff,", "0x000000,ffffff"]{
+            assert_eq!(Palette::parse(bad), None, "{}", bad);
+        }
+    }
+
+    #[test]
+    fn palettes_print_as_what_parses_back(){
+        assert_eq!(Palette::preset("amber").unwrap().to_string(), "amber");
+        let palette = Palette::new([[0x01, 0x23, 0x45], [0x67, 0x89, 0xab], [0xcd, 0xef, 0x00], [0xff, 0x0f, 0xf0]]);
+        assert_eq!(palette.to_string(), "012345,6789ab,cdef00,ff0ff0");
+        for palette in [palette, Palette::preset("octo").unwrap()]{
+            assert_eq!(Palette::parse(&palette.to_string()), Some(palette));
+        }
+    }
+
+    #[test]
+    fn palettes_are_kept_next_to_their_rom(){
+        assert_eq!(rom_path("games/pong.ch8"), "games/pong.ch8.palette");
+        let rom = temporary("pong.ch8");
+        assert!(Palette::for_rom(&rom).is_none());
+
+        let palette = Palette::parse("112233,445566").unwrap();
+        palette.save_file(&rom_path(&rom)).unwrap();
+        assert_eq!(Palette::for_rom(&rom), Some(Ok(palette)));
+
+        // a file that holds no palette is an error of its own, not one of reading it
+        std::fs::write(rom_path(&rom), "sepia\n").unwrap();
+        let message = format!("{} holds sepia, expected a preset or colours as rrggbb", rom_path(&rom));
+        assert_eq!(Palette::for_rom(&rom), Some(Err(ChipError::InvalidPalette(message))));
+        std::fs::remove_file(rom_path(&rom)).unwrap();
+    }
+}
@@ -0,0 +1,224 @@
+use crate::chip::error::ChipError;
+use crate::chip::state::{ StateWriter, StateReader };
+
+// how Fx55/Fx65 leave the index register once the registers are stored or loaded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexIncrement{ Unchanged, X, XPlusOne }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quirks{
+    pub shift_vy: bool,                 // 8xy6/8xyE shift vy into vx instead of shifting vx in place
+    pub load_store: IndexIncrement,     // Fx55/Fx65 increment of I
+    pub jump_vx: bool,                  // Bxnn jumps to xnn + vx instead of nnn + v0
+    pub clip_sprites: bool,             // Dxyn clips sprites at the screen edges instead of wrapping them
+    pub vf_reset: bool,                 // 8xy1/8xy2/8xy3 reset vf to 0
+    pub add_i_overflow: bool,           // Fx1E sets vf when I overflows past 0xfff
+    pub display_wait: bool,             // Dxyn waits for the vertical blank, at most one sprite per frame
+    pub wait_release: bool,             // Fx0A resolves when a key is released, as on the VIP, rather than pressed
+    pub memory_size: usize,             // addressable memory, 64 KiB on XO-CHIP
+}
+
+impl Quirks{
+    pub fn cosmac_vip()->Self{
+        Quirks{
+            shift_vy: true, load_store: IndexIncrement::XPlusOne, jump_vx: false, clip_sprites: true,
+            vf_reset: true, add_i_overflow: false, display_wait: true, wait_release: true, memory_size: 0x1000
+        }
+    }
+
+    pub fn chip48()->Self{
+        Quirks{
+            shift_vy: false, load_store: IndexIncrement::X, jump_vx: true, clip_sprites: true,
+            vf_reset: false, add_i_overflow: false, display_wait: false, wait_release: false, memory_size: 0x1000
+        }
+    }
+
+    pub fn schip10()->Self{ Quirks::chip48() }
+
+    pub fn schip11()->Self{
+        Quirks{ load_store: IndexIncrement::Unchanged, ..Quirks::schip10() }
+    }
+
+    pub fn schip_modern()->Self{
+        Quirks{
+            shift_vy: false, load_store: IndexIncrement::Unchanged, jump_vx: true, clip_sprites: true,
+            vf_reset: false, add_i_overflow: false, display_wait: false, wait_release: false, memory_size: 0x1000
+        }
+    }
+
+    pub fn xo_chip()->Self{
+        Quirks{
+            shift_vy: true, load_store: IndexIncrement::XPlusOne, jump_vx: false, clip_sprites: false,
+            vf_reset: false, add_i_overflow: false, display_wait: false, wait_release: false, memory_size: 0x10000
+        }
+    }
+
+    // looks a preset up by the names used on the command line and in rom settings
+    pub fn preset(name: &str)->Option<Self>{
+        return match name.to_lowercase().as_str(){
+            "vip" | "cosmac" | "cosmac-vip" | "chip-8" | "chip8" => Some(Quirks::cosmac_vip()),
+            "chip-48" | "chip48" => Some(Quirks::chip48()),
+            "schip-1.0" | "schip10" => Some(Quirks::schip10()),
+            "schip-1.1" | "schip11" => Some(Quirks::schip11()),
+            "schip" | "schip-modern" => Some(Quirks::schip_modern()),
+            "xo-chip" | "xochip" => Some(Quirks::xo_chip()),
+            _ => None
+        };
+    }
+
+    pub fn save_state(&self, state: &mut StateWriter){
+        for flag in [self.shift_vy, self.jump_vx, self.clip_sprites, self.vf_reset, self.add_i_overflow, self.display_wait]{
+            state.bool(flag);
+        }
+        state.u8(match self.load_store{
+            IndexIncrement::Unchanged => 0,
+            IndexIncrement::X => 1,
+            IndexIncrement::XPlusOne => 2
+        });
+        state.u32(self.memory_size as u32);
+        state.bool(self.wait_release);
+    }
+
+    pub fn load_state(state: &mut StateReader)->Result<Self, ChipError>{
+        let mut quirks = Quirks::default();
+        for flag in [&mut quirks.shift_vy, &mut quirks.jump_vx, &mut quirks.clip_sprites, &mut quirks.vf_reset, &mut quirks.add_i_overflow, &mut quirks.display_wait]{
+            *flag = state.bool()?;
+        }
+        quirks.load_store = match state.u8()?{
+            0 => IndexIncrement::Unchanged,
+            1 => IndexIncrement::X,
+            2 => IndexIncrement::XPlusOne,
+            _ => return Err(ChipError::InvalidState(String::from("unknown index increment quirk")))
+        };
+        quirks.memory_size = state.u32()? as usize;
+        if quirks.memory_size < 0x200 || quirks.memory_size > 0x10000{
+            return Err(ChipError::InvalidState(format!("memory size {} out of range", quirks.memory_size)));
+        }
+        quirks.wait_release = if state.version() >= 4 { state.bool()? } else { Quirks::default().wait_release };
+        return Ok(quirks);
+    }
+}
+
+impl Default for Quirks{
+    fn default()->Self{ Quirks::cosmac_vip() }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::chip::Chip;
+
+    // runs count instructions of rom on the quirks
+    fn run(quirks: Quirks, rom: &[u8], count: usize)->Chip{
+        let mut chip = Chip::with_quirks(quirks);
+        chip.load_rom(rom).unwrap();
+        chip.run(count).unwrap();
+        return chip;
+    }
+
+    fn registers(chip: &Chip, x: &[usize])->Vec<u8>{ x.iter().map(| x | chip.cpu().get_register(*x)).collect() }
+
+    #[test]
+    fn shifts_take_vy_or_shift_vx_in_place(){
+        // 6009 6103 8016 / 6009 6181 801E
+        let (right, left) = ([0x60, 0x09, 0x61, 0x03, 0x80, 0x16], [0x60, 0x09, 0x61, 0x81, 0x80, 0x1e]);
+        let on = Quirks{ shift_vy: true, ..Quirks::cosmac_vip() };
+        let off = Quirks{ shift_vy: false, ..Quirks::cosmac_vip() };
+        assert_eq!(registers(&run(on, &right, 3), &[0, 0xf]), [0x01, 1]);
+        assert_eq!(registers(&run(off, &right, 3), &[0, 0xf]), [0x04, 1]);
+        assert_eq!(registers(&run(on, &left, 3), &[0, 0xf]), [0x02, 1]);
+        assert_eq!(registers(&run(off, &left, 3), &[0, 0xf]), [0x12, 0]);
+    }
+
+    #[test]
+    fn loads_and_stores_leave_the_index_by_the_quirk(){
+        // A300 F255 / A300 F265
+        for (load_store, index) in [(IndexIncrement::Unchanged, 0x300), (IndexIncrement::X, 0x302), (IndexIncrement::XPlusOne, 0x303)]{
+            let quirks = Quirks{ load_store, ..Quirks::cosmac_vip() };
+            assert_eq!(run(quirks, &[0xa3, 0x00, 0xf2, 0x55], 2).cpu().get_index(), index);
+            assert_eq!(run(quirks, &[0xa3, 0x00, 0xf2, 0x65], 2).cpu().get_index(), index);
+        }
+    }
+
+    #[test]
+    fn bnnn_adds_v0_or_vx(){
+        // 6004 6110 B120
+        let rom = [0x60, 0x04, 0x61, 0x10, 0xb1, 0x20];
+        assert_eq!(run(Quirks{ jump_vx: false, ..Quirks::cosmac_vip() }, &rom, 3).cpu().get_pc(), 0x124);
+        assert_eq!(run(Quirks{ jump_vx: true, ..Quirks::cosmac_vip() }, &rom, 3).cpu().get_pc(), 0x130);
+    }
+
+    #[test]
+    fn logic_opcodes_reset_vf_by_the_quirk(){
+        // 6F05 8011, 8012 and 8013
+        for logic in [0x11, 0x12, 0x13]{
+            let rom = [0x6f, 0x05, 0x80, logic];
+            assert_eq!(run(Quirks{ vf_reset: true, ..Quirks::cosmac_vip() }, &rom, 2).cpu().get_register(0xf), 0);
+            assert_eq!(run(Quirks{ vf_reset: false, ..Quirks::cosmac_vip() }, &rom, 2).cpu().get_register(0xf), 5);
+        }
+    }
+
+    #[test]
+    fn display_wait_allows_one_sprite_a_frame(){
+        // A000 D015 D015 6001
+        let rom = [0xa0, 0x00, 0xd0, 0x15, 0xd0, 0x15, 0x60, 0x01];
+        let mut chip = run(Quirks{ display_wait: true, ..Quirks::cosmac_vip() }, &rom, 4);
+        // the second sprite waits on the vertical blank
+        assert_eq!(chip.cpu().get_pc(), 0x204);
+        assert_eq!(chip.screen().get(0, 0), 1);
+        chip.end_frame();
+        chip.run(2).unwrap();
+        assert_eq!((chip.cpu().get_pc(), chip.screen().get(0, 0)), (0x208, 0));
+
+        let chip = run(Quirks{ display_wait: false, ..Quirks::cosmac_vip() }, &rom, 4);
+        assert_eq!((chip.cpu().get_pc(), chip.screen().get(0, 0)), (0x208, 0));
+    }
+
+    #[test]
+    fn sprites_past_the_edges_are_clipped_or_wrapped(){
+        // 603E 611E A000 D015: the 0 of the font at 62, 30
+        let rom = [0x60, 0x3e, 0x61, 0x1e, 0xa0, 0x00, 0xd0, 0x15];
+        let chip = run(Quirks{ clip_sprites: true, ..Quirks::cosmac_vip() }, &rom, 4);
+        assert_eq!([chip.screen().get(62, 30), chip.screen().get(63, 30), chip.screen().get(0, 30), chip.screen().get(62, 0)], [1, 1, 0, 0]);
+        let chip = run(Quirks{ clip_sprites: false, ..Quirks::cosmac_vip() }, &rom, 4);
+        assert_eq!([chip.screen().get(62, 30), chip.screen().get(63, 30), chip.screen().get(0, 30), chip.screen().get(62, 0)], [1, 1, 1, 1]);
+
+        // the starting position wraps either way
+        let rom = [0x60, 0x41, 0xa0, 0x00, 0xd0, 0x15];
+        assert_eq!(run(Quirks{ clip_sprites: true, ..Quirks::cosmac_vip() }, &rom, 3).screen().get(1, 0), 1);
+    }
+
+    #[test]
+    fn fx1e_sets_vf_on_overflow_by_the_quirk(){
+        // 6F05 AFFF 6001 F01E
+        let rom = [0x6f, 0x05, 0xaf, 0xff, 0x60, 0x01, 0xf0, 0x1e];
+        let chip = run(Quirks{ add_i_overflow: true, ..Quirks::cosmac_vip() }, &rom, 4);
+        assert_eq!((chip.cpu().get_index(), chip.cpu().get_register(0xf)), (0x1000, 1));
+        let chip = run(Quirks{ add_i_overflow: false, ..Quirks::cosmac_vip() }, &rom, 4);
+        assert_eq!((chip.cpu().get_index(), chip.cpu().get_register(0xf)), (0x1000, 5));
+
+        // no overflow clears it
+        let rom = [0x6f, 0x05, 0xa1, 0x00, 0x60, 0x01, 0xf0, 0x1e];
+        assert_eq!(run(Quirks{ add_i_overflow: true, ..Quirks::cosmac_vip() }, &rom, 4).cpu().get_register(0xf), 0);
+    }
+
+    #[test]
+    fn presets_are_found_by_any_of_their_names(){
+        assert_eq!(Quirks::default(), Quirks::cosmac_vip());
+        for name in ["vip", "COSMAC", "cosmac-vip", "chip-8", "chip8"]{
+            assert_eq!(Quirks::preset(name), Some(Quirks::cosmac_vip()));
+        }
+        assert_eq!(Quirks::preset("Chip-48"), Some(Quirks::chip48()));
+        assert_eq!(Quirks::preset("schip-1.0"), Some(Quirks::schip10()));
+        assert_eq!(Quirks::preset("schip11"), Some(Quirks::schip11()));
+        assert_eq!(Quirks::preset("schip"), Some(Quirks::schip_modern()));
+        assert_eq!(Quirks::preset("xochip"), Some(Quirks::xo_chip()));
+        assert_eq!(Quirks::preset("octo"), None);
+
+        // schip 1.1 stopped incrementing I, xo-chip has 64K of memory and only the vip waits
+        assert_eq!(Quirks{ load_store: IndexIncrement::X, ..Quirks::schip11() }, Quirks::schip10());
+        assert_eq!(Quirks::xo_chip().memory_size, 0x10000);
+        assert!(Quirks::cosmac_vip().display_wait && Quirks::cosmac_vip().wait_release);
+        assert!(!Quirks::schip_modern().display_wait && !Quirks::xo_chip().wait_release);
+    }
+}
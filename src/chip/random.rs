@@ -0,0 +1,150 @@
+use crate::chip::error::ChipError;
+use crate::chip::state::{ StateWriter, StateReader };
+
+// source of the random bytes used by Cxkk
+pub trait Random{
+    fn next(&mut self)->u8;
+
+    // restarts the sequence, the same seed always gives the same bytes
+    fn reseed(&mut self, seed: u64);
+
+    // the generator state is part of save states, so a restored run draws the same numbers again
+    fn save_state(&self, state: &mut StateWriter);
+    fn load_state(&mut self, state: &mut StateReader)->Result<(), ChipError>;
+}
+
+// xorshift64*, the default generator
+pub struct XorShift{ state: u64 }
+
+impl XorShift{
+    pub fn new(seed: u64)->Self{
+        let mut random = XorShift{ state: 0 };
+        random.reseed(seed);
+        return random;
+    }
+}
+
+impl Random for XorShift{
+    fn next(&mut self)->u8{
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        return (self.state.wrapping_mul(0x2545f4914f6cdd1d) >> 56) as u8;
+    }
+
+    // xorshift never leaves the all zero state, so the seed is mixed into a non-zero one
+    fn reseed(&mut self, seed: u64){
+        self.state = seed.wrapping_mul(0x9e3779b97f4a7c15) | 1;
+    }
+
+    fn save_state(&self, state: &mut StateWriter){ state.u64(self.state); }
+    fn load_state(&mut self, state: &mut StateReader)->Result<(), ChipError>{
+        self.state = state.u64()?;
+        Ok(())
+    }
+}
+
+// The generator of the original COSMAC VIP interpreter: the low seed byte is incremented and used
+// to index page 0x100, which held the interpreter's own code, the byte found there is added to the
+// high seed byte and the 9 bit sum shifted right (the 1802 SHRC) becomes the new high byte and the result.
+// The interpreter is not in chip memory, below 0x200 there is only the font, so the generator keeps the page itself.
+// PAGE is a fixed table standing in for it, the numbers only match real hardware when with_page is given
+// a dump of 0x100-0x1ff of the VIP interpreter.
+pub struct VipRandom{ low: u8, high: u8, page: Box<[u8; 256]> }
+
+pub const PAGE: [u8; 256] = page();
+
+// the bytes of xorshift32 from a fixed start, spread over the whole range like code bytes are
+const fn page()->[u8; 256]{
+    let (mut page, mut state, mut i) = ([0u8; 256], 0x2545f491u32, 0);
+    while i < 256{
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        page[i] = (state >> 24) as u8;
+        i += 1;
+    }
+    return page;
+}
+
+impl VipRandom{
+    pub fn new(seed: u64)->Self{ VipRandom::with_page(seed, PAGE) }
+
+    pub fn with_page(seed: u64, page: [u8; 256])->Self{
+        let mut random = VipRandom{ low: 0, high: 0, page: Box::new(page) };
+        random.reseed(seed);
+        return random;
+    }
+}
+
+impl Random for VipRandom{
+    fn next(&mut self)->u8{
+        self.low = self.low.wrapping_add(1);
+        let sum = self.page[self.low as usize] as u16 + self.high as u16;
+        self.high = (sum >> 1) as u8;
+        return self.high;
+    }
+
+    fn reseed(&mut self, seed: u64){
+        self.low = seed as u8;
+        self.high = (seed >> 8) as u8;
+    }
+
+    fn save_state(&self, state: &mut StateWriter){
+        state.u8(self.low);
+        state.u8(self.high);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader)->Result<(), ChipError>{
+        self.low = state.u8()?;
+        self.high = state.u8()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::chip::Chip;
+
+    #[test]
+    fn vip_generator_averages_the_page_into_the_high_byte(){
+        let mut page = [0u8; 256];
+        for (i, byte) in page.iter_mut().enumerate(){
+            *byte = i as u8;
+        }
+        // low 0x02 and high 0x01: (3 + 1) / 2, (4 + 2) / 2, (5 + 3) / 2 ...
+        let mut random = VipRandom::with_page(0x0102, page);
+        let numbers: Vec<u8> = (0..4).map(|_| random.next()).collect();
+        assert_eq!(numbers, [2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn vip_generator_keeps_drawing_numbers_on_a_chip(){
+        // C0FF, then 1200 back to it
+        let mut chip = Chip::new();
+        chip.load_rom(&[0xc0, 0xff, 0x12, 0x00]).unwrap();
+        chip.set_seed(0x1234);
+        chip.set_random(Box::new(VipRandom::new(0)));
+        let mut numbers = Vec::new();
+        for _ in 0..20{
+            chip.run(2).unwrap();
+            numbers.push(chip.cpu().get_register(0));
+        }
+        numbers.sort();
+        numbers.dedup();
+        assert!(numbers.len() > 10);
+    }
+
+    #[test]
+    fn same_seed_gives_the_same_numbers(){
+        let mut first = VipRandom::new(0xbeef);
+        let mut second = VipRandom::new(0xbeef);
+        let numbers: Vec<u8> = (0..300).map(|_| first.next()).collect();
+        assert_eq!(numbers, (0..300).map(|_| second.next()).collect::<Vec<u8>>());
+        first.reseed(0xbeef);
+        assert_eq!(numbers, (0..300).map(|_| first.next()).collect::<Vec<u8>>());
+        let mut other = VipRandom::new(0xbeee);
+        assert_ne!(numbers, (0..300).map(|_| other.next()).collect::<Vec<u8>>());
+    }
+}
@@ -0,0 +1,66 @@
+use crate::chip::{ Palette, Screen };
+
+mod opengl;
+pub use opengl::GlRenderer;
+
+mod software;
+pub use software::SoftwareRenderer;
+
+mod preset;
+pub use preset::{ Preset, Pass };
+
+mod painter;
+pub use painter::Painter;
+
+mod shader;
+use shader::Shader;
+
+// Releases what a renderer holds outside of rust, the objects of the GL context it was made in.
+// It is called once, before the context goes away.
+pub trait Disposable{ fn dispose(&mut self); }
+
+// Draws the pixels of a screen onto a target of some size, in the colours of the palette. The screen keeps
+// its aspect in the middle of the target and black letterbox bars fill the rest.
+pub trait Renderer: Disposable{
+    // the size of the target in physical pixels
+    fn resize(&mut self, width: u32, height: u32);
+    fn set_palette(&mut self, palette: Palette);
+    // grow the screen in whole pixels only, leaving wider bars around it
+    fn set_integer_scale(&mut self, integer: bool);
+    // the post-processing passes the screen goes through, the renderer may not be able to run them
+    fn set_preset(&mut self, preset: &Preset)->Result<(), String>;
+    fn render(&mut self, screen: &Screen);
+}
+
+// Where the screen goes in a target of width x height pixels, as x, y, width and height: centred and as large as
+// the target allows at the screen's own aspect, in whole multiples of its pixels when integer is set and it fits at least once.
+pub fn viewport(target: [u32; 2], screen: [u32; 2], integer: bool)->[i32; 4]{
+    let [width, height] = target;
+    let fit = (width as f64 / screen[0] as f64).min(height as f64 / screen[1] as f64);
+    let scale = if integer && fit >= 1.0 { fit.floor() } else { fit };
+    let size = [(screen[0] as f64 * scale).round() as i32, (screen[1] as f64 * scale).round() as i32];
+    return [(width as i32 - size[0]) / 2, (height as i32 - size[1]) / 2, size[0], size[1]];
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn viewports_scale_in_whole_pixels_when_they_can(){
+        assert_eq!(viewport([640, 320], [64, 32], true), [0, 0, 640, 320]);
+        // 10.9 times across, 12.5 down
+        assert_eq!(viewport([700, 400], [64, 32], true), [30, 40, 640, 320]);
+        assert_eq!(viewport([700, 400], [128, 64], true), [30, 40, 640, 320]);
+        assert_eq!(viewport([300, 600], [64, 32], true), [22, 236, 256, 128]);
+        // too small to fit once, the screen shrinks
+        assert_eq!(viewport([32, 32], [64, 32], true), [0, 8, 32, 16]);
+    }
+
+    #[test]
+    fn fitted_viewports_fill_one_side(){
+        assert_eq!(viewport([700, 400], [64, 32], false), [0, 25, 700, 350]);
+        assert_eq!(viewport([300, 600], [64, 32], false), [0, 225, 300, 150]);
+        assert_eq!(viewport([1000, 400], [128, 64], false), [100, 0, 800, 400]);
+    }
+}
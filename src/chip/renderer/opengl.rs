@@ -0,0 +1,243 @@
+use crate::chip::{ Palette, Screen };
+use crate::chip::renderer::{ Disposable, Preset, Renderer, Shader, viewport };
+use gl::types::{ GLenum, GLfloat, GLsizei, GLsizeiptr, GLuint };
+
+use std::mem;
+use std::os::raw::c_void;
+
+// the texture is as large as the hires screen, lores only fills its top left quarter
+const WIDTH: usize = 128;
+const HEIGHT: usize = 64;
+
+// a triangle strip over the whole viewport: position, then texture coordinates with the first row on top
+const QUAD: [GLfloat; 16] = [
+    -1.0,  1.0,  0.0, 0.0,
+     1.0,  1.0,  1.0, 0.0,
+    -1.0, -1.0,  0.0, 1.0,
+     1.0, -1.0,  1.0, 1.0
+];
+
+// the vertex shader of every post-processing pass
+const POST: &str = include_str!("../../../shaders/post.vs");
+
+// a post-processing pass, sampling what was drawn before it with filter
+struct Pass{ shader: Shader, filter: GLenum }
+
+// a texture passes draw into, the size of the screen's area in the window
+struct Target{ framebuffer: GLuint, texture: GLuint }
+
+impl Target{
+    unsafe fn new(width: i32, height: i32)->Self{
+        let (mut framebuffer, mut texture) = (0, 0);
+        gl::GenTextures(1, &mut texture);
+        gl::BindTexture(gl::TEXTURE_2D, texture);
+        gl::TexImage2D(gl::TEXTURE_2D, 0, gl::RGBA8 as i32, width, height, 0, gl::RGBA, gl::UNSIGNED_BYTE, std::ptr::null());
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+        gl::BindTexture(gl::TEXTURE_2D, 0);
+
+        gl::GenFramebuffers(1, &mut framebuffer);
+        gl::BindFramebuffer(gl::FRAMEBUFFER, framebuffer);
+        gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::TEXTURE_2D, texture, 0);
+        gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+        Target{ framebuffer, texture }
+    }
+
+    unsafe fn dispose(&self){
+        gl::DeleteFramebuffers(1, &self.framebuffer);
+        gl::DeleteTextures(1, &self.texture);
+    }
+}
+
+// The screen drawn as one texture on a quad. The pixels, the plane bits of every pixel, are streamed to the
+// texture each frame and looked up in the palette by the fragment shader, sampled nearest so they stay sharp.
+// With a preset the screen is drawn into a texture instead, which goes through its passes in turn, each drawing
+// into the next texture and the last into the window. It draws to the current GL context, which has to outlive it.
+pub struct GlRenderer{
+    vao: GLuint, vbo: GLuint, texture: GLuint, shader: Box<Shader>, size: [u32; 2], integer: bool,
+    passes: Vec<Pass>,
+    targets: Vec<Target>, target_size: [i32; 2]     // one for the screen and one for each pass but the last
+}
+impl GlRenderer{
+    /// # Safety
+    /// The GL context has to be current, and stay alive until the renderer is disposed.
+    pub unsafe fn new()->Self{
+        let (mut vao, mut vbo, mut texture, shader):(GLuint, GLuint, GLuint, Shader) = ( 0, 0, 0, Shader::new());
+
+        gl::GenVertexArrays(1, &mut vao);
+        gl::BindVertexArray(vao);
+
+        gl::GenBuffers(1, &mut vbo);
+        gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+        gl::BufferData(gl::ARRAY_BUFFER, mem::size_of_val(&QUAD) as GLsizeiptr, QUAD.as_ptr() as *const c_void, gl::STATIC_DRAW);
+
+        let stride = 4 * mem::size_of::<GLfloat>() as GLsizei;
+        gl::EnableVertexAttribArray(0);
+        gl::VertexAttribPointer(0, 2, gl::FLOAT, gl::FALSE, stride, std::ptr::null());
+        gl::EnableVertexAttribArray(1);
+        gl::VertexAttribPointer(1, 2, gl::FLOAT, gl::FALSE, stride, (2 * mem::size_of::<GLfloat>()) as *const c_void);
+
+        gl::BindVertexArray(0);
+
+        gl::GenTextures(1, &mut texture);
+        gl::BindTexture(gl::TEXTURE_2D, texture);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+        gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+        gl::TexImage2D(gl::TEXTURE_2D, 0, gl::R8 as i32, WIDTH as GLsizei, HEIGHT as GLsizei, 0, gl::RED, gl::UNSIGNED_BYTE, std::ptr::null());
+        gl::BindTexture(gl::TEXTURE_2D, 0);
+
+        shader.set_uniform_int("screen", 0);
+        GlRenderer{ vao, vbo, texture, shader: Box::new(shader), size: [0, 0], integer: true, passes: Vec::new(), targets: Vec::new(), target_size: [0, 0] }
+    }
+
+    // the targets follow the size of the screen's area and the number of passes
+    unsafe fn prepare(&mut self, width: i32, height: i32){
+        if self.target_size == [width, height] && self.targets.len() == self.passes.len(){
+            return;
+        }
+        for target in self.targets.drain(..){
+            target.dispose();
+        }
+        for _ in 0..self.passes.len(){
+            self.targets.push(Target::new(width, height));
+        }
+        self.target_size = [width, height];
+    }
+
+    unsafe fn post_process(&mut self, screen: &Screen, area: [i32; 4]){
+        let [x, y, width, height] = area;
+        self.prepare(width, height);
+        gl::BindFramebuffer(gl::FRAMEBUFFER, self.targets[0].framebuffer);
+        gl::Viewport(0, 0, width, height);
+        self.draw(screen);
+
+        let screen_size = [screen.width() as f32, screen.height() as f32];
+        for (index, pass) in self.passes.iter().enumerate(){
+            let last = index + 1 == self.passes.len();
+            if last{
+                gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+                gl::Viewport(x, y, width, height);
+            }else{
+                gl::BindFramebuffer(gl::FRAMEBUFFER, self.targets[index + 1].framebuffer);
+                gl::Viewport(0, 0, width, height);
+            }
+
+            gl::ActiveTexture(gl::TEXTURE0);
+            gl::BindTexture(gl::TEXTURE_2D, self.targets[index].texture);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, pass.filter as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, pass.filter as i32);
+
+            pass.shader.bind();
+            pass.shader.set_uniform_int("source", 0);
+            pass.shader.set_uniform_vec2("sourceSize", width as f32, height as f32);
+            pass.shader.set_uniform_vec2("outputSize", width as f32, height as f32);
+            pass.shader.set_uniform_vec2("screenSize", screen_size[0], screen_size[1]);
+            gl::BindVertexArray(self.vao);
+            gl::DrawArrays(gl::TRIANGLE_STRIP, 0, 4);
+        }
+        gl::BindVertexArray(0);
+        gl::BindTexture(gl::TEXTURE_2D, 0);
+    }
+
+    // streams the pixels to the texture and draws them over the current viewport
+    unsafe fn draw(&self, screen: &Screen){
+        gl::ActiveTexture(gl::TEXTURE0);
+        gl::BindTexture(gl::TEXTURE_2D, self.texture);
+        gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+        gl::TexSubImage2D(gl::TEXTURE_2D, 0, 0, 0, WIDTH as GLsizei, HEIGHT as GLsizei, gl::RED, gl::UNSIGNED_BYTE, screen.pixels().as_ptr() as *const c_void);
+
+        self.shader.bind();
+        self.shader.set_uniform_vec2("area", screen.width() as f32 / WIDTH as f32, screen.height() as f32 / HEIGHT as f32);
+        gl::BindVertexArray(self.vao);
+        gl::DrawArrays(gl::TRIANGLE_STRIP, 0, 4);
+        gl::BindVertexArray(0);
+        gl::BindTexture(gl::TEXTURE_2D, 0);
+    }
+}
+
+impl Renderer for GlRenderer{
+    fn resize(&mut self, width: u32, height: u32){ self.size = [width, height]; }
+
+    fn set_palette(&mut self, palette: Palette){
+        for (index, color) in palette.colors.iter().enumerate(){
+            let color = [color[0] as f32 / 255.0, color[1] as f32 / 255.0, color[2] as f32 / 255.0];
+            unsafe{ self.shader.set_uniform_vec3(&format!("palette[{}]", index), color); }
+        }
+    }
+
+    fn set_integer_scale(&mut self, integer: bool){ self.integer = integer; }
+
+    // every pass is built before any is used, a preset with a broken shader leaves the chain as it was
+    fn set_preset(&mut self, preset: &Preset)->Result<(), String>{
+        let mut passes = Vec::new();
+        for pass in preset.passes(){
+            match unsafe{ Shader::build(POST, None, &pass.fragment) }{
+                Ok(shader) => passes.push(Pass{ shader, filter: if pass.linear { gl::LINEAR } else { gl::NEAREST } }),
+                Err(error) =>{
+                    for pass in passes{
+                        unsafe{ pass.shader.dispose(); }
+                    }
+                    return Err(format!("{}: {}", pass.name, error));
+                }
+            }
+        }
+        unsafe{
+            for pass in self.passes.drain(..){
+                pass.shader.dispose();
+            }
+        }
+        self.passes = passes;
+        return Ok(());
+    }
+
+    // the viewport is left covering the whole target for whatever is drawn next
+    fn render(&mut self, screen: &Screen){
+        let [width, height] = self.size;
+        let [x, y, w, h] = viewport(self.size, [screen.width() as u32, screen.height() as u32], self.integer);
+        unsafe{
+            gl::Viewport(0, 0, width as i32, height as i32);
+            gl::ClearColor(0.0, 0.0, 0.0, 1.0);
+            gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+
+            if self.passes.is_empty() || w <= 0 || h <= 0{
+                gl::Viewport(x, y, w, h);
+                self.draw(screen);
+            }else{
+                self.post_process(screen, [x, y, w, h]);
+            }
+            gl::Viewport(0, 0, width as i32, height as i32);
+        }
+    }
+}
+
+impl Disposable for GlRenderer{
+    fn dispose(&mut self) {
+        unsafe{
+            if self.texture != 0{
+                gl::BindTexture(gl::TEXTURE_2D, 0);
+                gl::DeleteTextures(1, &self.texture);
+            }
+
+            if self.vbo != 0{
+                gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+                gl::DeleteBuffers( 1, &self.vbo);
+            }
+
+            if self.vao != 0{
+                gl::BindVertexArray(0);
+                gl::DeleteVertexArrays(1, &self.vao);
+            }
+            self.shader.dispose();
+            for pass in self.passes.drain(..){
+                pass.shader.dispose();
+            }
+            for target in self.targets.drain(..){
+                target.dispose();
+            }
+        }
+        (self.texture, self.vbo, self.vao) = (0, 0, 0);
+    }
+}
@@ -0,0 +1,187 @@
+use crate::chip::renderer::Disposable;
+use crate::chip::renderer::Shader;
+use gl::types::GLint;
+use gl::types::GLsizei;
+use gl::types::GLsizeiptr;
+use gl::types::GLuint;
+
+use std::collections::HashMap;
+use std::{ mem, ptr };
+use std::os::raw::c_void;
+
+use egui::{ ClippedPrimitive, Color32, ImageData, Mesh, TextureFilter, TextureId, TexturesDelta };
+use egui::epaint::{ Primitive, Vertex };
+
+// egui hands over premultiplied colours, which are blended as they are
+const VERTEX: &str = "#version 330 core
+
+layout (location = 0) in vec2 position;
+layout (location = 1) in vec2 coordinates;
+layout (location = 2) in vec4 color;
+
+uniform vec2 screenSize;
+
+out vec2 uv;
+out vec4 tint;
+
+void main(){
+    gl_Position = vec4(2.0 * position.x / screenSize.x - 1.0, 1.0 - 2.0 * position.y / screenSize.y, 0.0, 1.0);
+    uv = coordinates;
+    tint = color;
+}";
+
+const FRAGMENT: &str = "#version 330 core
+
+uniform sampler2D sampler;
+
+in vec2 uv;
+in vec4 tint;
+
+out vec4 color;
+
+void main(){
+    color = tint * texture(sampler, uv);
+}";
+
+// draws the meshes egui tessellates, on top of whatever was rendered before
+pub struct Painter{ vao: GLuint, vbo: GLuint, ebo: GLuint, shader: Box<Shader>, textures: HashMap<TextureId, GLuint> }
+
+impl Painter{
+    /// # Safety
+    /// The GL context has to be current, and stay alive until the painter is disposed.
+    pub unsafe fn new()->Self{
+        let (mut vao, mut vbo, mut ebo) = (0, 0, 0);
+        let shader = Shader::from_source(VERTEX, None, FRAGMENT);
+
+        gl::GenVertexArrays(1, &mut vao);
+        gl::BindVertexArray(vao);
+
+        gl::GenBuffers(1, &mut vbo);
+        gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+        gl::GenBuffers(1, &mut ebo);
+        gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, ebo);
+
+        let stride = mem::size_of::<Vertex>() as GLsizei;
+        gl::EnableVertexAttribArray(0);
+        gl::VertexAttribPointer(0, 2, gl::FLOAT, gl::FALSE, stride, ptr::null());
+        gl::EnableVertexAttribArray(1);
+        gl::VertexAttribPointer(1, 2, gl::FLOAT, gl::FALSE, stride, (2 * mem::size_of::<f32>()) as *const c_void);
+        gl::EnableVertexAttribArray(2);
+        gl::VertexAttribPointer(2, 4, gl::UNSIGNED_BYTE, gl::TRUE, stride, (4 * mem::size_of::<f32>()) as *const c_void);
+
+        gl::BindVertexArray(0);
+
+        Painter{ vao, vbo, ebo, shader: Box::new(shader), textures: HashMap::new() }
+    }
+
+    // size is the framebuffer in pixels, egui works in points
+    /// # Safety
+    /// The GL context the painter was made in has to be current.
+    pub unsafe fn paint(&mut self, size: [u32; 2], pixels_per_point: f32, primitives: &[ClippedPrimitive], textures: &TexturesDelta){
+        for (id, delta) in textures.set.iter(){
+            self.set_texture(*id, delta);
+        }
+
+        let mut polygon_mode: [GLint; 2] = [0; 2];
+        gl::GetIntegerv(gl::POLYGON_MODE, polygon_mode.as_mut_ptr());
+        let cull_face = gl::IsEnabled(gl::CULL_FACE) == gl::TRUE;
+        gl::PolygonMode(gl::FRONT_AND_BACK, gl::FILL);
+        gl::Disable(gl::CULL_FACE);
+        gl::Enable(gl::BLEND);
+        gl::BlendFunc(gl::ONE, gl::ONE_MINUS_SRC_ALPHA);
+        gl::Enable(gl::SCISSOR_TEST);
+
+        self.shader.bind();
+        self.shader.set_uniform_vec2("screenSize", size[0] as f32 / pixels_per_point, size[1] as f32 / pixels_per_point);
+        self.shader.set_uniform_int("sampler", 0);
+        gl::ActiveTexture(gl::TEXTURE0);
+        gl::BindVertexArray(self.vao);
+        for primitive in primitives.iter(){
+            if let Primitive::Mesh(mesh) = &primitive.primitive{
+                // the clip rectangle is in points from the top left, the scissor box in pixels from the bottom left
+                let clip = primitive.clip_rect;
+                let left = (clip.min.x * pixels_per_point).round().clamp(0.0, size[0] as f32) as i32;
+                let right = (clip.max.x * pixels_per_point).round().clamp(0.0, size[0] as f32) as i32;
+                let top = (clip.min.y * pixels_per_point).round().clamp(0.0, size[1] as f32) as i32;
+                let bottom = (clip.max.y * pixels_per_point).round().clamp(0.0, size[1] as f32) as i32;
+                if right > left && bottom > top{
+                    gl::Scissor(left, size[1] as i32 - bottom, right - left, bottom - top);
+                    self.draw(mesh);
+                }
+            }
+        }
+        gl::BindVertexArray(0);
+
+        gl::Disable(gl::SCISSOR_TEST);
+        gl::Disable(gl::BLEND);
+        if cull_face{
+            gl::Enable(gl::CULL_FACE);
+        }
+        gl::PolygonMode(gl::FRONT_AND_BACK, polygon_mode[0] as u32);
+
+        for id in textures.free.iter(){
+            if let Some(texture) = self.textures.remove(id){
+                gl::DeleteTextures(1, &texture);
+            }
+        }
+    }
+
+    unsafe fn draw(&self, mesh: &Mesh){
+        let texture = match self.textures.get(&mesh.texture_id){
+            Some(texture) => *texture,
+            None => return
+        };
+        gl::BindTexture(gl::TEXTURE_2D, texture);
+        gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
+        gl::BufferData(gl::ARRAY_BUFFER, mem::size_of_val(mesh.vertices.as_slice()) as GLsizeiptr, mesh.vertices.as_ptr() as *const c_void, gl::STREAM_DRAW);
+        gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, self.ebo);
+        gl::BufferData(gl::ELEMENT_ARRAY_BUFFER, mem::size_of_val(mesh.indices.as_slice()) as GLsizeiptr, mesh.indices.as_ptr() as *const c_void, gl::STREAM_DRAW);
+        gl::DrawElements(gl::TRIANGLES, mesh.indices.len() as GLsizei, gl::UNSIGNED_INT, ptr::null());
+    }
+
+    // uploads a whole texture, or a patch of one when the delta has a position
+    unsafe fn set_texture(&mut self, id: TextureId, delta: &egui::epaint::ImageDelta){
+        let pixels: Vec<Color32> = match &delta.image{
+            ImageData::Color(image) => image.pixels.clone(),
+            ImageData::Font(image) => image.srgba_pixels(1.0).collect()
+        };
+        let [width, height] = delta.image.size();
+
+        let texture = *self.textures.entry(id).or_insert_with(||{
+            let mut texture = 0;
+            gl::GenTextures(1, &mut texture);
+            texture
+        });
+        gl::BindTexture(gl::TEXTURE_2D, texture);
+        let filter = match delta.filter{
+            TextureFilter::Nearest => gl::NEAREST,
+            TextureFilter::Linear => gl::LINEAR
+        } as GLint;
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, filter);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, filter);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as GLint);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as GLint);
+        gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+        let data = pixels.as_ptr() as *const c_void;
+        match delta.pos{
+            Some([x, y]) => gl::TexSubImage2D(gl::TEXTURE_2D, 0, x as GLint, y as GLint, width as GLsizei, height as GLsizei, gl::RGBA, gl::UNSIGNED_BYTE, data),
+            None => gl::TexImage2D(gl::TEXTURE_2D, 0, gl::RGBA8 as GLint, width as GLsizei, height as GLsizei, 0, gl::RGBA, gl::UNSIGNED_BYTE, data)
+        }
+    }
+}
+
+impl Disposable for Painter{
+    fn dispose(&mut self){
+        unsafe{
+            for (_, texture) in self.textures.drain(){
+                gl::DeleteTextures(1, &texture);
+            }
+            self.shader.dispose();
+            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+            gl::DeleteBuffers(1, &self.vbo);
+            gl::DeleteBuffers(1, &self.ebo);
+            gl::BindVertexArray(0);
+            gl::DeleteVertexArrays(1, &self.vao);
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use std::fs;
+use std::path::Path;
+
+use crate::chip::error::ChipError;
+
+// the post-processing shaders built into the binary, by the name presets use for them
+const SHADERS: [(&str, &str); 4] = [
+    ("scanlines", include_str!("../../../shaders/post/scanlines.fs")),
+    ("grid", include_str!("../../../shaders/post/grid.fs")),
+    ("curvature", include_str!("../../../shaders/post/curvature.fs")),
+    ("bloom", include_str!("../../../shaders/post/bloom.fs"))
+];
+
+const PRESETS: [(&str, &str); 5] = [
+    ("none", ""),
+    ("scanlines", include_str!("../../../shaders/presets/scanlines.preset")),
+    ("grid", include_str!("../../../shaders/presets/grid.preset")),
+    ("bloom", include_str!("../../../shaders/presets/bloom.preset")),
+    ("crt", include_str!("../../../shaders/presets/crt.preset"))
+];
+
+// one pass of a chain: the fragment shader, and whether it samples what the pass before drew smoothly or nearest
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pass{ pub name: String, pub fragment: String, pub linear: bool }
+
+// An ordered chain of post-processing passes the screen goes through before it reaches the window.
+// A preset file lists a pass a line, with # starting a comment:
+//
+//   pass bloom linear
+//   pass scanlines
+//   pass shaders/my-effect.fs nearest
+//
+// A shader is one of the built-in ones or the path of a fragment shader, relative to the preset file.
+// Passes get the texture drawn so far as the sampler2D source, along with the vec2 uniforms sourceSize,
+// outputSize and screenSize, the last being the chip-8 resolution.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Preset{ name: String, passes: Vec<Pass> }
+
+impl Preset{
+    // no passes, the screen is drawn straight to the window
+    pub fn none()->Self{ Preset{ name: String::from("none"), passes: Vec::new() } }
+
+    pub fn name(&self)->&str{ &self.name }
+    pub fn passes(&self)->&[Pass]{ &self.passes }
+
+    // the names of the built-in presets, in the order they are cycled through
+    pub fn names()->Vec<&'static str>{ PRESETS.iter().map(| (name, _) | *name).collect() }
+
+    pub fn builtin(name: &str)->Option<Self>{
+        let (name, text) = PRESETS.iter().find(| (preset, _) | preset.eq_ignore_ascii_case(name))?;
+        return Preset::parse(name, text, None).ok();
+    }
+
+    // a built-in preset by its name, or else a preset file
+    pub fn find(name: &str)->Result<Self, ChipError>{
+        if let Some(preset) = Preset::builtin(name){
+            return Ok(preset);
+        }
+        if !Path::new(name).exists(){
+            return Err(ChipError::Io(format!("{} is neither a preset of {} nor a file", name, Preset::names().join(", "))));
+        }
+        return Preset::load_file(name);
+    }
+
+    pub fn load_file(path: &str)->Result<Self, ChipError>{
+        let text = fs::read_to_string(path)?;
+        let name = Path::new(path).file_stem().map_or(path.to_string(), | stem | stem.to_string_lossy().into_owned());
+        return Preset::parse(&name, &text, Path::new(path).parent());
+    }
+
+    // shader paths are looked up from directory, or the working directory without one
+    pub fn parse(name: &str, text: &str, directory: Option<&Path>)->Result<Self, ChipError>{
+        let mut passes = Vec::new();
+        for (index, line) in text.lines().enumerate(){
+            let error = | message: String | ChipError::InvalidPreset{ line: index + 1, message };
+            let words: Vec<&str> = line.split('#').next().unwrap_or("").split_whitespace().collect();
+            let (shader, filter) = match words.as_slice(){
+                [] => continue,
+                ["pass", shader] => (*shader, "nearest"),
+                ["pass", shader, filter] => (*shader, *filter),
+                _ => return Err(error(String::from("expected pass <shader> [nearest|linear]")))
+            };
+            let linear = match filter{
+                "nearest" => false,
+                "linear" => true,
+                _ => return Err(error(format!("{} is neither nearest nor linear", filter)))
+            };
+            let fragment = match SHADERS.iter().find(| (builtin, _) | *builtin == shader){
+                Some((_, source)) => source.to_string(),
+                None =>{
+                    let path = directory.map_or(Path::new(shader).to_path_buf(), | directory | directory.join(shader));
+                    fs::read_to_string(&path).map_err(| io | error(format!("cannot read {}: {}", path.display(), io)))?
+                }
+            };
+            passes.push(Pass{ name: shader.to_string(), fragment, linear });
+        }
+        return Ok(Preset{ name: name.to_string(), passes });
+    }
+}
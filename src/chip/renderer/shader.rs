@@ -0,0 +1,139 @@
+use gl::types::*;
+use std::{ptr, str};
+use std::ffi::CString;
+
+// built into the binary so that it runs from any directory
+const VERTEX: &str = include_str!("../../../shaders/simple.vs");
+const FRAGMENT: &str = include_str!("../../../shaders/simple.fs");
+
+pub struct Shader{ shader_program: u32}
+
+#[allow(dead_code)]
+impl Shader{
+    pub unsafe fn new() -> Self{
+        Shader::from_source(VERTEX, None, FRAGMENT)
+    }
+
+    // builds a program from sources held in memory, the geometry stage is optional.
+    // Errors are printed and leave a program that draws nothing.
+    pub unsafe fn from_source(vertex_data: &str, geometry_data: Option<&str>, fragment_data: &str) -> Self{
+        match Shader::build(vertex_data, geometry_data, fragment_data){
+            Ok(shader) => shader,
+            Err(error) =>{
+                println!("{}", error);
+                Shader{ shader_program: 0 }
+            }
+        }
+    }
+
+    // like from_source, returning what went wrong instead
+    pub unsafe fn build(vertex_data: &str, geometry_data: Option<&str>, fragment_data: &str) -> Result<Self, String>{
+        let vertex_shader = Shader::compile(gl::VERTEX_SHADER, vertex_data);
+        let geometry_shader = geometry_data.map(| data | Shader::compile(gl::GEOMETRY_SHADER, data)).transpose();
+        let fragment_shader = Shader::compile(gl::FRAGMENT_SHADER, fragment_data);
+
+        match (vertex_shader, geometry_shader, fragment_shader){
+            (Ok(vertex_shader), Ok(geometry_shader), Ok(fragment_shader)) =>{
+                let shader_program = Shader::link(vertex_shader, geometry_shader, fragment_shader)?;
+                Ok(Shader { shader_program })
+            },
+            (vertex_shader, geometry_shader, fragment_shader) =>{
+                // the stages that did compile are not needed any more
+                for shader in [vertex_shader.as_ref().ok(), geometry_shader.as_ref().ok().and_then(| shader | shader.as_ref()), fragment_shader.as_ref().ok()].iter().flatten(){
+                    gl::DeleteShader(**shader);
+                }
+                let errors: Vec<String> = [vertex_shader.err(), geometry_shader.err(), fragment_shader.err()].into_iter().flatten().collect();
+                Err(errors.join("\n"))
+            }
+        }
+    }
+
+    unsafe fn compile(shader_type: u32, shader_source:&str) -> Result<u32, String>{
+        // Setup shader compilation checks
+        let mut success = i32::from(gl::FALSE);
+        let mut info_log: Vec<u8> = vec![0; 512 - 1]; // -1 to skip trialing null character
+
+        let shader = gl::CreateShader(shader_type);
+        let c_str_vert = CString::new(shader_source.as_bytes()).map_err(| error | error.to_string())?;
+        gl::ShaderSource(shader, 1, &c_str_vert.as_ptr(), ptr::null());
+        gl::CompileShader(shader);
+
+        // Check for shader compilation errors
+        gl::GetShaderiv(shader, gl::COMPILE_STATUS, &mut success);
+        if success != i32::from(gl::TRUE) {
+            gl::GetShaderInfoLog(shader, 512, ptr::null_mut(), info_log.as_mut_ptr() as *mut GLchar, );
+            gl::DeleteShader(shader);
+            return Err(format!("ERROR::SHADER::COMPILATION_FAILED\n{}", String::from_utf8_lossy(&info_log).trim_end_matches('\0')));
+        }
+        Ok(shader)
+    }
+
+    unsafe fn link(vertex_shader:u32, geometry_shader:Option<u32>, fragment_shader:u32) -> Result<u32, String>{
+        let mut success = i32::from(gl::FALSE);
+        let mut info_log: Vec<u8> = vec![0; 512 - 1]; // -1 to skip trialing null character
+
+        // Link Shaders
+        let shader_program = gl::CreateProgram();
+        gl::AttachShader(shader_program, vertex_shader);
+        if let Some(geometry_shader) = geometry_shader{
+            gl::AttachShader(shader_program, geometry_shader);
+        }
+        gl::AttachShader(shader_program, fragment_shader);
+        gl::LinkProgram(shader_program);
+
+        gl::DeleteShader(vertex_shader);
+        if let Some(geometry_shader) = geometry_shader{
+            gl::DeleteShader(geometry_shader);
+        }
+        gl::DeleteShader(fragment_shader);
+
+        // Check for linking errors
+        gl::GetProgramiv(shader_program, gl::LINK_STATUS, &mut success);
+        if success != i32::from(gl::TRUE) {
+            gl::GetProgramInfoLog(shader_program, 512, ptr::null_mut(), info_log.as_mut_ptr() as *mut GLchar, );
+            gl::DeleteProgram(shader_program);
+            return Err(format!("ERROR::SHADER::PROGRAM::COMPILATION_FAILED\n{}", String::from_utf8_lossy(&info_log).trim_end_matches('\0')));
+        }
+        Ok(shader_program)
+    }
+
+    pub unsafe fn set_uniform_value(&self, name:&str, value: f32){
+        let c_name = CString::new(name).unwrap();
+        let ptr = c_name.as_ptr();
+        let uniform = gl::GetUniformLocation(self.shader_program, ptr);
+        gl::ProgramUniform1f(self.shader_program, uniform, value);
+    }
+
+    pub unsafe fn set_uniform_vec2(&self, name:&str, x: f32, y: f32){
+        let c_name = CString::new(name).unwrap();
+        let uniform = gl::GetUniformLocation(self.shader_program, c_name.as_ptr());
+        gl::ProgramUniform2f(self.shader_program, uniform, x, y);
+    }
+
+    pub unsafe fn set_uniform_vec3(&self, name:&str, value: [f32; 3]){
+        let c_name = CString::new(name).unwrap();
+        let uniform = gl::GetUniformLocation(self.shader_program, c_name.as_ptr());
+        gl::ProgramUniform3f(self.shader_program, uniform, value[0], value[1], value[2]);
+    }
+
+    pub unsafe fn set_uniform_int(&self, name:&str, value: i32){
+        let c_name = CString::new(name).unwrap();
+        let uniform = gl::GetUniformLocation(self.shader_program, c_name.as_ptr());
+        gl::ProgramUniform1i(self.shader_program, uniform, value);
+    }
+
+    pub unsafe fn set_uniform_matrix4(&self, name:&str, matrix: &[[f32; 4]; 4]){
+        let c_name = CString::new(name).unwrap();
+
+       let uniform = gl::GetUniformLocation(self.shader_program, c_name.as_ptr());
+        gl::ProgramUniformMatrix4fv(self.shader_program, uniform, 1, gl::TRUE, matrix.as_ptr() as *const f32);
+    }
+
+    pub unsafe  fn bind(&self){
+        gl::UseProgram(self.shader_program);
+    }
+
+    pub unsafe fn dispose(&self){
+        gl::DeleteProgram(self.shader_program);
+    }
+}
\ No newline at end of file
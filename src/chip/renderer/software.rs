@@ -0,0 +1,140 @@
+use crate::chip::{ Palette, Screen };
+use crate::chip::renderer::{ Disposable, Preset, Renderer, viewport };
+
+// Renders into an RGBA buffer in memory with nearest neighbour scaling, for headless runs and machines without a GPU.
+// Rows go from the top down, four bytes a pixel.
+pub struct SoftwareRenderer{ width: u32, height: u32, pixels: Vec<u8>, palette: Palette, integer: bool }
+impl SoftwareRenderer{
+    pub fn new(width: u32, height: u32)->Self{
+        SoftwareRenderer{ width, height, pixels: vec![0; (width * height * 4) as usize], palette: Palette::default(), integer: true }
+    }
+
+    pub fn width(&self)->u32{ self.width }
+    pub fn height(&self)->u32{ self.height }
+    pub fn pixels(&self)->&[u8]{ &self.pixels }
+
+    fn fill(&mut self, color: [u8; 3]){
+        for pixel in self.pixels.chunks_exact_mut(4){
+            pixel.copy_from_slice(&[color[0], color[1], color[2], 0xff]);
+        }
+    }
+}
+
+impl Renderer for SoftwareRenderer{
+    fn resize(&mut self, width: u32, height: u32){
+        self.width = width;
+        self.height = height;
+        self.pixels.resize((width * height * 4) as usize, 0);
+    }
+
+    fn set_palette(&mut self, palette: Palette){ self.palette = palette; }
+    fn set_integer_scale(&mut self, integer: bool){ self.integer = integer; }
+
+    // shaders need a GPU, the software renderer only draws the plain screen
+    fn set_preset(&mut self, preset: &Preset)->Result<(), String>{
+        if preset.passes().is_empty(){
+            return Ok(());
+        }
+        return Err(format!("the software renderer cannot run the shaders of {}", preset.name()));
+    }
+
+    // every target pixel of the screen's area takes the colour of the screen pixel it falls in
+    fn render(&mut self, screen: &Screen){
+        self.fill([0; 3]);
+        let (columns, rows) = (screen.width(), screen.height());
+        let [x, y, width, height] = viewport([self.width, self.height], [columns as u32, rows as u32], self.integer);
+        for target_y in y.max(0)..(y + height).min(self.height as i32){
+            let row = ((target_y - y) as usize * rows / height as usize).min(rows - 1);
+            for target_x in x.max(0)..(x + width).min(self.width as i32){
+                let column = ((target_x - x) as usize * columns / width as usize).min(columns - 1);
+                let color = self.palette.colors[(screen.get(column, row) & 0x03) as usize];
+                let offset = (target_y as usize * self.width as usize + target_x as usize) * 4;
+                self.pixels[offset..offset + 3].copy_from_slice(&color);
+            }
+        }
+    }
+}
+
+// nothing is held outside of the buffer
+impl Disposable for SoftwareRenderer{
+    fn dispose(&mut self){}
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    const BAR: [u8; 4] = [0, 0, 0, 0xff];
+
+    // the first plane red, the second green, both blue and a background apart from the black bars
+    fn renderer(width: u32, height: u32)->SoftwareRenderer{
+        let mut renderer = SoftwareRenderer::new(width, height);
+        renderer.set_palette(Palette::new([[1, 2, 3], [0xff, 0, 0], [0, 0xff, 0], [0, 0, 0xff]]));
+        return renderer;
+    }
+
+    fn screen()->Screen{
+        let mut screen = Screen::new();
+        screen.set(0, 0);
+        screen.set_planes(2);
+        screen.set(1, 0);
+        screen.set_planes(3);
+        screen.set(63, 31);
+        return screen;
+    }
+
+    fn pixel(renderer: &SoftwareRenderer, x: u32, y: u32)->[u8; 4]{
+        let offset = ((y * renderer.width() + x) * 4) as usize;
+        return renderer.pixels()[offset..offset + 4].try_into().unwrap();
+    }
+
+    #[test]
+    fn screen_pixels_are_drawn_in_the_palette(){
+        let mut renderer = renderer(128, 64);
+        renderer.render(&screen());
+        for (x, y, color) in [(0, 0, [0xff, 0, 0, 0xff]), (1, 1, [0xff, 0, 0, 0xff]), (2, 0, [0, 0xff, 0, 0xff]), (4, 0, [1, 2, 3, 0xff]), (127, 63, [0, 0, 0xff, 0xff]), (125, 63, [1, 2, 3, 0xff])]{
+            assert_eq!(pixel(&renderer, x, y), color, "at {}, {}", x, y);
+        }
+    }
+
+    #[test]
+    fn whole_pixels_leave_bars_on_every_side(){
+        // 140 x 80 only fits the screen twice, at 6, 8
+        let mut renderer = renderer(140, 80);
+        renderer.render(&screen());
+        assert_eq!(pixel(&renderer, 5, 8), BAR);
+        assert_eq!(pixel(&renderer, 6, 7), BAR);
+        assert_eq!(pixel(&renderer, 6, 8), [0xff, 0, 0, 0xff]);
+        assert_eq!(pixel(&renderer, 133, 71), [0, 0, 0xff, 0xff]);
+        assert_eq!(pixel(&renderer, 134, 71), BAR);
+        assert_eq!(pixel(&renderer, 133, 72), BAR);
+    }
+
+    #[test]
+    fn fitted_pixels_only_leave_bars_on_two_sides(){
+        // 140 x 80 is filled across at 2.1875, 70 rows high from row 5
+        let mut renderer = renderer(140, 80);
+        renderer.set_integer_scale(false);
+        renderer.render(&screen());
+        assert_eq!(pixel(&renderer, 0, 4), BAR);
+        assert_eq!(pixel(&renderer, 0, 5), [0xff, 0, 0, 0xff]);
+        assert_eq!(pixel(&renderer, 2, 5), [0xff, 0, 0, 0xff]);
+        assert_eq!(pixel(&renderer, 3, 5), [0, 0xff, 0, 0xff]);
+        assert_eq!(pixel(&renderer, 139, 74), [0, 0, 0xff, 0xff]);
+        assert_eq!(pixel(&renderer, 139, 75), BAR);
+    }
+
+    #[test]
+    fn resizing_redraws_into_a_buffer_of_the_new_size(){
+        let mut renderer = renderer(128, 64);
+        renderer.render(&screen());
+        renderer.resize(64, 40);
+        renderer.render(&screen());
+        assert_eq!(renderer.pixels().len(), 64 * 40 * 4);
+        // one to one, 4 rows of bars above
+        assert_eq!(pixel(&renderer, 0, 3), BAR);
+        assert_eq!(pixel(&renderer, 0, 4), [0xff, 0, 0, 0xff]);
+        assert_eq!(pixel(&renderer, 1, 4), [0, 0xff, 0, 0xff]);
+        assert_eq!(pixel(&renderer, 63, 35), [0, 0, 0xff, 0xff]);
+    }
+}
@@ -0,0 +1,251 @@
+use std::collections::VecDeque;
+
+use crate::chip::Chip;
+
+// Rewind keeps the save state of every frame in a ring buffer bounded by a memory budget.
+// Frames are grouped behind a keyframe: the keyframe holds the whole state, the frames after it
+// only the xor against it, which is mostly zeros since little changes between nearby frames.
+// Both are stored run-length compressed. Once over budget the oldest group is dropped as a whole.
+pub const DEFAULT_BUDGET: usize = 16 * 1024 * 1024;
+pub const DEFAULT_KEYFRAME_INTERVAL: usize = 60;
+
+// a run of zero bytes followed by literal bytes, both lengths as LEB128 varints
+fn varint(data: &mut Vec<u8>, mut value: usize){
+    while value >= 0x80{
+        data.push((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+    data.push(value as u8);
+}
+
+fn read_varint(data: &[u8], position: &mut usize)->usize{
+    let mut value = 0;
+    let mut shift = 0;
+    loop{
+        let byte = data[*position];
+        *position += 1;
+        value |= ((byte & 0x7f) as usize) << shift;
+        if byte & 0x80 == 0{
+            return value;
+        }
+        shift += 7;
+    }
+}
+
+pub fn compress(data: &[u8])->Vec<u8>{
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < data.len(){
+        let start = i;
+        while i < data.len() && data[i] == 0{ i += 1; }
+        let zeros = i - start;
+
+        // short runs of zeros inside literals cost more as a new run than as literals
+        let literal = i;
+        while i < data.len() && !(data[i] == 0 && data[i..].iter().take(4).all(| byte | *byte == 0)){ i += 1; }
+        varint(&mut out, zeros);
+        varint(&mut out, i - literal);
+        out.extend_from_slice(&data[literal..i]);
+    }
+    return out;
+}
+
+pub fn decompress(data: &[u8])->Vec<u8>{
+    let mut out = Vec::new();
+    let mut position = 0;
+    while position < data.len(){
+        let zeros = read_varint(data, &mut position);
+        out.resize(out.len() + zeros, 0);
+        let literal = read_varint(data, &mut position);
+        out.extend_from_slice(&data[position..position + literal]);
+        position += literal;
+    }
+    return out;
+}
+
+fn xor(state: &[u8], base: &[u8])->Vec<u8>{
+    return state.iter().zip(base.iter()).map(| (a, b) | a ^ b).collect();
+}
+
+struct Group{ keyframe: Vec<u8>, deltas: Vec<Vec<u8>>, size: usize }
+
+pub struct Rewind{
+    groups: VecDeque<Group>,
+    base: Vec<u8>,      // uncompressed keyframe of the newest group
+    budget: usize, used: usize, keyframe_interval: usize
+}
+
+impl Rewind{
+    pub fn new()->Self{ Rewind::with_budget(DEFAULT_BUDGET) }
+
+    pub fn with_budget(budget: usize)->Self{
+        Rewind{ groups: VecDeque::new(), base: Vec::new(), budget, used: 0, keyframe_interval: DEFAULT_KEYFRAME_INTERVAL }
+    }
+
+    pub fn get_budget(&self)->usize{ self.budget }
+    pub fn set_budget(&mut self, budget: usize){
+        self.budget = budget;
+        self.trim();
+    }
+
+    pub fn get_keyframe_interval(&self)->usize{ self.keyframe_interval }
+    pub fn set_keyframe_interval(&mut self, interval: usize){ self.keyframe_interval = interval.max(1); }
+
+    // bytes used by the compressed snapshots
+    pub fn used(&self)->usize{ self.used }
+
+    // number of frames that can be stepped back
+    pub fn len(&self)->usize{
+        return self.groups.iter().map(| group | group.deltas.len() + 1).sum();
+    }
+    pub fn is_empty(&self)->bool{ self.groups.is_empty() }
+
+    pub fn clear(&mut self){
+        self.groups.clear();
+        self.base.clear();
+        self.used = 0;
+    }
+
+    // records the state of the chip, called once per frame
+    pub fn push(&mut self, chip: &Chip){
+        let state = chip.save_state();
+        let keyframe = match self.groups.back(){
+            Some(group) => group.deltas.len() + 1 >= self.keyframe_interval || state.len() != self.base.len(),
+            None => true
+        };
+
+        if keyframe{
+            let data = compress(&state);
+            self.used += data.len();
+            self.groups.push_back(Group{ size: data.len(), keyframe: data, deltas: Vec::new() });
+            self.base = state;
+        }else{
+            let data = compress(&xor(&state, &self.base));
+            self.used += data.len();
+            let group = self.groups.back_mut().unwrap();
+            group.size += data.len();
+            group.deltas.push(data);
+        }
+        self.trim();
+    }
+
+    // Steps the chip back a frame, false once there is nothing left to go back to. The snapshot of the frame
+    // the chip is on is dropped and the one before it restored, which stays the newest so that the next step
+    // goes further back and frames run from there are recorded after it.
+    pub fn pop(&mut self, chip: &mut Chip)->bool{
+        if self.newest() == Some(chip.save_state()){
+            self.drop_newest();
+        }
+        return match self.newest(){
+            Some(state) => chip.load_state(&state).is_ok(),
+            None => false
+        };
+    }
+
+    // the most recent snapshot, uncompressed
+    fn newest(&self)->Option<Vec<u8>>{
+        let group = self.groups.back()?;
+        return Some(match group.deltas.last(){
+            Some(delta) => xor(&decompress(delta), &self.base),
+            None => decompress(&group.keyframe)
+        });
+    }
+
+    fn drop_newest(&mut self){
+        let group = match self.groups.back_mut(){
+            Some(group) => group,
+            None => return
+        };
+        match group.deltas.pop(){
+            Some(delta) =>{
+                group.size -= delta.len();
+                self.used -= delta.len();
+            },
+            None =>{
+                let group = self.groups.pop_back().unwrap();
+                self.used -= group.size;
+                self.base = match self.groups.back(){
+                    Some(previous) => decompress(&previous.keyframe),
+                    None => Vec::new()
+                };
+            }
+        }
+    }
+
+    // the newest group is kept even over budget so that the last frames can always be stepped back
+    fn trim(&mut self){
+        while self.used > self.budget && self.groups.len() > 1{
+            let group = self.groups.pop_front().unwrap();
+            self.used -= group.size;
+        }
+    }
+}
+
+impl Default for Rewind{
+    fn default()->Self{ Rewind::new() }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    // counts v0 up a frame at a time: 7001 1200
+    fn chip()->Chip{
+        let mut chip = Chip::new();
+        chip.load_rom(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+        return chip;
+    }
+
+    fn frames(chip: &mut Chip, rewind: &mut Rewind, count: usize){
+        for _ in 0..count{
+            chip.frame(2).unwrap();
+            chip.end_frame();
+            rewind.push(chip);
+        }
+    }
+
+    #[test]
+    fn compression_round_trips(){
+        let data = [0, 0, 0, 0, 0, 1, 2, 0, 3, 0, 0, 0, 0, 0, 0, 4];
+        assert_eq!(decompress(&compress(&data)), data);
+        assert_eq!(decompress(&compress(&[])), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn every_pop_steps_back_a_frame(){
+        let mut chip = chip();
+        let mut rewind = Rewind::new();
+        rewind.set_keyframe_interval(4);
+        frames(&mut chip, &mut rewind, 10);
+        assert_eq!(chip.cpu().get_register(0), 10);
+        for value in (1..10).rev(){
+            assert!(rewind.pop(&mut chip));
+            assert_eq!(chip.cpu().get_register(0), value);
+        }
+        assert!(!rewind.pop(&mut chip));
+        assert!(rewind.is_empty());
+    }
+
+    #[test]
+    fn recording_carries_on_after_rewinding(){
+        let mut chip = chip();
+        let mut rewind = Rewind::new();
+        frames(&mut chip, &mut rewind, 5);
+        assert!(rewind.pop(&mut chip));
+        assert!(rewind.pop(&mut chip));
+        assert_eq!(chip.cpu().get_register(0), 3);
+        frames(&mut chip, &mut rewind, 2);
+        assert_eq!(chip.cpu().get_register(0), 5);
+        assert!(rewind.pop(&mut chip));
+        assert_eq!(chip.cpu().get_register(0), 4);
+    }
+
+    #[test]
+    fn budget_drops_the_oldest_groups(){
+        let mut chip = chip();
+        let mut rewind = Rewind::with_budget(0);
+        rewind.set_keyframe_interval(2);
+        frames(&mut chip, &mut rewind, 10);
+        assert!(rewind.len() <= 2);
+    }
+}
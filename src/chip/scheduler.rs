@@ -0,0 +1,292 @@
+use std::cell::Cell;
+use std::rc::Rc;
+use std::time::Instant;
+
+use crate::chip::Chip;
+use crate::chip::audio::Sink;
+use crate::chip::error::ChipError;
+use crate::chip::rewind::Rewind;
+use crate::chip::movie::Movie;
+use crate::chip::debugger::Debugger;
+
+pub const FRAME_RATE: f64 = 60.0;
+
+// source of time for the scheduler, in seconds
+pub trait Clock{
+    fn now(&self)->f64;
+}
+
+pub struct SystemClock{ start: Instant }
+impl SystemClock{
+    pub fn new()->Self{ SystemClock{ start: Instant::now() } }
+}
+
+impl Default for SystemClock{
+    fn default()->Self{ SystemClock::new() }
+}
+
+impl Clock for SystemClock{
+    fn now(&self)->f64{ self.start.elapsed().as_secs_f64() }
+}
+
+// a clock that only moves when told to, clones share the same time so a test can keep
+// one handle while the scheduler owns the other
+#[derive(Clone)]
+pub struct ManualClock{ time: Rc<Cell<f64>> }
+impl ManualClock{
+    pub fn new()->Self{ ManualClock{ time: Rc::new(Cell::new(0.0)) } }
+
+    pub fn advance(&self, seconds: f64){ self.time.set(self.time.get() + seconds); }
+    pub fn set(&self, seconds: f64){ self.time.set(seconds); }
+}
+
+impl Default for ManualClock{
+    fn default()->Self{ ManualClock::new() }
+}
+
+impl Clock for ManualClock{
+    fn now(&self)->f64{ self.time.get() }
+}
+
+// runs the chip in 60 Hz frames: a fixed number of instructions per frame, then one timer tick.
+// The speed multiplier scales emulated time against the clock, above 1 fast-forwards and below 1 is slow motion.
+// With a rewind buffer every frame is recorded, and while rewinding the due frames step back through it instead.
+// A movie either records the keys of every frame or plays them back, and a debugger runs the frames
+// instruction by instruction to stop on breakpoints.
+pub struct Scheduler{
+    clock: Box<dyn Clock>, sink: Option<Box<dyn Sink>>, rewind: Option<Rewind>, movie: Option<Movie>, debugger: Option<Debugger>,
+    instructions_per_frame: usize, speed: f64, rewinding: bool,
+    last: f64, accumulator: f64, samples: f64, frames: u64
+}
+
+impl Scheduler{
+    pub fn new(clock: Box<dyn Clock>)->Self{
+        let last = clock.now();
+        Scheduler{
+            clock, sink: None, rewind: None, movie: None, debugger: None, instructions_per_frame: 10, speed: 1.0, rewinding: false,
+            last, accumulator: 0.0, samples: 0.0, frames: 0
+        }
+    }
+
+    pub fn get_instructions_per_frame(&self)->usize{ self.instructions_per_frame }
+    pub fn set_instructions_per_frame(&mut self, count: usize){ self.instructions_per_frame = count; }
+
+    pub fn get_speed(&self)->f64{ self.speed }
+    pub fn set_speed(&mut self, speed: f64){ self.speed = speed.max(0.0); }
+
+    // total number of frames run so far
+    pub fn frames(&self)->u64{ self.frames }
+
+    // audio for every frame is rendered into the sink, sample_rate / 60 samples at a time
+    pub fn set_sink(&mut self, sink: Option<Box<dyn Sink>>){ self.sink = sink; }
+    pub fn sink_mut(&mut self)->Option<&mut Box<dyn Sink>>{ self.sink.as_mut() }
+
+    pub fn set_rewind(&mut self, rewind: Option<Rewind>){ self.rewind = rewind; }
+    pub fn rewind_mut(&mut self)->Option<&mut Rewind>{ self.rewind.as_mut() }
+
+    // a movie being played back also sets the number of instructions per frame it was recorded with
+    pub fn set_movie(&mut self, movie: Option<Movie>){
+        if let Some(movie) = movie.as_ref().filter(| movie | movie.is_playing()){
+            self.instructions_per_frame = movie.get_instructions_per_frame();
+        }
+        self.movie = movie;
+    }
+    pub fn movie(&self)->Option<&Movie>{ self.movie.as_ref() }
+    pub fn take_movie(&mut self)->Option<Movie>{ self.movie.take() }
+
+    pub fn set_debugger(&mut self, debugger: Option<Debugger>){ self.debugger = debugger; }
+    pub fn debugger(&self)->Option<&Debugger>{ self.debugger.as_ref() }
+    pub fn debugger_mut(&mut self)->Option<&mut Debugger>{ self.debugger.as_mut() }
+
+    pub fn is_rewinding(&self)->bool{ self.rewinding }
+    pub fn set_rewinding(&mut self, rewinding: bool){ self.rewinding = rewinding; }
+
+    // runs the frames that are due according to the clock, stopping at the first fault.
+    // Time spent with the chip paused is not caught up once it is resumed.
+    pub fn update(&mut self, chip: &mut Chip)->Result<(), ChipError>{
+        let now = self.clock.now();
+        let elapsed = now - self.last;
+        self.last = now;
+        if chip.is_paused(){
+            self.accumulator = 0.0;
+            return Ok(());
+        }
+
+        // the accumulator counts frames owed, a stalled host should not make the emulator race to catch up
+        let limit = (self.speed.max(1.0) * 4.0).ceil();
+        self.accumulator = (self.accumulator + elapsed * self.speed * FRAME_RATE).min(limit);
+
+        // the epsilon keeps a clock advanced by exactly 1/60 from losing a frame to rounding
+        while self.accumulator >= 1.0 - 1e-9{
+            self.accumulator -= 1.0;
+            if self.rewinding{
+                self.back(chip);
+                continue;
+            }
+            if let Err(error) = self.frame(chip){
+                self.accumulator = 0.0;
+                return Err(error);
+            }
+        }
+        return Ok(());
+    }
+
+    // runs a single frame regardless of the clock, used to step frame by frame and by headless runners
+    // frames where the chip is not running are skipped, they do not count nor get recorded
+    pub fn frame(&mut self, chip: &mut Chip)->Result<(), ChipError>{
+        if !chip.is_running(){
+            return Ok(());
+        }
+        if let Some(movie) = self.movie.as_mut(){
+            movie.before(chip);
+        }
+        let mut ok = match self.debugger.as_mut(){
+            Some(debugger) => debugger.frame(chip, self.instructions_per_frame),
+            None => chip.frame(self.instructions_per_frame)
+        };
+        self.frames += 1;
+        if let (Ok(_), Some(movie)) = (&ok, self.movie.as_mut()){
+            ok = movie.after(chip);
+        }
+        if let (Ok(_), Some(rewind)) = (&ok, self.rewind.as_mut()){
+            rewind.push(chip);
+        }
+
+        if let Some(sink) = self.sink.as_mut(){
+            self.samples += chip.audio().get_sample_rate() as f64 / FRAME_RATE;
+            let count = self.samples as usize;
+            self.samples -= count as f64;
+            chip.play(count, sink.as_mut());
+        }
+        return ok;
+    }
+
+    // steps one frame back through the rewind buffer, false when there is nothing left to rewind
+    pub fn back(&mut self, chip: &mut Chip)->bool{
+        return match self.rewind.as_mut(){
+            Some(rewind) => rewind.pop(chip),
+            None => false
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    // counts the samples written to it, a clone stays with the test
+    #[derive(Clone)]
+    struct Counter(Rc<Cell<usize>>);
+    impl Sink for Counter{
+        fn write(&mut self, samples: &[f32]){ self.0.set(self.0.get() + samples.len()); }
+    }
+
+    // at 2 instructions a frame v0 counts the frames run: 7001 1200
+    fn setup()->(Chip, Scheduler, ManualClock){
+        let mut chip = Chip::new();
+        chip.load_rom(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+        let clock = ManualClock::new();
+        let mut scheduler = Scheduler::new(Box::new(clock.clone()));
+        scheduler.set_instructions_per_frame(2);
+        return (chip, scheduler, clock);
+    }
+
+    #[test]
+    fn a_frame_runs_every_sixtieth_of_a_second(){
+        let (mut chip, mut scheduler, clock) = setup();
+        for _ in 0..60{
+            clock.advance(1.0 / FRAME_RATE);
+            scheduler.update(&mut chip).unwrap();
+        }
+        assert_eq!(scheduler.frames(), 60);
+        assert_eq!(chip.cpu().get_register(0), 60);
+
+        clock.advance(0.5 / FRAME_RATE);
+        scheduler.update(&mut chip).unwrap();
+        assert_eq!(scheduler.frames(), 60);
+        clock.advance(0.5 / FRAME_RATE);
+        scheduler.update(&mut chip).unwrap();
+        assert_eq!(scheduler.frames(), 61);
+    }
+
+    #[test]
+    fn speed_scales_the_frames_run(){
+        let (mut chip, mut scheduler, clock) = setup();
+        scheduler.set_speed(2.0);
+        clock.advance(1.0 / FRAME_RATE);
+        scheduler.update(&mut chip).unwrap();
+        assert_eq!(scheduler.frames(), 2);
+
+        scheduler.set_speed(0.25);
+        for _ in 0..4{
+            clock.advance(1.0 / FRAME_RATE);
+            scheduler.update(&mut chip).unwrap();
+        }
+        assert_eq!(scheduler.frames(), 3);
+    }
+
+    #[test]
+    fn a_stalled_host_is_not_caught_up(){
+        let (mut chip, mut scheduler, clock) = setup();
+        clock.advance(10.0);
+        scheduler.update(&mut chip).unwrap();
+        assert_eq!(scheduler.frames(), 4);
+
+        chip.pause();
+        clock.advance(1.0);
+        scheduler.update(&mut chip).unwrap();
+        assert_eq!(scheduler.frames(), 4);
+        chip.resume();
+        clock.advance(1.0 / FRAME_RATE);
+        scheduler.update(&mut chip).unwrap();
+        assert_eq!(scheduler.frames(), 5);
+    }
+
+    #[test]
+    fn a_chip_paused_between_updates_loses_the_time_it_was_paused(){
+        let (mut chip, mut scheduler, clock) = setup();
+        clock.advance(0.5 / FRAME_RATE);
+        scheduler.update(&mut chip).unwrap();
+        chip.pause();
+        clock.advance(2.0 / FRAME_RATE);
+        scheduler.update(&mut chip).unwrap();
+        assert_eq!((scheduler.frames(), chip.cpu().get_register(0)), (0, 0));
+
+        // neither the half frame owed before the pause nor the pause itself is run on resuming
+        chip.resume();
+        clock.advance(0.5 / FRAME_RATE);
+        scheduler.update(&mut chip).unwrap();
+        assert_eq!(scheduler.frames(), 0);
+        clock.advance(0.5 / FRAME_RATE);
+        scheduler.update(&mut chip).unwrap();
+        assert_eq!(scheduler.frames(), 1);
+    }
+
+    #[test]
+    fn every_frame_renders_its_share_of_samples(){
+        let (mut chip, mut scheduler, clock) = setup();
+        let counter = Counter(Rc::new(Cell::new(0)));
+        scheduler.set_sink(Some(Box::new(counter.clone())));
+        for _ in 0..120{
+            clock.advance(1.0 / FRAME_RATE);
+            scheduler.update(&mut chip).unwrap();
+        }
+        assert_eq!(counter.0.get(), 2 * chip.audio().get_sample_rate() as usize);
+    }
+
+    #[test]
+    fn rewinding_steps_back_through_the_frames_run(){
+        let (mut chip, mut scheduler, clock) = setup();
+        scheduler.set_rewind(Some(Rewind::new()));
+        for _ in 0..10{
+            clock.advance(1.0 / FRAME_RATE);
+            scheduler.update(&mut chip).unwrap();
+        }
+        scheduler.set_rewinding(true);
+        for _ in 0..3{
+            clock.advance(1.0 / FRAME_RATE);
+            scheduler.update(&mut chip).unwrap();
+        }
+        assert_eq!(chip.cpu().get_register(0), 7);
+    }
+}
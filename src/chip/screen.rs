@@ -0,0 +1,157 @@
+use crate::chip::error::ChipError;
+use crate::chip::state::{ StateWriter, StateReader };
+
+// every pixel holds one bit per XO-CHIP bit-plane, giving colour indices 0-3
+pub struct Screen{ pixels : [[u8; 128]; 64], extended: bool, planes: u8 }
+
+impl Screen{
+    pub fn new()->Self{
+        return Screen{ pixels: [[0; 128]; 64], extended: false, planes: 1 }
+    }
+
+    pub fn clear(&mut self){
+        for row in 0..self.pixels.len(){
+            for column in 0..self.pixels[row].len(){
+                self.pixels[row][column] = 0;
+            }
+        }
+    }
+
+    // clears only the selected planes, as done by 00E0
+    pub fn clear_planes(&mut self){
+        for row in self.pixels.iter_mut(){
+            for pixel in row.iter_mut(){
+                *pixel &= !self.planes;
+            }
+        }
+    }
+
+    pub fn is_extended(&self)->bool{ return self.extended; }
+
+    pub fn get_planes(&self)->u8{ self.planes }
+    pub fn set_planes(&mut self, planes: u8){ self.planes = planes & 0x03; }
+
+    // switches between the 64x32 and the 128x64 (SCHIP hires) mode, clearing the display
+    pub fn set_extended(&mut self, extended: bool){
+        self.extended = extended;
+        self.clear();
+    }
+
+    pub fn set(&mut self, x:usize, y:usize){
+        self.pixels[y][x] |= self.planes;
+    }
+
+    pub fn get(&self, x:usize, y:usize)->u8{ self.pixels[y][x] }
+
+    pub fn pixels(&self)->&[[u8; 128]; 64]{ &self.pixels }
+
+    pub fn save_state(&self, state: &mut StateWriter){
+        state.bool(self.extended);
+        state.u8(self.planes);
+        for row in self.pixels.iter(){
+            state.bytes(row);
+        }
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader)->Result<(), ChipError>{
+        self.extended = state.bool()?;
+        self.planes = state.u8()? & 0x03;
+        for row in self.pixels.iter_mut(){
+            row.copy_from_slice(state.bytes(128)?);
+        }
+        Ok(())
+    }
+
+    // size of the active display, only the top left corner of pixels is used in lores
+    pub fn width(&self)->usize{ if self.extended { 128 } else { 64 } }
+    pub fn height(&self)->usize{ if self.extended { 64 } else { 32 } }
+
+    // moves the selected planes of a pixel in from the given source, leaving the other planes untouched
+    fn shift(&mut self, row: usize, column: usize, source: Option<(usize, usize)>){
+        let init = match source{
+            Some((y, x)) => self.pixels[y][x] & self.planes,
+            None => 0
+        };
+        self.pixels[row][column] = (self.pixels[row][column] & !self.planes) | init;
+    }
+
+    pub fn scroll_up(&mut self, lines: usize){
+        let (width, height) = (self.width(), self.height());
+        for row in 0..height{
+            for column in 0..width{
+                self.shift(row, column, if row + lines < height { Some((row + lines, column)) } else { None });
+            }
+        }
+    }
+
+    pub fn scroll_down(&mut self, lines: usize){
+        let (width, height) = (self.width(), self.height());
+        for row in (0..height).rev(){
+            for column in 0..width{
+                self.shift(row, column, if row >= lines { Some((row - lines, column)) } else { None });
+            }
+        }
+    }
+
+    pub fn scroll_right(&mut self, columns: usize){
+        let (width, height) = (self.width(), self.height());
+        for row in 0..height{
+            for column in (0..width).rev(){
+                self.shift(row, column, if column >= columns { Some((row, column - columns)) } else { None });
+            }
+        }
+    }
+
+    pub fn scroll_left(&mut self, columns: usize){
+        let (width, height) = (self.width(), self.height());
+        for row in 0..height{
+            for column in 0..width{
+                self.shift(row, column, if column + columns < width { Some((row, column + columns)) } else { None });
+            }
+        }
+    }
+
+    // Function that draw in Xor mode, sprite rows are width bits wide (8, or 16 for the SCHIP 16x16 sprites).
+    // The starting position always wraps, the pixels past the edges are either clipped or wrapped around.
+    // In hires vf holds the number of rows that collided or were clipped off the bottom, otherwise 1 on any collision.
+    // plane is the single plane bit the sprite is drawn into.
+    pub fn draw(&mut self, x: u16, y: u16, sprite:Vec<u16>, width: usize, clip: bool, plane: u8)->u8{
+        let (screen_width, screen_height) = (self.width(), self.height());
+        let (x, y) = (x as usize % screen_width, y as usize % screen_height);
+        let mut rows = 0;
+        for (yline, row) in sprite.iter().enumerate(){
+            let mut screen_y = y + yline;
+            if screen_y >= screen_height{
+                if clip{
+                    if self.extended{ rows += 1; }
+                    continue;
+                }
+                screen_y %= screen_height;
+            }
+
+            let mut collided = false;
+            for xline in 0..width{
+                if (row & (1 << (width - 1 - xline))) != 0{
+                    let mut screen_x = x + xline;
+                    if screen_x >= screen_width{
+                        if clip{ continue; }
+                        screen_x %= screen_width;
+                    }
+
+                    collided |= self.pixels[screen_y][screen_x] & plane != 0;
+                    self.pixels[screen_y][screen_x] ^= plane;
+                }
+            }
+            if collided{ rows += 1; }
+        }
+
+        if self.extended{
+            return rows;
+        }
+        return if rows > 0 { 1 } else { 0 };
+    }
+}
+
+impl Default for Screen{
+    fn default()->Self{ Screen::new() }
+}
@@ -1,7 +0,0 @@
-mod batch;
-pub use batch::{ Batch, BatchConfig};
-
-mod shader;
-use shader::Shader;
-
-pub trait Disposable{ unsafe fn dispose(&mut self); }
\ No newline at end of file
@@ -0,0 +1,353 @@
+use std::fs;
+
+use crate::chip::{ Chip, CPU, Memory, Screen, KeyPad, Audio };
+use crate::chip::error::ChipError;
+
+// Save states are a little endian snapshot of the whole machine:
+//
+//   offset  size  field
+//   0       4     magic "C8ST"
+//   4       2     format version
+//   6       4     crc32 of the rom the state was taken from
+//   10      4     payload length n
+//   14      n     payload
+//   14 + n  4     crc32 of everything before it
+//
+// The version 1 payload holds, in order:
+//
+//   cpu     v0-vf (16), user flags (16), I (2), pc (2), sp (1), stack (16 x 2), delay timer (1), sound timer (1)
+//   quirks  shift_vy, jump_vx, clip_sprites, vf_reset, add_i_overflow, display_wait (1 each),
+//           Fx55/Fx65 increment (1: unchanged, x, x + 1), memory size (4)
+//   memory  length (4), contents
+//   screen  hires (1), selected planes (1), pixels (128 x 64, one byte of plane bits each)
+//   keys    pressed keys as a bitmask, key 0 in the lowest bit (2)
+//   audio   pattern (16), pitch (1), phase (8, f64)
+//   chip    loaded, drawn since the last vblank, halted (1 each)
+//
+// Version 2 appends the random number generator:
+//
+//   random  seed (8), generator state length (4), generator state
+//
+// Version 3 adds to the keys the presses and releases not yet seen by Fx0A, as two more bitmasks (2 each).
+//
+// Version 4 adds the Fx0A release quirk after the memory size (1), and at the end of the payload
+// the register Fx0A is waiting to store a key in, 0xff when not waiting (1).
+//
+// Version 5 adds to the audio whether an F002 pattern replaced the beeper (1) and the envelope gain (8, f64).
+//
+// Every field is read knowing the version of the state, so an older state is migrated by
+// filling in the fields it lacks with their reset values. Saving always writes the current version.
+pub const MAGIC: &[u8; 4] = b"C8ST";
+pub const VERSION: u16 = 5;
+
+const HEADER_SIZE: usize = 14;
+
+pub struct StateWriter{ data: Vec<u8> }
+
+impl StateWriter{
+    pub fn new()->Self{ StateWriter{ data: Vec::new() } }
+
+    pub fn u8(&mut self, value: u8){ self.data.push(value); }
+    pub fn u16(&mut self, value: u16){ self.data.extend_from_slice(&value.to_le_bytes()); }
+    pub fn u32(&mut self, value: u32){ self.data.extend_from_slice(&value.to_le_bytes()); }
+    pub fn u64(&mut self, value: u64){ self.data.extend_from_slice(&value.to_le_bytes()); }
+    pub fn f64(&mut self, value: f64){ self.data.extend_from_slice(&value.to_le_bytes()); }
+    pub fn bool(&mut self, value: bool){ self.data.push(value as u8); }
+    pub fn bytes(&mut self, value: &[u8]){ self.data.extend_from_slice(value); }
+
+    pub fn data(&self)->&[u8]{ &self.data }
+}
+
+impl Default for StateWriter{
+    fn default()->Self{ StateWriter::new() }
+}
+
+pub struct StateReader<'a>{ data: &'a [u8], position: usize, version: u16 }
+
+impl<'a> StateReader<'a>{
+    pub fn new(data: &'a [u8], version: u16)->Self{ StateReader{ data, position: 0, version } }
+
+    // version of the state being read, used to migrate older layouts
+    pub fn version(&self)->u16{ self.version }
+
+    pub fn bytes(&mut self, count: usize)->Result<&'a [u8], ChipError>{
+        if self.position + count > self.data.len(){
+            return Err(ChipError::InvalidState(String::from("state is truncated")));
+        }
+        let bytes = &self.data[self.position..self.position + count];
+        self.position += count;
+        return Ok(bytes);
+    }
+
+    pub fn u8(&mut self)->Result<u8, ChipError>{ Ok(self.bytes(1)?[0]) }
+    pub fn u16(&mut self)->Result<u16, ChipError>{ Ok(u16::from_le_bytes(self.bytes(2)?.try_into().unwrap())) }
+    pub fn u32(&mut self)->Result<u32, ChipError>{ Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap())) }
+    pub fn u64(&mut self)->Result<u64, ChipError>{ Ok(u64::from_le_bytes(self.bytes(8)?.try_into().unwrap())) }
+    pub fn f64(&mut self)->Result<f64, ChipError>{ Ok(f64::from_le_bytes(self.bytes(8)?.try_into().unwrap())) }
+    pub fn bool(&mut self)->Result<bool, ChipError>{ Ok(self.u8()? != 0) }
+
+    pub fn is_empty(&self)->bool{ self.position == self.data.len() }
+}
+
+pub fn rom_hash(rom: &[u8])->u32{ crc32fast::hash(rom) }
+
+pub fn save(chip: &Chip)->Vec<u8>{
+    let mut payload = StateWriter::new();
+    chip.cpu.save_state(&mut payload);
+    chip.memory.save_state(&mut payload);
+    chip.screen.save_state(&mut payload);
+    chip.keys.save_state(&mut payload);
+    chip.audio.save_state(&mut payload);
+    payload.bool(chip.loaded);
+    payload.bool(chip.drawn);
+    payload.bool(chip.halted);
+    let mut random = StateWriter::new();
+    chip.random.save_state(&mut random);
+    payload.u64(chip.seed);
+    payload.u32(random.data().len() as u32);
+    payload.bytes(random.data());
+    payload.u8(chip.waiting.map_or(0xff, | x | x as u8));
+
+    let mut state = StateWriter::new();
+    state.bytes(MAGIC);
+    state.u16(VERSION);
+    state.u32(chip.rom_hash);
+    state.u32(payload.data().len() as u32);
+    state.bytes(payload.data());
+    let crc = crc32fast::hash(state.data());
+    state.u32(crc);
+    return state.data;
+}
+
+// checks the header and crc, returning the version, rom hash and payload of a state
+fn open(data: &[u8])->Result<(u16, u32, &[u8]), ChipError>{
+    if data.len() < HEADER_SIZE + 4 || &data[0..4] != MAGIC{
+        return Err(ChipError::InvalidState(String::from("not a save state")));
+    }
+    let (body, crc) = data.split_at(data.len() - 4);
+    if crc32fast::hash(body) != u32::from_le_bytes(crc.try_into().unwrap()){
+        return Err(ChipError::InvalidState(String::from("checksum mismatch, the state is corrupted")));
+    }
+
+    let mut header = StateReader::new(&body[4..HEADER_SIZE], 0);
+    let version = header.u16()?;
+    let rom = header.u32()?;
+    let length = header.u32()? as usize;
+    if version == 0 || version > VERSION{
+        return Err(ChipError::InvalidState(format!("unsupported state version {}", version)));
+    }
+    if body.len() != HEADER_SIZE + length{
+        return Err(ChipError::InvalidState(String::from("payload length mismatch")));
+    }
+    return Ok((version, rom, &body[HEADER_SIZE..]));
+}
+
+// the chip is left untouched unless the whole state could be read
+pub fn load(chip: &mut Chip, data: &[u8])->Result<(), ChipError>{
+    let (version, rom, payload) = open(data)?;
+    if rom != chip.rom_hash{
+        return Err(ChipError::RomMismatch);
+    }
+
+    let mut state = StateReader::new(payload, version);
+    let mut cpu = CPU::new();
+    cpu.set_policy(*chip.cpu.get_policy());
+    cpu.load_state(&mut state)?;
+    let mut memory = Memory::with_size(cpu.get_quirks().memory_size);
+    memory.set_policy(chip.cpu.get_policy().memory_out_of_bounds);
+    memory.load_state(&mut state)?;
+    let mut screen = Screen::new();
+    screen.load_state(&mut state)?;
+    let mut keys = KeyPad::new();
+    keys.load_state(&mut state)?;
+    let mut audio = Audio::new();
+    audio.set_sample_rate(chip.audio.get_sample_rate());
+    audio.set_volume(chip.audio.get_volume());
+    audio.load_state(&mut state)?;
+    let (loaded, drawn, halted) = (state.bool()?, state.bool()?, state.bool()?);
+
+    // version 1 states predate the generator, which then carries on from where it is
+    let mut random = None;
+    if version >= 2{
+        let seed = state.u64()?;
+        let length = state.u32()? as usize;
+        random = Some((seed, state.bytes(length)?));
+    }
+    let waiting = if version >= 4 { Some(state.u8()?).filter(| x | *x < 16).map(| x | x as usize) } else { None };
+    if !state.is_empty(){
+        return Err(ChipError::InvalidState(String::from("unexpected data after the payload")));
+    }
+    // the generator sits behind a trait object, so it is read in place and put back as it was when that fails
+    if let Some((seed, random)) = random{
+        let mut previous = StateWriter::new();
+        chip.random.save_state(&mut previous);
+        if previous.data().len() != random.len(){
+            return Err(ChipError::InvalidState(String::from("state was made with a different random generator")));
+        }
+        if let Err(error) = chip.random.load_state(&mut StateReader::new(random, version)){
+            chip.random.load_state(&mut StateReader::new(previous.data(), version))?;
+            return Err(error);
+        }
+        chip.seed = seed;
+    }
+
+    // nothing can fail from here on, every part is committed together
+    *chip.cpu = cpu;
+    *chip.memory = memory;
+    *chip.screen = screen;
+    *chip.keys = keys;
+    *chip.audio = audio;
+    chip.loaded = loaded;
+    chip.drawn = drawn;
+    chip.halted = halted;
+    chip.waiting = waiting;
+    chip.fault = None;
+    chip.breakpoint = None;
+    return Ok(());
+}
+
+// slots are kept next to the rom, slot 3 of games/pong.ch8 is games/pong.ch8.state3
+pub fn slot_path(rom: &str, slot: u8)->String{ format!("{}.state{}", rom, slot) }
+
+pub fn save_slot(chip: &Chip, rom: &str, slot: u8)->Result<(), ChipError>{
+    fs::write(slot_path(rom, slot), save(chip))?;
+    Ok(())
+}
+
+pub fn load_slot(chip: &mut Chip, rom: &str, slot: u8)->Result<(), ChipError>{
+    let data = fs::read(slot_path(rom, slot))?;
+    return load(chip, &data);
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::chip::Quirks;
+
+    // where the memory length sits in the payload, after the cpu and its quirks
+    const MEMORY: usize = 83;
+
+    fn chip()->Chip{
+        let mut chip = Chip::new();
+        chip.set_seed(7);
+        chip.load_rom(&[0x60, 0x2a, 0xc1, 0xff, 0xa0, 0x00, 0xd0, 0x15, 0x12, 0x00]).unwrap();
+        chip.run(20).unwrap();
+        return chip;
+    }
+
+    // wraps a payload into a state with a valid header and crc, as a crafted file would be
+    fn seal(version: u16, rom: u32, payload: &[u8])->Vec<u8>{
+        let mut state = StateWriter::new();
+        state.bytes(MAGIC);
+        state.u16(version);
+        state.u32(rom);
+        state.u32(payload.len() as u32);
+        state.bytes(payload);
+        let crc = crc32fast::hash(state.data());
+        state.u32(crc);
+        return state.data().to_vec();
+    }
+
+    #[test]
+    fn round_trip_carries_on_with_the_same_random_numbers(){
+        let mut chip = chip();
+        let data = chip.save_state();
+        chip.run(40).unwrap();
+        let after = chip.save_state();
+
+        let mut restored = Chip::new();
+        restored.load_rom(&[0x60, 0x2a, 0xc1, 0xff, 0xa0, 0x00, 0xd0, 0x15, 0x12, 0x00]).unwrap();
+        restored.load_state(&data).unwrap();
+        assert_eq!(restored.save_state(), data);
+        restored.run(40).unwrap();
+        assert_eq!(restored.save_state(), after);
+    }
+
+    #[test]
+    fn state_of_another_rom_is_refused(){
+        let data = chip().save_state();
+        let mut other = Chip::new();
+        other.load_rom(&[0x12, 0x00]).unwrap();
+        assert_eq!(other.load_state(&data), Err(ChipError::RomMismatch));
+    }
+
+    #[test]
+    fn memory_of_another_size_is_refused(){
+        let mut chip = chip();
+        let data = chip.save_state();
+        let payload = &data[HEADER_SIZE..data.len() - 4];
+        assert_eq!(payload[MEMORY..MEMORY + 4], 0x1000u32.to_le_bytes());
+        let mut crafted = payload[..MEMORY].to_vec();
+        crafted.extend_from_slice(&0u32.to_le_bytes());
+        crafted.extend_from_slice(&payload[MEMORY + 4 + 0x1000..]);
+        let before = chip.save_state();
+        assert!(matches!(chip.load_state(&seal(VERSION, chip.rom_hash(), &crafted)), Err(ChipError::InvalidState(_))));
+        assert_eq!(chip.save_state(), before);
+    }
+
+    #[test]
+    fn corrupted_states_are_refused(){
+        let mut chip = chip();
+        let mut data = chip.save_state();
+        data[HEADER_SIZE + 1] ^= 0xff;
+        let before = chip.save_state();
+        assert_eq!(chip.load_state(&data), Err(ChipError::InvalidState(String::from("checksum mismatch, the state is corrupted"))));
+        assert_eq!(chip.save_state(), before);
+    }
+
+    #[test]
+    fn version_1_states_are_migrated(){
+        let mut chip = chip();
+        let seed = chip.seed();
+        let mut payload = StateWriter::new();
+        // cpu: v0 = 0x2a, I = 0x300, pc = 0x208, called from 0x204, delay timer 5
+        let mut registers = [0u8; 16];
+        registers[0] = 0x2a;
+        payload.bytes(&registers);
+        payload.bytes(&[0; 16]);
+        payload.u16(0x300);
+        payload.u16(0x208);
+        payload.u8(1);
+        payload.u16(0x204);
+        for _ in 1..16{
+            payload.u16(0);
+        }
+        payload.u8(5);
+        payload.u8(0);
+        // quirks without the Fx0A release one
+        payload.bytes(&[0, 0, 0, 1, 0, 1]);
+        payload.u8(2);
+        payload.u32(0x1000);
+        chip.memory().save_state(&mut payload);
+        // screen, keys with only key 4 held, the beeper and the chip flags
+        payload.bool(false);
+        payload.u8(1);
+        payload.bytes(&[0; 128 * 64]);
+        payload.u16(0x0010);
+        payload.bytes(&[0xf0; 16]);
+        payload.u8(64);
+        payload.f64(0.0);
+        payload.bool(true);
+        payload.bool(false);
+        payload.bool(false);
+
+        chip.load_state(&seal(1, chip.rom_hash(), payload.data())).unwrap();
+        assert_eq!(chip.cpu().get_register(0), 0x2a);
+        assert_eq!(chip.cpu().get_index(), 0x300);
+        assert_eq!(chip.cpu().get_pc(), 0x208);
+        assert_eq!(chip.cpu().get_stack(), [0x204]);
+        assert_eq!(chip.cpu().timers().get_timer(), 5);
+        assert_eq!(chip.quirks().wait_release, Quirks::default().wait_release);
+        assert_eq!(chip.keys().mask(), 0x0010);
+        assert_eq!(chip.keys().events(), (0, 0));
+        assert!(!chip.audio().is_pattern());
+        assert!(!chip.is_waiting());
+        assert_eq!(chip.seed(), seed);
+
+        // saving writes the current version, which loads back the same
+        let data = chip.save_state();
+        assert_eq!(u16::from_le_bytes([data[4], data[5]]), VERSION);
+        chip.load_state(&data).unwrap();
+        assert_eq!(chip.save_state(), data);
+    }
+}
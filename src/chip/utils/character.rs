@@ -9,7 +9,7 @@ impl Character{
 
     pub fn is_alphabetic(&self)->bool{
         let value = self.value as u32;
-        return (value >= 65 && value <= 91) || (value >= 97 && value <= 123) || self.value == '_';
+        return (value >= 65 && value <= 90) || (value >= 97 && value <= 122) || self.value == '_';
     }
     
     pub fn is_numeric(&self)->bool{
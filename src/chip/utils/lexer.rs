@@ -7,17 +7,25 @@ pub enum Token{
 	ForwardSlash, OpenSquareBracket, ClosingSquareBracket, Term(char), Factor(char),
 	OpenCurlyBracket, ClosingCurlyBracket, OpenBracket, ClosingBracket, Colon, SemiColon, Coma, Equal, Dot, None}
 
-pub struct Lexer{ index:usize, current:Character, data: String, to_newline: bool }
+#[derive(Clone)]
+pub struct Lexer{ index:usize, current:Character, data: Vec<char>, to_newline: bool }
 impl Lexer{
     pub fn new(data:&str)->Self{
-        let string = String::from(data);
-        let init = Character::new(string.chars().nth(0).unwrap());
-        Lexer{ index:0, current:init, data: string, to_newline: false }
+        // indexed as chars, walking the string for every character made long sources crawl
+        let data: Vec<char> = data.chars().collect();
+        // an empty source has no first character, has_next then finds nothing to read
+        let init = Character::new(data.first().copied().unwrap_or('\n'));
+        Lexer{ index:0, current:init, data, to_newline: false }
+    }
+
+    // the line the lexer is on, counted from 1, for error messages
+    pub fn line(&self)->usize{
+        return 1 + self.data[..self.index.min(self.data.len())].iter().filter(| ch | **ch == '\n').count();
     }
     
     pub fn has_next(&mut self)->bool{
         while self.index < self.data.len(){
-            self.current = Character::new(self.data.chars().nth(self.index).unwrap());
+            self.current = Character::new(self.data[self.index]);
 			if self.to_newline && self.current.unwrap() == '\n'{
 				self.to_newline = false;
 			}else if !self.to_newline && !self.current.is_whitespace(){
@@ -29,7 +37,7 @@ impl Lexer{
     }
     
     fn pop(&mut self)->char{
-        let init = self.data.chars().nth(self.index).unwrap();
+        let init = self.data[self.index];
         self.index += 1;
         return init;
     }
@@ -55,10 +63,13 @@ impl Lexer{
 			'/' => {
 				let init = self.pop();
 				if self.index < self.data.len(){
-					let next = self.data.chars().nth(self.index).unwrap();
+					let next = self.data[self.index];
 					if next == '/'{
+						// a comment closing the source leaves nothing to read
 						self.to_newline = true;
-						self.has_next();
+						if !self.has_next(){
+							return Ok(Token::None);
+						}
 						return self.get_next_token();
 					}
 				}
@@ -70,14 +81,14 @@ impl Lexer{
 			'=' => { self.pop(); return Ok(Token::Equal); },
 			'.' => { self.pop(); return Ok(Token::Dot); },
 			'"' => return self.get_string_token(),
-			__  => return Result::Err(format!("unexpected token {} encountered", self.pop()))
+			_  => return Result::Err(format!("unexpected token {} encountered", self.pop()))
 		}
 	}
 
 	fn get_name_token(&mut self)->Result<Token, String>{
 		let mut builder = String::new();
 		while self.index < self.data.len(){
-            self.current = Character::new(self.data.chars().nth(self.index).unwrap());
+            self.current = Character::new(self.data[self.index]);
             let passable = !self.current.is_alphanumeric();
             if passable { break; }else { builder.push(self.current.unwrap()); }
             self.index += 1;
@@ -92,7 +103,7 @@ impl Lexer{
 	fn get_number_token(&mut self)->Result<Token, String>{
 		let mut builder = String::new();
 		while self.index < self.data.len(){
-            self.current = Character::new(self.data.chars().nth(self.index).unwrap());
+            self.current = Character::new(self.data[self.index]);
             let important = !self.current.is_whitespace() && self.current.is_hexdigit();
             if important { builder.push(self.current.unwrap()); } else { break; };
             self.index += 1;
@@ -104,7 +115,7 @@ impl Lexer{
 		let open = self.pop();
 		let mut builder = String::new();
 		while self.index < self.data.len(){
-		    let close = self.data.chars().nth(self.index).unwrap();
+		    let close = self.data[self.index];
 			if close == open{
                 self.pop();
                 return Ok(Token::String( builder));
@@ -49,29 +49,32 @@ pub fn hex(value: u16)->String{
             "13" => "D",
             "14" => "E",
             "15" => "F",
-            __ => ch.as_ref()
+            _ => ch.as_ref()
         });
         init /= 16;
     }
     return if builder.is_empty() { String::from("0") } else { builder };
 }
 
-pub fn from_hex(numb: &str)->u16{
-    if numb.contains("0x") || numb.contains("0b"){
-        let mut number: u16 = 0;
-        for i in 2..numb.len(){
-            let mut value = numb.chars().nth(i).unwrap() as u16;
-            if value >= 65 && value <= 70{
-                value -= 55;
-            }else if value >= 97 && value <= 102{
-                value -= 87;
-            }else if value >= 48 && value <= 57{
-                value -= 48;
-            }
-            number += value * u32::pow( if numb.contains("0x") { 16 } else { 2 }, (numb.len() - i - 1 ) as u32) as u16;
-        }
-        return number;   
+// reads a number written in hex with 0x, in binary with 0b, or else in decimal
+pub fn from_hex(numb: &str)->Result<u16, String>{
+    let (digits, radix) = match (numb.strip_prefix("0x"), numb.strip_prefix("0b")){
+        (Some(digits), _) => (digits, 16),
+        (_, Some(digits)) => (digits, 2),
+        _ => (numb, 10)
+    };
+    if digits.is_empty() || !digits.chars().all(| ch | ch.is_digit(radix)){
+        return Err(format!("{} is not a number", numb));
     }
-    return numb.parse().unwrap(); 
+    return u16::from_str_radix(digits, radix).map_err(|_| format!("{} does not fit in 16 bits", numb));
 }
 
+// reads a colour written as rrggbb in hex, with an optional leading #
+pub fn parse_color(text: &str)->Option<[u8; 3]>{
+    let text = text.trim().trim_start_matches('#');
+    if text.len() != 6 || !text.chars().all(| ch | ch.is_ascii_hexdigit()){
+        return None;
+    }
+    let value = u32::from_str_radix(text, 16).ok()?;
+    return Some([(value >> 16) as u8, (value >> 8) as u8, value as u8]);
+}
@@ -0,0 +1,275 @@
+use crate::chip::{ Chip, Palette, Quirks };
+use crate::chip::palette;
+use crate::chip::keys::KeyMap;
+use crate::chip::renderer::{ GlRenderer, Disposable, Preset, Renderer };
+use crate::chip::scheduler::{ Scheduler, SystemClock };
+use crate::chip::state;
+use crate::chip::rewind::Rewind;
+use crate::chip::movie::Movie;
+use crate::chip::debugger::Debugger;
+use crate::chip::gui::Gui;
+
+use glutin::ContextBuilder;
+use glutin::window::{ Fullscreen, WindowBuilder };
+use glutin::event::{Event, WindowEvent, ElementState, VirtualKeyCode};
+use glutin::event_loop::{ ControlFlow, EventLoop};
+
+const FAST_FORWARD: f64 = 4.0;
+const SLOW_MOTION: f64 = 0.25;
+const SLOTS: u8 = 10;
+
+// how the window runs a rom, the command line fills these in
+#[derive(Debug, Clone, PartialEq)]
+pub struct Settings{
+    pub quirks: Quirks,
+    pub instructions_per_frame: usize,
+    pub speed: f64,
+    pub scale: u32,                 // window pixels per lores pixel
+    pub integer_scale: bool,        // grow the screen in whole pixels only, leaving wider bars around it
+    pub palette: Option<Palette>,   // over the rom's own palette and the default
+    pub shader: String,             // a built-in shader preset or a preset file
+    pub keymap: KeyMap              // the host keys of the keypad, by scancode
+}
+
+impl Default for Settings{
+    fn default()->Self{
+        Settings{ quirks: Quirks::default(), instructions_per_frame: 10, speed: 1.0, scale: 10, integer_scale: true, palette: None, shader: String::from("none"), keymap: KeyMap::new() }
+    }
+}
+
+pub fn start(file: &str, settings: Settings){
+    let event_loop = EventLoop::new();
+    let size = glutin::dpi::LogicalSize::new(64 * settings.scale.max(1), 32 * settings.scale.max(1));
+    let window = WindowBuilder::new().with_title("Chip-8 Emulator").with_inner_size(size);
+    let context = unsafe {
+        let context = ContextBuilder::new().build_windowed(window, &event_loop).unwrap();
+        context.make_current().unwrap()
+    };
+
+    gl::load_with(| symbol | context.get_proc_address(symbol) as *const _);
+
+    let mut chip = Chip::with_quirks(settings.quirks);
+    let mut scheduler = Scheduler::new(Box::new(SystemClock::new()));
+    scheduler.set_instructions_per_frame(settings.instructions_per_frame);
+    scheduler.set_speed(settings.speed);
+    scheduler.set_rewind(Some(Rewind::new()));
+    scheduler.set_debugger(Some(Debugger::new()));
+
+    // without the host-audio feature the emulator runs silent
+    #[cfg(feature = "host-audio")]
+    match crate::chip::audio::DeviceSink::open(){
+        Ok(sink) =>{
+            chip.audio_mut().set_sample_rate(sink.get_sample_rate());
+            scheduler.set_sink(Some(Box::new(sink)));
+        },
+        Err(error) => println!("{}", error)
+    }
+    let mut slow_motion = false;
+    let rom = file.to_string();
+    let mut slot: u8 = 0;
+    let keymap = settings.keymap;
+    let mut renderer: Box<dyn Renderer> = Box::new(unsafe{ GlRenderer::new() });
+    let mut gui = unsafe{ Gui::new(&event_loop) };
+    let size = context.window().inner_size();
+    renderer.resize(size.width, size.height);
+    renderer.set_integer_scale(settings.integer_scale);
+
+    // the palette given wins over the one kept for the rom. F8 cycles through the presets and F4 keeps the current one for the rom
+    let rom_palette = match Palette::for_rom(file){
+        Some(Ok(palette)) => Some(palette),
+        Some(Err(error)) =>{
+            println!("{}", error);
+            None
+        },
+        None => None
+    };
+    let mut palette = settings.palette.or(rom_palette).unwrap_or_default();
+    renderer.set_palette(palette);
+
+    // F10 cycles through the built-in presets, and the one given when it was read from a file
+    let mut presets: Vec<Preset> = Preset::names().iter().filter_map(| name | Preset::builtin(name)).collect();
+    let mut preset = 0;
+    match Preset::find(&settings.shader){
+        Ok(found) =>{
+            preset = presets.iter().position(| builtin | *builtin == found).unwrap_or_else(||{
+                presets.push(found);
+                presets.len() - 1
+            });
+            if let Err(error) = renderer.set_preset(&presets[preset]){
+                println!("{}", error);
+            }
+        },
+        Err(error) => println!("{}", error)
+    }
+    if let Err(error) = chip.load(file){
+        println!("{}", error);
+    }
+
+    let mut focus_paused = false;
+
+    event_loop.run(move | event, _, control_flow| {
+        *control_flow = ControlFlow::Poll;
+        match event {
+            Event::LoopDestroyed => return,
+            Event::WindowEvent{ event, ..} =>{
+                gui.on_event(&event);
+                match event{
+                    WindowEvent::CloseRequested => {
+                        renderer.dispose();
+                        gui.dispose();
+                        *control_flow = ControlFlow::Exit
+                    },
+                    // losing the focus pauses the game until the window gets it back, unless it was paused already
+                    WindowEvent::Focused(focused) =>{
+                        if !focused && !chip.is_paused(){
+                            chip.pause();
+                            focus_paused = true;
+                        }else if focused && focus_paused{
+                            chip.resume();
+                            focus_paused = false;
+                        }
+                    },
+                    // sizes are physical pixels, which the renderer draws in
+                    WindowEvent::Resized(size) =>{
+                        context.resize(size);
+                        renderer.resize(size.width, size.height);
+                    },
+                    WindowEvent::ScaleFactorChanged{ new_inner_size, .. } =>{
+                        context.resize(*new_inner_size);
+                        renderer.resize(new_inner_size.width, new_inner_size.height);
+                    },
+                    WindowEvent::KeyboardInput { input, .. } =>{
+                        if gui.wants_keyboard(){
+                            return;
+                        }
+                        // keys mapped to the keypad take precedence over the hotkeys
+                        let pressed = input.state == ElementState::Pressed;
+                        if let Some(key) = keymap.get(input.scancode){
+                            if pressed { chip.press_key(key); } else { chip.release_key(key); }
+                            return;
+                        }
+
+                        // P pauses, Tab fast-forwards while held and ` toggles slow motion.
+                        // F5 saves to the current slot, F9 loads it back and F6/F7 pick the slot.
+                        // Backspace runs the game backwards while held.
+                        // F2 starts recording a movie from a fresh start and saves it when pressed again, F3 plays it back.
+                        // F1 shows the debugger panels, F10 switches the shader preset and F11 toggles fullscreen.
+                        // F8 switches the palette and F4 keeps it for the rom.
+                        match input.virtual_keycode{
+                            Some(VirtualKeyCode::F1) if pressed => gui.toggle(),
+                            Some(VirtualKeyCode::F8) if pressed =>{
+                                let names = Palette::names();
+                                // a custom palette goes back to the first preset
+                                let next = palette.name().and_then(| name | names.iter().position(| preset | *preset == name)).map_or(0, | index | (index + 1) % names.len());
+                                palette = Palette::preset(names[next]).unwrap_or_default();
+                                renderer.set_palette(palette);
+                                println!("palette {}", palette);
+                            },
+                            Some(VirtualKeyCode::F4) if pressed =>{
+                                let path = palette::rom_path(&rom);
+                                match palette.save_file(&path){
+                                    Ok(_) => println!("palette {} kept in {}", palette, path),
+                                    Err(error) => println!("{}", error)
+                                }
+                            },
+                            Some(VirtualKeyCode::F10) if pressed =>{
+                                preset = (preset + 1) % presets.len();
+                                match renderer.set_preset(&presets[preset]){
+                                    Ok(_) => println!("shader {}", presets[preset].name()),
+                                    Err(error) => println!("{}", error)
+                                }
+                            },
+                            Some(VirtualKeyCode::F11) if pressed =>{
+                                let window = context.window();
+                                window.set_fullscreen(if window.fullscreen().is_some() { None } else { Some(Fullscreen::Borderless(None)) });
+                            },
+                            Some(VirtualKeyCode::P) if pressed =>{
+                                if chip.is_paused() { chip.resume(); } else { chip.pause(); }
+                                println!("{:?}", chip.state());
+                            },
+                            Some(VirtualKeyCode::Tab) =>{
+                                scheduler.set_speed(settings.speed * if pressed { FAST_FORWARD } else if slow_motion { SLOW_MOTION } else { 1.0 });
+                            },
+                            Some(VirtualKeyCode::Grave) if pressed =>{
+                                slow_motion = !slow_motion;
+                                scheduler.set_speed(settings.speed * if slow_motion { SLOW_MOTION } else { 1.0 });
+                            },
+                            Some(VirtualKeyCode::Back) =>{
+                                scheduler.set_rewinding(pressed);
+                            },
+                            Some(VirtualKeyCode::F2) if pressed =>{
+                                let movie = format!("{}.movie", rom);
+                                match scheduler.take_movie(){
+                                    Some(recording) if !recording.is_playing() =>{
+                                        match recording.save_file(&movie){
+                                            Ok(_) => println!("saved {} frames to {}", recording.frames().len(), movie),
+                                            Err(error) => println!("{}", error)
+                                        }
+                                    },
+                                    _ =>{
+                                        chip.reset();
+                                        if let Err(error) = chip.load(&rom){
+                                            println!("{}", error);
+                                        }
+                                        scheduler.set_movie(Some(Movie::record(&mut chip, scheduler.get_instructions_per_frame())));
+                                        println!("recording");
+                                    }
+                                }
+                            },
+                            Some(VirtualKeyCode::F3) if pressed =>{
+                                let result = Movie::load_file(&format!("{}.movie", rom)).and_then(| mut movie |{
+                                    chip.reset();
+                                    chip.load(&rom)?;
+                                    movie.play(&mut chip)?;
+                                    Ok(movie)
+                                });
+                                match result{
+                                    Ok(movie) =>{
+                                        println!("playing {} frames", movie.frames().len());
+                                        scheduler.set_movie(Some(movie));
+                                    },
+                                    Err(error) => println!("{}", error)
+                                }
+                            },
+                            Some(VirtualKeyCode::F5) if pressed =>{
+                                match state::save_slot(&chip, &rom, slot){
+                                    Ok(_) => println!("saved slot {}", slot),
+                                    Err(error) => println!("{}", error)
+                                }
+                            },
+                            Some(VirtualKeyCode::F9) if pressed =>{
+                                match state::load_slot(&mut chip, &rom, slot){
+                                    Ok(_) => println!("loaded slot {}", slot),
+                                    Err(error) => println!("{}", error)
+                                }
+                            },
+                            Some(VirtualKeyCode::F6) if pressed =>{
+                                slot = (slot + SLOTS - 1) % SLOTS;
+                                println!("slot {}", slot);
+                            },
+                            Some(VirtualKeyCode::F7) if pressed =>{
+                                slot = (slot + 1) % SLOTS;
+                                println!("slot {}", slot);
+                            },
+                            _ => {}
+                        }
+                    },
+                    _ => {}
+                }
+            },
+            Event::MainEventsCleared =>{
+                // a fault is reported once, the chip then stays faulted and the scheduler runs no more frames
+                if chip.is_loaded(){
+                    if let Err(error) = scheduler.update(&mut chip){
+                        println!("{}", error);
+                    }
+                }
+
+                renderer.render(chip.screen());
+                unsafe{ gui.draw(context.window(), &mut chip, &mut scheduler); }
+                context.swap_buffers().unwrap();
+            },
+            _ =>{}
+        }
+    });
+}
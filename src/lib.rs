@@ -0,0 +1,4 @@
+#![allow(clippy::needless_return, clippy::upper_case_acronyms, clippy::manual_range_contains)]
+
+pub mod chip;
+pub use chip::{ Chip, ChipError, ChipState, Quirks, Sink, Settings, start };
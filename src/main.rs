@@ -1,5 +1,426 @@
-mod chip;
+#![allow(clippy::needless_return)]
 
-fn main() {
-    chip::start("scripts/test.asm");
-}
\ No newline at end of file
+use std::fmt::Write as _;
+use std::fs;
+use std::io::Write as _;
+use std::process::exit;
+use std::time::Instant;
+
+use chip_8::{ Chip, ChipError, Quirks, Settings };
+use chip_8::chip::{ Assemblier, Compiler, Disassembler, Headless, Timeline, KeyMap, Palette, Preset, Renderer, SoftwareRenderer, headless };
+use chip_8::chip::quirks::IndexIncrement;
+use chip_8::chip::state::rom_hash;
+
+// exit codes: everything went fine, the command failed, or it was called wrong
+const OK: i32 = 0;
+const FAILURE: i32 = 1;
+const USAGE: i32 = 2;
+
+const USAGE_TEXT: &str = "usage: chip-8 <command> [options]
+
+commands:
+    run <rom>                       play a rom or an .asm source in a window
+        --quirks <preset>           vip, chip-48, schip-1.0, schip-1.1, schip or xo-chip
+        --quirk <name>=<on|off>     shift-vy, jump-vx, clip, vf-reset, add-i-overflow, display-wait, wait-release
+                                    and index-increment=<x|x+1|none>, how far fx55/fx65 move i
+        --ipf <count>               instructions per frame (10)
+        --speed <factor>            speed of the emulation (1.0)
+        --scale <pixels>            window pixels per chip-8 pixel (10)
+        --fit                       fill the window instead of scaling in whole pixels
+        --palette <palette>         blue, green, amber, lcd, high-contrast, colour-blind, octo, or colours
+                                    as rrggbb: <bg>,<fg> or the four of the xo-chip planes, like 001100,33ff33
+                                    F8 switches while running and F4 keeps the palette for the rom
+        --shader <preset>           none, scanlines, grid, bloom, crt or a preset file, F10 switches while running
+        --keymap <file>             keypad keys by scancode, lines of <key> = <scancode> in hex
+    asm <source> [-o <rom>]         assemble a source, to <source>.ch8 by default
+    disasm <rom> [-o <source>]      disassemble a rom, to the standard output by default
+    compile <script> [-o <rom>]     compile a script, to <script>.ch8 by default
+    info <rom>                      size, checksum, labels and platform of a rom
+    bench <rom> [--frames N] [--ipf N]
+                                    run without a window and report instructions per second
+    headless <rom>                  run without a window and report the state it ends in
+        --frames <count>            frames to run (60)
+        --ipf <count>               instructions per frame (10)
+        --quirks, --quirk           as for run
+        --seed <number>             seed of the random numbers (0)
+        --keys <timeline>           file of the keys held from each frame on, lines of <frame> <keys|->
+        --dump                      print the screen
+        --pbm <file>, --png <file>  save the screen as an image, the png in the colours of --palette
+        --render <file>             save the screen as the window draws it, with --scale, --fit and --palette
+        --hash                      print only the hash of the screen
+    help                            show this text";
+
+// a command line mistake is reported with the usage text, anything else on its own
+enum Failure{ Usage(String), Error(String) }
+
+fn usage(error: String)->Failure{ Failure::Usage(error) }
+
+fn main(){
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let code = match command(&args){
+        Ok(code) => code,
+        Err(Failure::Error(error)) =>{
+            eprintln!("error: {}", error);
+            FAILURE
+        },
+        Err(Failure::Usage(error)) =>{
+            eprintln!("error: {}\n\n{}", error, USAGE_TEXT);
+            USAGE
+        }
+    };
+    exit(code);
+}
+
+fn command(args: &[String])->Result<i32, Failure>{
+    let name = match args.first(){
+        Some(name) => name.as_str(),
+        None => return Err(usage(String::from("no command given")))
+    };
+    let rest = &args[1..];
+    return match name{
+        "run" => run(&Options::parse(rest)?),
+        "asm" => asm(&Options::parse(rest)?),
+        "disasm" => disasm(&Options::parse(rest)?),
+        "compile" => compile(&Options::parse(rest)?),
+        "info" => info(&Options::parse(rest)?),
+        "bench" => bench(&Options::parse(rest)?),
+        "headless" => headless(&Options::parse(rest)?),
+        "help" | "-h" | "--help" =>{
+            println!("{}", USAGE_TEXT);
+            Ok(OK)
+        },
+        // a bare file is played, as dropping a rom on the program does
+        _ if !name.starts_with('-') && fs::metadata(name).is_ok() => run(&Options::parse(args)?),
+        _ => Err(usage(format!("unknown command {}", name)))
+    };
+}
+
+// the file a command works on and the flags after it, each flag given once with a value or alone
+struct Options{ file: Option<String>, flags: Vec<(String, Option<String>)> }
+
+impl Options{
+    fn parse(args: &[String])->Result<Self, Failure>{
+        let mut options = Options{ file: None, flags: Vec::new() };
+        let mut index = 0;
+        while index < args.len(){
+            let arg = &args[index];
+            if arg.starts_with('-') && arg.len() > 1{
+                let value = if takes_value(arg) {
+                    index += 1;
+                    match args.get(index){
+                        Some(value) => Some(value.clone()),
+                        None => return Err(usage(format!("{} needs a value", arg)))
+                    }
+                }else{
+                    None
+                };
+                options.flags.push((arg.clone(), value));
+            }else if options.file.is_none(){
+                options.file = Some(arg.clone());
+            }else{
+                return Err(usage(format!("unexpected argument {}", arg)));
+            }
+            index += 1;
+        }
+        return Ok(options);
+    }
+
+    fn file(&self)->Result<&str, Failure>{
+        return self.file.as_deref().ok_or_else(|| usage(String::from("no file given")));
+    }
+
+    fn has(&self, flag: &str)->bool{ self.flags.iter().any(| (name, _) | name == flag) }
+
+    fn value(&self, flag: &str)->Option<&str>{
+        return self.flags.iter().rev().find(| (name, _) | name == flag).and_then(| (_, value) | value.as_deref());
+    }
+
+    fn values<'a>(&'a self, flag: &'a str)->impl Iterator<Item = &'a str>{
+        return self.flags.iter().filter(move | (name, _) | name == flag).filter_map(| (_, value) | value.as_deref());
+    }
+
+    fn number<T: std::str::FromStr>(&self, flag: &str, default: T)->Result<T, Failure>{
+        return match self.value(flag){
+            Some(value) => value.parse().map_err(|_| usage(format!("{} is not a valid value for {}", value, flag))),
+            None => Ok(default)
+        };
+    }
+
+    // only the flags a command knows are accepted, a typo should not be silently ignored
+    fn allow(&self, known: &[&str])->Result<(), Failure>{
+        match self.flags.iter().find(| (name, _) | !known.contains(&name.as_str())){
+            Some((name, _)) => Err(usage(format!("unknown option {}", name))),
+            None => Ok(())
+        }
+    }
+
+    fn quirks(&self)->Result<Quirks, Failure>{
+        let mut quirks = match self.value("--quirks"){
+            Some(name) => Quirks::preset(name).ok_or_else(|| usage(format!("unknown quirks preset {}", name)))?,
+            None => Quirks::default()
+        };
+        for quirk in self.values("--quirk"){
+            set_quirk(&mut quirks, quirk)?;
+        }
+        return Ok(quirks);
+    }
+
+    fn settings(&self)->Result<Settings, Failure>{
+        let default = Settings::default();
+        let mut settings = Settings{
+            quirks: self.quirks()?,
+            instructions_per_frame: self.number("--ipf", default.instructions_per_frame)?,
+            speed: self.number("--speed", default.speed)?,
+            scale: self.number("--scale", default.scale)?,
+            integer_scale: !self.has("--fit"),
+            shader: self.value("--shader").unwrap_or(&default.shader).to_string(),
+            ..default
+        };
+        if settings.speed <= 0.0 || settings.scale == 0{
+            return Err(usage(String::from("speed and scale must be above zero")));
+        }
+        if let Some(palette) = self.value("--palette"){
+            settings.palette = Some(Palette::parse(palette).ok_or_else(|| usage(format!("{} is not a palette, expected a preset or <bg>,<fg> colours as rrggbb", palette)))?);
+        }
+        if let Some(keymap) = self.value("--keymap"){
+            settings.keymap = KeyMap::load_file(keymap).map_err(| error | Failure::Error(format!("cannot load key map {}: {}", keymap, error)))?;
+        }
+        return Ok(settings);
+    }
+}
+
+fn takes_value(flag: &str)->bool{
+    return matches!(flag, "-o" | "--quirks" | "--quirk" | "--ipf" | "--speed" | "--scale" | "--palette" | "--frames" | "--seed" | "--keys" | "--pbm" | "--png" | "--render" | "--shader" | "--keymap");
+}
+
+fn set_quirk(quirks: &mut Quirks, text: &str)->Result<(), Failure>{
+    let (name, value) = text.split_once('=').ok_or_else(|| usage(format!("{} is not <name>=<on|off>", text)))?;
+    // the one quirk with three settings
+    if name == "index-increment"{
+        quirks.load_store = match value{
+            "x" => IndexIncrement::X,
+            "x+1" => IndexIncrement::XPlusOne,
+            "none" => IndexIncrement::Unchanged,
+            _ => return Err(usage(format!("{} is not x, x+1 or none", value)))
+        };
+        return Ok(());
+    }
+    let value = match value{
+        "on" | "true" | "1" => true,
+        "off" | "false" | "0" => false,
+        _ => return Err(usage(format!("{} is neither on nor off", value)))
+    };
+    match name{
+        "shift-vy" => quirks.shift_vy = value,
+        "jump-vx" => quirks.jump_vx = value,
+        "clip" => quirks.clip_sprites = value,
+        "vf-reset" => quirks.vf_reset = value,
+        "add-i-overflow" => quirks.add_i_overflow = value,
+        "display-wait" => quirks.display_wait = value,
+        "wait-release" => quirks.wait_release = value,
+        _ => return Err(usage(format!("unknown quirk {}", name)))
+    }
+    return Ok(());
+}
+
+fn run(options: &Options)->Result<i32, Failure>{
+    options.allow(&["--quirks", "--quirk", "--ipf", "--speed", "--scale", "--fit", "--palette", "--shader", "--keymap"])?;
+    let file = options.file()?;
+    let settings = options.settings()?;
+    // a broken preset is caught before the window opens
+    Preset::find(&settings.shader).map_err(| error | Failure::Error(format!("cannot load shader {}: {}", settings.shader, error)))?;
+    // the window reports a bad rom only once it is open, so it is checked here first
+    load(file, settings.quirks)?;
+    chip_8::start(file, settings);
+    return Ok(OK);
+}
+
+fn asm(options: &Options)->Result<i32, Failure>{
+    options.allow(&["-o"])?;
+    let file = options.file()?;
+    let output = options.value("-o").map(String::from).unwrap_or_else(|| format!("{}.ch8", file.trim_end_matches(".asm")));
+    let source = text(file)?;
+    let mut assembler = Assemblier::new();
+    let rom = assembler.init(&source).and_then(|_| assembler.run()).map_err(| error | Failure::Error(format!("{}: {}", file, error)))?;
+    write(&output, &rom)?;
+    println!("{} bytes written to {}", rom.len(), output);
+    return Ok(OK);
+}
+
+fn disasm(options: &Options)->Result<i32, Failure>{
+    options.allow(&["-o"])?;
+    let file = options.file()?;
+    let mut disassembler = Disassembler::new();
+    disassembler.init(&read(file)?);
+    let source = disassembler.run();
+    match options.value("-o"){
+        Some(output) => write(output, source.as_bytes())?,
+        None => print(&source)
+    }
+    return Ok(OK);
+}
+
+fn compile(options: &Options)->Result<i32, Failure>{
+    options.allow(&["-o"])?;
+    let file = options.file()?;
+    let output = options.value("-o").map(String::from).unwrap_or_else(|| format!("{}.ch8", file.trim_end_matches(".bs")));
+    let rom = match Compiler::new().compile(&text(file)?){
+        Ok(rom) => rom,
+        Err(errors) =>{
+            for error in errors.iter(){
+                eprintln!("{}: {}", file, error);
+            }
+            return Err(Failure::Error(format!("{} has {} errors", file, errors.len())));
+        }
+    };
+    write(&output, &rom)?;
+    println!("{} bytes written to {}", rom.len(), output);
+    return Ok(OK);
+}
+
+fn info(options: &Options)->Result<i32, Failure>{
+    options.allow(&[])?;
+    let file = options.file()?;
+    let rom = read(file)?;
+    let mut disassembler = Disassembler::new();
+    disassembler.init(&rom);
+    let code = disassembler.code_size();
+    let labels = disassembler.labels();
+    let mut text = String::new();
+    let _ = writeln!(text, "file:      {}", file);
+    let _ = writeln!(text, "size:      {} bytes", rom.len());
+    let _ = writeln!(text, "crc32:     {:08X}", rom_hash(&rom));
+    let _ = writeln!(text, "code:      {} bytes", code);
+    let _ = writeln!(text, "data:      {} bytes", rom.len() - code);
+    let _ = writeln!(text, "platform:  {}", platform(&disassembler));
+    if rom.len() > 0x1000 - 0x200{
+        let _ = writeln!(text, "           too large for 4 KiB, only fits the 64 KiB of xo-chip");
+    }
+    let _ = writeln!(text, "labels:    {}", labels.len());
+    for (name, address) in labels{
+        let _ = writeln!(text, "    {:03X}  {}", address, name);
+    }
+    print(&text);
+    return Ok(OK);
+}
+
+// the platform a rom was written for, told from the instructions it runs
+fn platform(disassembler: &Disassembler)->&'static str{
+    let instructions = disassembler.instructions();
+    let xo = instructions.iter().any(| (_, code) |{
+        *code == 0xf000 || *code == 0xf002 || matches!(code & 0xf0ff, 0xf001 | 0xf03a) || matches!(code & 0xf00f, 0x5002 | 0x5003) || code & 0xfff0 == 0x00d0
+    });
+    let schip = instructions.iter().any(| (_, code) |{
+        matches!(code, 0x00fb..=0x00ff) || code & 0xfff0 == 0x00c0 || matches!(code & 0xf0ff, 0xf030 | 0xf075 | 0xf085)
+    });
+    return if xo { "xo-chip" } else if schip { "super-chip" } else { "chip-8" };
+}
+
+fn bench(options: &Options)->Result<i32, Failure>{
+    options.allow(&["--frames", "--ipf", "--quirks", "--quirk"])?;
+    let file = options.file()?;
+    let frames: usize = options.number("--frames", 600)?;
+    let per_frame: usize = options.number("--ipf", 1000)?;
+    let mut chip = load(file, options.quirks()?)?;
+    let start = Instant::now();
+    for _ in 0..frames{
+        if !chip.is_running(){
+            break;
+        }
+        chip.frame(per_frame).map_err(| error | fault(&chip, &error))?;
+    }
+    let seconds = start.elapsed().as_secs_f64();
+    let count = chip.instructions();
+    println!("{} instructions in {:.3} s", count, seconds);
+    println!("{:.0} instructions per second", count as f64 / seconds.max(f64::EPSILON));
+    return Ok(OK);
+}
+
+fn headless(options: &Options)->Result<i32, Failure>{
+    options.allow(&["--frames", "--ipf", "--quirks", "--quirk", "--seed", "--keys", "--dump", "--pbm", "--png", "--render", "--scale", "--fit", "--palette", "--hash"])?;
+    let file = options.file()?;
+    let frames: u64 = options.number("--frames", 60)?;
+    let mut runner = Headless::new(options.number("--ipf", Settings::default().instructions_per_frame)?);
+    runner.set_seed(options.number("--seed", 0)?);
+    if let Some(keys) = options.value("--keys"){
+        runner.set_timeline(Timeline::load_file(keys).map_err(| error | Failure::Error(format!("cannot load {}: {}", keys, error)))?);
+    }
+    let mut chip = load(file, options.quirks()?)?;
+    let report = runner.run(&mut chip, frames);
+
+    // the images are in the colours the window would use
+    let settings = options.settings()?;
+    let palette = match settings.palette{
+        Some(palette) => palette,
+        None => Palette::for_rom(file).transpose().map_err(| error | Failure::Error(error.to_string()))?.unwrap_or_default()
+    };
+    if let Some(output) = options.value("--pbm"){
+        write(output, &headless::pbm(chip.screen()))?;
+    }
+    if let Some(output) = options.value("--png"){
+        write(output, &headless::png(chip.screen(), &palette))?;
+    }
+    // drawn the way the window would, at its default size unless scaled
+    if let Some(output) = options.value("--render"){
+        let mut renderer = SoftwareRenderer::new(64 * settings.scale, 32 * settings.scale);
+        renderer.set_integer_scale(settings.integer_scale);
+        renderer.set_palette(palette);
+        renderer.render(chip.screen());
+        write(output, &headless::rgba_png(renderer.width(), renderer.height(), renderer.pixels()))?;
+    }
+    // only the hash is printed for scripts comparing it against a golden one
+    if options.has("--hash"){
+        print(&format!("{:08X}\n", report.screen_hash));
+    }else{
+        if options.has("--dump"){
+            dump(&chip);
+        }
+        print(&format!("{}\n", report));
+    }
+    return match report.fault(){
+        Some(error) => Err(fault(&chip, error)),
+        None => Ok(OK)
+    };
+}
+
+fn read(file: &str)->Result<Vec<u8>, Failure>{
+    return fs::read(file).map_err(| error | Failure::Error(format!("cannot read {}: {}", file, error)));
+}
+
+fn text(file: &str)->Result<String, Failure>{
+    return String::from_utf8(read(file)?).map_err(|_| Failure::Error(format!("{} is not text", file)));
+}
+
+fn write(file: &str, data: &[u8])->Result<(), Failure>{
+    return fs::write(file, data).map_err(| error | Failure::Error(format!("cannot write {}: {}", file, error)));
+}
+
+// a chip with the rom loaded, assembling it first when it is a source
+fn load(file: &str, quirks: Quirks)->Result<Chip, Failure>{
+    let mut chip = Chip::with_quirks(quirks);
+    chip.load(file).map_err(| error | Failure::Error(format!("cannot load {}: {}", file, error)))?;
+    if chip.rom_size() == 0{
+        return Err(Failure::Error(format!("{} holds no program", file)));
+    }
+    return Ok(chip);
+}
+
+fn fault(chip: &Chip, error: &ChipError)->Failure{
+    return Failure::Error(format!("{} (pc {:03X}, opcode {:04X})", error, chip.cpu().get_pc(), chip.opcode().code()));
+}
+
+// the screen drawn with # and .
+fn dump(chip: &Chip){
+    let screen = chip.screen();
+    let mut text = String::new();
+    for y in 0..screen.height(){
+        let row: String = (0..screen.width()).map(| x | if screen.get(x, y) != 0 { '#' } else { '.' }).collect();
+        let _ = writeln!(text, "{}", row);
+    }
+    print(&text);
+}
+
+// println panics once the reader of a pipe is gone, like head after its lines, where there is nothing left to do
+fn print(text: &str){
+    let _ = std::io::stdout().write_all(text.as_bytes());
+}